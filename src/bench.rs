@@ -16,15 +16,12 @@
  * Crab. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{
-    sync::{mpsc::channel, Mutex},
-    time::{Duration, Instant},
-};
+use std::time::Duration;
 
 use crate::{
     board::Board,
-    engine::{uci::UciOptions, ZobristStack},
-    search::{iterative_deepening, Limits},
+    engine::uci::UciOptions,
+    search::{search_sync, Limits},
     transposition_table::TranspositionTable,
 };
 
@@ -32,14 +29,79 @@ use crate::{
 pub const LIMIT: u64 = 8;
 /// The default limit type.
 pub const LIMIT_TYPE: &str = "depth";
-/// Test positions with an expected depth 4 perft result at the end.
-static TEST_POSITIONS: &str = include_str!("../test_positions.epd");
+/// The default number of threads.
+pub const THREADS: usize = 1;
+/// The positions searched by [`bench()`]: openings, middlegames, endgames
+/// and short mates.
+pub const BENCH_POSITIONS: [&str; 50] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkbnr/pppp1ppp/2n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3",
+    "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2",
+    "rnbqkb1r/pp1p1ppp/4pn2/2p5/2PP4/2N5/PP2PPPP/R1BQKBNR w KQkq - 0 4",
+    "rnbqkb1r/ppp1pppp/5n2/3p4/3P1B2/5N2/PPP1PPPP/RN1QKB1R b KQkq - 3 3",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "rq3rk1/ppp2ppp/1bnpb3/3N2B1/3NP3/7P/PPPQ1PP1/2KR3R w - - 7 14",
+    "r1bq1r1k/1pp1n1pp/1p1p4/4p2Q/4Pp2/1BNP4/PPP2PPP/3R1RK1 w - - 2 14",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "r1bbk1nr/pp3p1p/2n5/1N4p1/2Np1B2/8/PPP2PPP/2KR1B1R w kq - 0 13",
+    "r1bq1rk1/ppp1nppp/4n3/3p3Q/3P4/1BP1B3/PP1N2PP/R4RK1 w - - 1 16",
+    "4r1k1/r1q2ppp/ppp2n2/4P3/5Rb1/1N1BQ3/PPP3PP/R5K1 w - - 1 17",
+    "2rqkb1r/ppp2p2/2npb1p1/1N1Nn2p/2P1PP2/8/PP2B1PP/R1BQK2R b KQ - 0 11",
+    "r1bq1r1k/b1p1npp1/p2p3p/1p6/3PP3/1B2NN2/PP3PPP/R2Q1RK1 w - - 1 16",
+    "3r1rk1/p5pp/bpp1pp2/8/q1PP1P2/b3P3/P2NQRPP/1R2B1K1 b - - 6 22",
+    "r1q2rk1/2p1bppp/2Pp4/p6b/Q1PNp3/4B3/PP1R1PPP/2K4R w - - 2 18",
+    "4k2r/1pb2ppp/1p2p3/1R1p4/3P4/2r1PN2/P4PPP/1R4K1 b - - 3 22",
+    "3q2k1/pb3p1p/4pbp1/2r5/PpN2N2/1P2P2P/5PP1/Q2R2K1 b - - 4 26",
+    "r3k2r/3nnpbp/q2pp1p1/p7/Pp1PPPP1/4BNN1/1P5P/R2Q1RK1 w kq - 0 16",
+    "4rrk1/1p1nq3/p7/2p1P1pp/3P2bp/3Q1Bn1/PPPB4/1K2R1NR w - - 40 21",
+    "3Qb1k1/1r2ppb1/pN1n2q1/Pp1Pp1Pr/4P2p/4BP2/4B1R1/1R5K b - - 11 40",
+    "5rk1/q6p/2p3bR/1pPp1rP1/1P1Pp3/P3B1Q1/1K3P2/R7 w - - 93 90",
+    "1r6/1P4bk/3qr1p1/N6p/3pp2P/6R1/3Q1PP1/1R4K1 w - - 1 42",
+    "4k3/3q1r2/1N2r1b1/3ppN2/2nPP3/1B1R2n1/2R1Q3/3K4 w - - 5 1",
+    "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/3N4 b - - 0 1",
+    "3b4/5kp1/1p1p1p1p/pP1PpP1P/P1P1P3/3KN3/8/8 w - - 0 1",
+    "2K5/p7/7P/5pR1/8/5k2/r7/8 w - - 0 1",
+    "8/6pk/1p6/8/PP3p1p/5P2/4KP1q/3Q4 w - - 0 1",
+    "7k/3p2pp/4q3/8/4Q3/5Kp1/P6b/8 w - - 0 1",
+    "8/2p5/8/2kPKp1p/2p4P/2P5/3P4/8 w - - 0 1",
+    "8/1p3pp1/7p/5P1P/2k3P1/8/2K2P2/8 w - - 0 1",
+    "8/pp2r1k1/2p1p3/3pP2p/1P1P1P1P/P5KR/8/8 w - - 0 1",
+    "8/3p4/p1bk3p/Pp6/1Kp1PpPp/2P2P1P/2P5/5B2 b - - 0 1",
+    "5k2/7R/4P2p/5K2/p1r2P1p/8/8/8 b - - 0 1",
+    "6k1/6p1/P6p/r1N5/5p2/7P/1b3PP1/4R1K1 w - - 0 1",
+    "1r3k2/4q3/2Pp3b/3Bp3/2Q2p2/1p1P2P1/1P2KP2/3N4 w - - 0 1",
+    "6k1/4pp1p/3p2p1/P1pPb3/R7/1r2P1PP/3B1P2/6K1 w - - 0 1",
+    "8/3p3B/5p2/5P2/p7/PP5b/k7/6K1 w - - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+    "8/k7/3p4/p2P1p2/P2P1P2/8/8/K7 w - - 0 1",
+    "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+    "8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1",
+    "8/8/8/5N2/8/p7/8/2NK3k w - - 0 1",
+    "8/3k4/8/8/8/4B3/4KB2/2B5 w - - 0 1",
+    "8/8/8/4k3/8/8/8/4K2Q w - - 0 1",
+    "8/8/8/8/4k3/8/8/R3K3 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+    "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 8",
+];
 /// The default hash size of each benched position.
 pub const TT_SIZE: usize = 32;
 
-/// Runs a benchmark on all the positions in [`TEST_POSITIONS`].
+/// Runs a benchmark on all the positions in [`BENCH_POSITIONS`].
+///
+/// The options are `[hash] [threads] [limit] [limit type]`, in the same order
+/// as `OpenBench`. With a depth or node limit, the total node count is the
+/// same on every run, so it can be used as a signature of the search. A
+/// movetime limit (e.g. `bench 16 1 100 movetime`) makes a quick smoke test
+/// instead, but the node count will differ between runs.
 ///
-/// It treats the first 6 tokens as the FEN string and ignores the rest.
+/// With more than 1 thread, a depth or node limit runs the helper threads
+/// deterministically: they search with tables of their own, so they can't
+/// change what the main thread does, and only the nodes of the main thread
+/// are counted. The signature then stays the same as with 1 thread while
+/// still running the helper threads. A movetime limit lets the threads share
+/// the table as they would in a game.
 pub fn bench<'a, T>(mut options: T)
 where
     T: Iterator<Item = &'a str>,
@@ -48,6 +110,10 @@ where
         .next()
         .and_then(|t| t.parse::<usize>().ok())
         .unwrap_or(TT_SIZE);
+    let threads = options
+        .next()
+        .and_then(|t| t.parse::<usize>().ok())
+        .unwrap_or(THREADS);
     let limit = options
         .next()
         .and_then(|l| l.parse::<u64>().ok())
@@ -67,42 +133,44 @@ where
         "movetime" => limits.set_movetime(Some(Duration::from_millis(limit))),
         _ => return,
     }
-    let mut zobrists = ZobristStack::new();
-    let (_tx, rx) = channel();
-    let rx = Mutex::new(rx);
-    let options = UciOptions::default();
-    let mut tt = TranspositionTable::with_capacity(tt_size);
+    let mut options = UciOptions::new();
+    options.set_hash(tt_size);
+    options.set_threads(threads);
+    options.set_deterministic(limit_type != "movetime");
+    let (total_nodes, total_time) = run_bench(&options, limits);
 
-    let mut fen_str = String::new();
-    let mut total_time = Duration::ZERO;
-    let mut total_nodes = 0;
-
-    for position in TEST_POSITIONS.lines() {
-        let mut tokens = position.split_whitespace();
+    // I can't just do `start.elapsed()` because that includes the boilerplate
+    let total_time = total_time.as_millis();
+    let nps = (total_nodes * 1000) / total_time.max(1) as u64;
+    println!("{total_nodes} nodes {nps} nps {total_time} ms");
+}
 
-        tokens.next_back();
+/// Searches every position in [`BENCH_POSITIONS`] with the given options and
+/// limits, printing the nodes of each, and returns the total nodes and time
+/// taken.
+///
+/// Each position starts with a freshly-cleared transposition table, so unless
+/// the limits depend on time, the total nodes are the same on every run.
+fn run_bench(options: &UciOptions, limits: Limits) -> (u64, Duration) {
+    let mut tt = TranspositionTable::with_capacity(options.hash());
 
-        for token in tokens.take(6) {
-            fen_str.push_str(token);
-            fen_str.push(' ');
-        }
-        println!("Position: {fen_str}");
+    let mut total_time = Duration::ZERO;
+    let mut total_nodes = 0;
 
-        let board = fen_str.parse::<Board>().expect("Malformed test position");
-        fen_str.clear();
+    for (number, fen) in BENCH_POSITIONS.iter().enumerate() {
+        println!("Position {}/{}: {fen}", number + 1, BENCH_POSITIONS.len());
 
-        let start = Instant::now();
-        let report = iterative_deepening(board, start, limits, &rx, &mut zobrists, options, &tt);
+        let board = fen.parse::<Board>().expect("Malformed bench position");
 
         tt.clear();
+        let report = search_sync(board, limits, options, &tt, &[]);
+        println!("Nodes searched: {}", report.nodes);
+
         total_time += report.time;
         total_nodes += report.nodes;
     }
 
-    // I can't just do `start.elapsed()` because that includes the boilerplate
-    let total_time = total_time.as_millis();
-    let nps = (total_nodes * 1000) / total_time.max(1) as u64;
-    println!("{total_nodes} nodes {nps} nps {total_time} ms");
+    (total_nodes, total_time)
 }
 
 #[cfg(test)]
@@ -111,10 +179,14 @@ mod test {
         iter,
         sync::{mpsc::channel, Arc, Mutex},
         thread::{available_parallelism, spawn},
+        time::Duration,
     };
 
-    use super::TEST_POSITIONS;
-    use crate::perft::perft;
+    use super::{run_bench, BENCH_POSITIONS};
+    use crate::{board::Board, engine::uci::UciOptions, perft::perft, search::Limits};
+
+    /// Test positions with an expected depth 4 perft result at the end.
+    static TEST_POSITIONS: &str = include_str!("../test_positions.epd");
 
     /// The depth to which each position will run `perft`.
     const PERFT_DEPTH: u8 = 4;
@@ -147,6 +219,67 @@ mod test {
         }
     }
 
+    /// Checks that two runs of the benchmark search the same number of nodes.
+    #[test]
+    fn bench_is_deterministic() {
+        let mut limits = Limits::default();
+        limits.set_depth(Some(4));
+
+        let mut options = UciOptions::new();
+        options.set_hash(1);
+
+        let (first_nodes, _) = run_bench(&options, limits);
+        let (second_nodes, _) = run_bench(&options, limits);
+
+        assert_eq!(first_nodes, second_nodes, "Bench node counts differ");
+    }
+
+    /// Checks that helper threads don't change the node count of a
+    /// deterministic benchmark.
+    #[test]
+    fn deterministic_threads() {
+        let mut limits = Limits::default();
+        limits.set_depth(Some(4));
+        let mut options = UciOptions::new();
+        options.set_hash(1);
+        options.set_deterministic(true);
+
+        let (single_nodes, _) = run_bench(&options, limits);
+        options.set_threads(3);
+        let (threaded_nodes, _) = run_bench(&options, limits);
+
+        assert_eq!(
+            single_nodes, threaded_nodes,
+            "Helper threads changed the node count"
+        );
+    }
+
+    /// Checks that every bench position is valid and has legal moves.
+    #[test]
+    fn bench_positions() {
+        for fen in BENCH_POSITIONS {
+            let board = fen.parse::<Board>().expect("Malformed bench position");
+
+            assert!(board.validate().is_ok(), "{fen}: invalid position");
+            assert!(board.legal_moves().len() > 0, "{fen}: no legal moves");
+        }
+    }
+
+    /// Checks that the benchmark also works with a movetime limit.
+    #[test]
+    fn bench_movetime() {
+        let mut limits = Limits::default();
+        limits.set_movetime(Some(Duration::from_millis(1)));
+
+        let mut options = UciOptions::new();
+        options.set_hash(1);
+
+        let (nodes, time) = run_bench(&options, limits);
+
+        assert!(nodes > 0, "No nodes searched");
+        assert!(time > Duration::ZERO, "No time taken");
+    }
+
     /// Runs perft to depth 4 on all positions in [`TEST_POSITIONS`].
     ///
     /// It treats the first 6 tokens of a line as the FEN string and the last
@@ -24,7 +24,10 @@ use std::{
     },
 };
 
-use crate::defs::{File, Rank, Square};
+use crate::{
+    defs::{File, Rank, Square},
+    movegen::LOOKUPS,
+};
 
 /// A bitboard: a set of bits representing a certain state of the board.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -33,21 +36,6 @@ pub struct Bitboard(pub u64);
 /// An iterator over the bits of a [`Bitboard`].
 pub struct BitIter(Bitboard);
 
-impl Bitboard {
-    /// The squares betwen the White king and kingside rook in the starting
-    /// position.
-    const CASTLING_SPACE_WK: Self = Self(0x0000_0000_0000_0060);
-    /// The squares betwen the White king and queenside rook in the starting
-    /// position.
-    const CASTLING_SPACE_WQ: Self = Self(0x0000_0000_0000_000e);
-    /// The squares betwen the Black king and kingside rook in the starting
-    /// position.
-    const CASTLING_SPACE_BK: Self = Self(0x6000_0000_0000_0000);
-    /// The squares betwen the Black king and queenside rook in the starting
-    /// position.
-    const CASTLING_SPACE_BQ: Self = Self(0x0e00_0000_0000_0000);
-}
-
 impl BitAnd for Bitboard {
     type Output = Self;
 
@@ -184,6 +172,11 @@ impl Iterator for BitIter {
 }
 
 impl Bitboard {
+    /// The light squares, such as b1 and a2.
+    pub const LIGHT_SQUARES: Self = Self(0x55aa_55aa_55aa_55aa);
+    /// The dark squares, such as a1 and b2.
+    pub const DARK_SQUARES: Self = Self(0xaa55_aa55_aa55_aa55);
+
     /// Returns the given file represented on a bitboard.
     ///
     /// e.g. `file_bb(File::FILE2) == 0x0202020202020202`.
@@ -250,20 +243,20 @@ impl Bitboard {
         occupancies: Self,
     ) -> bool {
         #[allow(clippy::collapsible_else_if)]
-        let castling_space = if IS_WHITE {
+        let (king, rook) = if IS_WHITE {
             if IS_KINGSIDE {
-                Self::CASTLING_SPACE_WK
+                (Square::E1, Square::H1)
             } else {
-                Self::CASTLING_SPACE_WQ
+                (Square::E1, Square::A1)
             }
         } else {
             if IS_KINGSIDE {
-                Self::CASTLING_SPACE_BK
+                (Square::E8, Square::H8)
             } else {
-                Self::CASTLING_SPACE_BQ
+                (Square::E8, Square::A8)
             }
         };
-        (occupancies & castling_space).is_empty()
+        (occupancies & LOOKUPS.between(king, rook)).is_empty()
     }
 
     /// Returns an empty bitboard.
@@ -276,6 +269,11 @@ impl Bitboard {
         self == Self::empty()
     }
 
+    /// Counts the number of set bits.
+    pub const fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
     /// Shifts the bitboard one square north if `IS_WHITE` is true, otherwise
     /// shifts it one square south.
     pub fn pawn_push<const IS_WHITE: bool>(self) -> Self {
@@ -26,13 +26,17 @@ use crate::{
     bitboard::Bitboard,
     defs::{File, Piece, PieceType, Rank, Side, Square},
     error::ParseError,
-    evaluation::{Phase, Score},
-    movegen::{Move, LOOKUPS},
+    evaluation::{Eval, Phase, Score},
+    movegen::{legal::generate_legal_moves, Move, Moves, LOOKUPS},
     util::{get_unchecked, insert_unchecked, is_double_pawn_push},
 };
 
 /// Accumulated, incrementally-updated fields.
 mod accumulators;
+/// Packing the board into a few bytes.
+pub mod packed;
+/// Calculating Polyglot keys.
+mod polyglot;
 
 /// The type of a zobrist key.
 pub type Key = u64;
@@ -77,10 +81,18 @@ pub struct Board {
     ///
     /// It is incrementally updated.
     score: Score,
+    /// The material of each side, excluding pawns and the king.
+    ///
+    /// It is incrementally updated.
+    non_pawn_material: [Eval; Side::TOTAL],
     /// The current zobrist key of the board.
     ///
     /// It is incrementally updated.
     zobrist: Key,
+    /// The zobrist key of only the pawns of the board.
+    ///
+    /// It is incrementally updated.
+    pawn_zobrist: Key,
 }
 
 /// Castling rights.
@@ -175,34 +187,47 @@ impl FromStr for Board {
     /// Parses a full `position` command.
     ///
     /// It will return with an [`Err`] if the FEN string cannot be parsed (e.g.
-    /// if it's too short) but does not check if the FEN string actually makes
-    /// sense (e.g. if it contains a row with 14 pieces).
+    /// if it's too short or contains a rank with 14 pieces) but does not check
+    /// if the position actually makes sense (e.g. if it has no kings): see
+    /// [`Board::validate()`] for that.
     fn from_str(string: &str) -> Result<Self, Self::Err> {
         let mut board = Self::new();
         let mut tokens = string.split_whitespace();
 
-        let board_str = tokens.next().ok_or(ParseError)?;
-        let side_to_move = tokens.next().ok_or(ParseError)?;
-        let castling_rights = tokens.next().ok_or(ParseError)?;
-        let ep_square = tokens.next().ok_or(ParseError)?;
-        let halfmoves = tokens.next().ok_or(ParseError)?;
-        let fullmoves = tokens.next().ok_or(ParseError)?;
+        let board_str = tokens.next().ok_or(ParseError::MissingToken)?;
+        let side_to_move = tokens.next().ok_or(ParseError::MissingToken)?;
+        let castling_rights = tokens.next().ok_or(ParseError::MissingToken)?;
+        let ep_square = tokens.next().ok_or(ParseError::MissingToken)?;
+        let halfmoves = tokens.next().ok_or(ParseError::MissingToken)?;
+        let fullmoves = tokens.next().ok_or(ParseError::MissingToken)?;
 
         // 1. the board itself
-        let mut square = 56;
-        let ranks = board_str.split('/');
-        for rank in ranks {
-            for piece in rank.chars() {
+        let rank_count = board_str.split('/').count();
+        if rank_count != Rank::TOTAL {
+            return Err(ParseError::WrongRankCount(rank_count));
+        }
+        // the ranks go from the 8th to the 1st
+        for (rank, rank_str) in (0..8_u8).rev().zip(board_str.split('/')) {
+            let mut squares = 0_u8;
+            for piece in rank_str.chars() {
                 // if it's a number, skip over that many files
-                if ('0'..='8').contains(&piece) {
-                    // SAFETY: we just checked `piece` is in the valid range
-                    square += unsafe { piece.to_digit(10).unwrap_unchecked() as u8 };
+                if ('1'..='8').contains(&piece) {
+                    squares = squares.saturating_add(piece as u8 - b'0');
                 } else {
-                    board.add_piece(Square(square), Piece::try_from(piece)?);
-                    square += 1;
+                    let piece = Piece::try_from(piece)?;
+                    // don't spill over into the next rank
+                    if squares < 8 {
+                        board.add_piece(Square(rank * 8 + squares), piece);
+                    }
+                    squares = squares.saturating_add(1);
                 }
             }
-            square = square.wrapping_sub(16);
+            if squares != 8 {
+                return Err(ParseError::WrongRankLength {
+                    rank: rank + 1,
+                    squares,
+                });
+            }
         }
 
         // 2. side to move
@@ -320,18 +345,28 @@ impl Board {
             fullmoves: 1,
             phase: 0,
             score: Score(0, 0),
+            non_pawn_material: [0; Side::TOTAL],
             zobrist: 0,
+            pawn_zobrist: 0,
         }
     }
 
     /// Pretty-prints the current state of the board.
-    pub fn pretty_print(&self) {
+    ///
+    /// If `use_unicode`, the pieces are printed as Unicode chess symbols
+    /// instead of letters.
+    pub fn pretty_print(&self, use_unicode: bool) {
         for rank in (0..Rank::TOTAL as u8).rev() {
             print!("{} | ", rank + 1);
             for file in 0..File::TOTAL as u8 {
                 let square = Square::from_pos(Rank(rank), File(file));
                 let piece = self.piece_on(square);
-                print!("{} ", char::from(piece));
+                let piece_char = if use_unicode {
+                    piece.to_unicode()
+                } else {
+                    char::from(piece)
+                };
+                print!("{piece_char} ");
             }
             println!();
         }
@@ -404,7 +439,14 @@ impl Board {
     /// Makes the given move on the internal board. `mv` is assumed to be a
     /// valid move. Returns `true` if the given move is legal and `false`
     /// otherwise.
+    ///
+    /// Will panic in debug if `mv` is not pseudolegal in this position.
     pub fn make_move(&mut self, mv: Move) -> bool {
+        debug_assert!(
+            self.is_pseudolegal(mv),
+            "Move {mv} is not pseudolegal in position {self}"
+        );
+
         let start = mv.start();
         let end = mv.end();
         let is_promotion = mv.is_promotion();
@@ -461,21 +503,15 @@ impl Board {
         }
 
         if is_castling {
-            // if the king is castling out of check
-            if self.is_square_attacked(start) {
-                return false;
-            }
-            // if the king is castling into check
-            if self.is_square_attacked(end) {
-                return false;
+            // if the king is castling out of, through or into check
+            for square in LOOKUPS.between_inclusive(start, end) {
+                if self.is_square_attacked(square) {
+                    return false;
+                }
             }
 
             let rook_start = Square(end.0.wrapping_add_signed(mv.rook_offset()));
             let rook_end = Square((start.0 + end.0) >> 1);
-            // if the king is castling through check
-            if self.is_square_attacked(rook_end) {
-                return false;
-            }
 
             self.move_piece(
                 rook_start,
@@ -544,6 +580,64 @@ impl Board {
         true
     }
 
+    /// Makes a null move: passes the turn to the other side without moving a
+    /// piece.
+    ///
+    /// Assumes the side to move is not in check.
+    pub fn make_null_move(&mut self) {
+        self.increment_halfmoves();
+        if self.side_to_move() == Side::BLACK {
+            self.increment_fullmoves();
+        }
+        self.clear_ep_square();
+        self.flip_side();
+    }
+
+    /// Checks if neither side has enough material to checkmate.
+    ///
+    /// This is the case with only kings and a single minor piece, or with
+    /// only kings and any number of bishops that are all on squares of the
+    /// same colour.
+    pub fn is_insufficient_material(&self) -> bool {
+        let pawns_rooks_queens = self.piece::<{ PieceType::PAWN.to_index() }>()
+            | self.piece::<{ PieceType::ROOK.to_index() }>()
+            | self.piece::<{ PieceType::QUEEN.to_index() }>();
+        if !pawns_rooks_queens.is_empty() {
+            return false;
+        }
+
+        let knights = self.piece::<{ PieceType::KNIGHT.to_index() }>();
+        let bishops = self.piece::<{ PieceType::BISHOP.to_index() }>();
+        if (knights | bishops).count() <= 1 {
+            return true;
+        }
+
+        knights.is_empty()
+            && ((bishops & Bitboard::LIGHT_SQUARES).is_empty()
+                || (bishops & Bitboard::DARK_SQUARES).is_empty())
+    }
+
+    /// Counts the pieces of type `piece_type` that `side` has.
+    pub fn piece_count(&self, piece_type: PieceType, side: Side) -> u32 {
+        (self.piece_any(piece_type) & self.side_any(side)).count()
+    }
+
+    /// Calculates the total material of `side`, excluding the king, using the
+    /// values of the static exchange evaluation.
+    #[allow(dead_code)]
+    pub fn material(&self, side: Side) -> Eval {
+        [
+            PieceType::PAWN,
+            PieceType::KNIGHT,
+            PieceType::BISHOP,
+            PieceType::ROOK,
+            PieceType::QUEEN,
+        ]
+        .into_iter()
+        .map(|piece_type| self.piece_count(piece_type, side) as Eval * piece_type.see_bonus())
+        .sum()
+    }
+
     /// Moves `piece` from `start` to `end`, updating all relevant fields.
     ///
     /// `piece == Piece::from_piecetype(piece_type, side)`. Having the two
@@ -569,6 +663,16 @@ impl Board {
         *get_unchecked(&self.mailbox, square.to_index())
     }
 
+    /// Checks if the given move captures a piece, including en passant.
+    pub fn is_capture(&self, mv: Move) -> bool {
+        mv.is_en_passant() || self.piece_on(mv.end()) != Piece::NONE
+    }
+
+    /// Checks if the given move is quiet: neither a capture nor a promotion.
+    pub fn is_quiet(&self, mv: Move) -> bool {
+        !self.is_capture(mv) && !mv.is_promotion()
+    }
+
     /// Adds a piece to square `square` for side `side`.
     ///
     /// Assumes there is no piece on the square to be written to.
@@ -718,9 +822,132 @@ impl Board {
         self.fullmoves = count;
     }
 
+    /// Creates a copy of the board with the colours swapped and the ranks
+    /// flipped, which is the same position from the other side's point of
+    /// view.
+    pub fn mirrored(&self) -> Self {
+        self.rebuild(true)
+    }
+
+    /// Creates a copy of the board with every accumulator calculated from
+    /// scratch instead of incrementally.
+    pub fn rebuilt(&self) -> Self {
+        self.rebuild(false)
+    }
+
+    /// Creates a copy of the board by adding each piece and setting each
+    /// field in turn, mirroring the board if `is_mirrored`.
+    fn rebuild(&self, is_mirrored: bool) -> Self {
+        let mirror_square = |square: Square| {
+            if is_mirrored && square != Square::NONE {
+                Square(square.0 ^ 0x38)
+            } else {
+                square
+            }
+        };
+        let mirror_side = |side: Side| if is_mirrored { side.flip() } else { side };
+
+        let mut board = Self::new();
+        for square in 0..Square::TOTAL as u8 {
+            let piece = self.piece_on(Square(square));
+            if piece != Piece::NONE {
+                let piece =
+                    Piece::from_piecetype(PieceType::from(piece), mirror_side(Side::from(piece)));
+                board.add_piece(mirror_square(Square(square)), piece);
+            }
+        }
+        board.set_side_to_move(mirror_side(self.side_to_move()));
+        let rights = [
+            (CastlingRights::K, CastlingRights::k),
+            (CastlingRights::Q, CastlingRights::q),
+            (CastlingRights::k, CastlingRights::K),
+            (CastlingRights::q, CastlingRights::Q),
+        ];
+        for (right, mirrored_right) in rights {
+            if self.castling_rights() & right == right {
+                board.add_castling_rights(if is_mirrored { mirrored_right } else { right });
+            }
+        }
+        board.set_ep_square(mirror_square(self.ep_square()));
+        board.set_halfmoves(self.halfmoves());
+        board.set_fullmoves(self.fullmoves());
+        board
+    }
+
+    /// Checks that the position could arise in a game, as far as can be
+    /// cheaply told: each side has 1 king, there are no pawns on the first or
+    /// last rank, the side not to move isn't in check and the castling rights
+    /// and en passant square are possible.
+    ///
+    /// Parsing a FEN string doesn't do this, so it should be called on any
+    /// position from outside the engine before it's searched.
+    pub fn validate(&self) -> Result<(), ParseError> {
+        let kings = self.piece::<{ PieceType::KING.to_index() }>();
+        for side in [Side::WHITE, Side::BLACK] {
+            let count = (kings & self.side_any(side)).count();
+            if count != 1 {
+                return Err(ParseError::KingCount { side, count });
+            }
+        }
+
+        let back_ranks = Bitboard::rank_bb(Rank::RANK1) | Bitboard::rank_bb(Rank::RANK8);
+        let back_rank_pawns = self.piece::<{ PieceType::PAWN.to_index() }>() & back_ranks;
+        if !back_rank_pawns.is_empty() {
+            return Err(ParseError::PawnOnBackRank(Square::from(back_rank_pawns)));
+        }
+
+        let us = self.side_to_move();
+        let their_king = kings & self.side_any(us.flip());
+        if !(self.attacked_squares(us) & their_king).is_empty() {
+            return Err(ParseError::OpponentInCheck);
+        }
+
+        let castling = [
+            (CastlingRights::K, 'K', Square::E1, Square::H1, Side::WHITE),
+            (CastlingRights::Q, 'Q', Square::E1, Square::A1, Side::WHITE),
+            (CastlingRights::k, 'k', Square::E8, Square::H8, Side::BLACK),
+            (CastlingRights::q, 'q', Square::E8, Square::A8, Side::BLACK),
+        ];
+        for (right, name, king_square, rook_square, side) in castling {
+            if self.castling_rights() & right == right
+                && (self.piece_on(king_square) != Piece::from_piecetype(PieceType::KING, side)
+                    || self.piece_on(rook_square) != Piece::from_piecetype(PieceType::ROOK, side))
+            {
+                return Err(ParseError::InvalidCastlingRights(name));
+            }
+        }
+
+        // the pawn that just moved 2 squares must be in front of the en
+        // passant square and the square it passed over must be empty
+        let ep_square = self.ep_square();
+        if ep_square != Square::NONE {
+            let (ep_rank, pawn_square) = if us == Side::WHITE {
+                (Rank::RANK6, Square(ep_square.0.wrapping_sub(8)))
+            } else {
+                (Rank::RANK3, Square(ep_square.0 + 8))
+            };
+            if Rank::from(ep_square).0 != ep_rank.0
+                || self.piece_on(pawn_square) != Piece::from_piecetype(PieceType::PAWN, us.flip())
+                || self.piece_on(ep_square) != Piece::NONE
+            {
+                return Err(ParseError::InvalidEpSquare(ep_square));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Tests if the king is in check.
     pub fn is_in_check(&self) -> bool {
-        self.is_square_attacked(self.king_square())
+        !self.checkers().is_empty()
+    }
+
+    /// Calculates the pieces giving check to the king of the side to move.
+    pub fn checkers(&self) -> Bitboard {
+        let us = self.side_to_move();
+        let them_bb = self.side_any(us.flip());
+
+        self.square_attackers(us, self.king_square()) & them_bb
     }
 
     /// Calculates the square the king is on.
@@ -755,6 +982,50 @@ impl Board {
             | orthogonal_attacks & (rooks | queens)
     }
 
+    /// Calculates all the squares attacked by `side`.
+    ///
+    /// The king of the other side is treated as transparent to sliding
+    /// pieces, since it can't escape a slider by moving along the slider's
+    /// ray. This means the result can be used to mask king moves directly.
+    pub fn attacked_squares(&self, side: Side) -> Bitboard {
+        let side_bb = self.side_any(side);
+        let their_king =
+            self.piece::<{ PieceType::KING.to_index() }>() & self.side_any(side.flip());
+        let occupancies = self.occupancies() ^ their_king;
+
+        let pawns = self.piece::<{ PieceType::PAWN.to_index() }>() & side_bb;
+        let pawns = if side == Side::WHITE {
+            pawns.north()
+        } else {
+            pawns.south()
+        };
+        let mut attacked = pawns.east() | pawns.west();
+
+        let knights = self.piece::<{ PieceType::KNIGHT.to_index() }>() & side_bb;
+        for knight in knights {
+            attacked |= LOOKUPS.knight_attacks(knight);
+        }
+
+        let queens = self.piece::<{ PieceType::QUEEN.to_index() }>();
+        let diagonal_sliders =
+            (self.piece::<{ PieceType::BISHOP.to_index() }>() | queens) & side_bb;
+        for slider in diagonal_sliders {
+            attacked |= LOOKUPS.bishop_attacks(slider, occupancies);
+        }
+        let orthogonal_sliders =
+            (self.piece::<{ PieceType::ROOK.to_index() }>() | queens) & side_bb;
+        for slider in orthogonal_sliders {
+            attacked |= LOOKUPS.rook_attacks(slider, occupancies);
+        }
+
+        let kings = self.piece::<{ PieceType::KING.to_index() }>() & side_bb;
+        for king in kings {
+            attacked |= LOOKUPS.king_attacks(king);
+        }
+
+        attacked
+    }
+
     /// Tests if `square` is attacked by an enemy piece.
     fn is_square_attacked(&self, square: Square) -> bool {
         let us = self.side_to_move();
@@ -764,10 +1035,135 @@ impl Board {
         !(self.square_attackers(us, square) & them_bb).is_empty()
     }
 
+    /// Checks if `mv` could have been generated in the current position by
+    /// [`generate_moves()`](crate::movegen::generate_moves) with
+    /// `MoveType::ALL`, ignoring whether or not it leaves the king in check.
+    ///
+    /// This catches moves that were created from a different position.
+    pub fn is_pseudolegal(&self, mv: Move) -> bool {
+        if mv == Move::null() {
+            return false;
+        }
+
+        let start = mv.start();
+        let end = mv.end();
+        let us = self.side_to_move();
+        let us_bb = self.side_any(us);
+        let them_bb = self.side_any(us.flip());
+        let occupancies = us_bb | them_bb;
+        let end_bb = Bitboard::from(end);
+        let piece_type = PieceType::from(self.piece_on(start));
+
+        // we have to move one of our own pieces and we can't capture one of
+        // our own pieces
+        if (Bitboard::from(start) & us_bb).is_empty() || !(end_bb & us_bb).is_empty() {
+            return false;
+        }
+
+        if mv.is_castling() {
+            return piece_type == PieceType::KING && self.is_pseudolegal_castle(mv);
+        }
+
+        if piece_type != PieceType::PAWN {
+            if mv.is_en_passant() || mv.is_promotion() {
+                return false;
+            }
+
+            let attacks = match piece_type {
+                PieceType::KNIGHT => LOOKUPS.knight_attacks(start),
+                PieceType::BISHOP => LOOKUPS.bishop_attacks(start, occupancies),
+                PieceType::ROOK => LOOKUPS.rook_attacks(start, occupancies),
+                PieceType::QUEEN => LOOKUPS.queen_attacks(start, occupancies),
+                PieceType::KING => LOOKUPS.king_attacks(start),
+                _ => Bitboard::empty(),
+            };
+            return !(attacks & end_bb).is_empty();
+        }
+
+        // the bitboards below can't wrap around the edge of the board, but a
+        // pawn can never change file by more than 1 so make it explicit
+        if File::from(start).0.abs_diff(File::from(end).0) > 1 {
+            return false;
+        }
+
+        let attacks = LOOKUPS.pawn_attacks(us, start);
+        if mv.is_en_passant() {
+            return end == self.ep_square() && !(attacks & end_bb).is_empty();
+        }
+
+        // a pawn moving to the last rank has to promote and a promotion has
+        // to move to the last rank
+        let (last_rank, double_push_rank) = if us == Side::WHITE {
+            (Rank::RANK8, Rank::RANK4)
+        } else {
+            (Rank::RANK1, Rank::RANK5)
+        };
+        if mv.is_promotion() == (end_bb & Bitboard::rank_bb(last_rank)).is_empty() {
+            return false;
+        }
+
+        let forward = |bb: Bitboard| {
+            if us == Side::WHITE {
+                bb.north()
+            } else {
+                bb.south()
+            }
+        };
+        let single_push = forward(Bitboard::from(start)) & !occupancies;
+        let double_push = forward(single_push) & !occupancies & Bitboard::rank_bb(double_push_rank);
+        let targets = attacks & them_bb | single_push | double_push;
+
+        !(targets & end_bb).is_empty()
+    }
+
+    /// Checks if `mv` is legal in the current position.
+    pub fn is_legal(&self, mv: Move) -> bool {
+        let mut copy = *self;
+        self.is_pseudolegal(mv) && copy.make_move(mv)
+    }
+
+    /// Generates all legal moves.
+    pub fn legal_moves(&self) -> Moves {
+        generate_legal_moves(self)
+    }
+
+    /// Generates all legal moves that start from `square`.
+    ///
+    /// This generates every move and then filters them, so it isn't meant to
+    /// be used in the search.
+    pub fn legal_moves_from(&self, square: Square) -> Moves {
+        self.legal_moves()
+            .filter(|&mv| mv.start() == square)
+            .collect()
+    }
+
+    /// Checks if the castling move `mv` could have been generated in the
+    /// current position.
+    fn is_pseudolegal_castle(&self, mv: Move) -> bool {
+        let rights = self.castling_rights();
+        let occupancies = self.occupancies();
+
+        if self.side_to_move() == Side::WHITE {
+            mv == Move::new_castle::<true, true>()
+                && rights.can_castle_kingside::<true>()
+                && Bitboard::is_clear_to_castle::<true, true>(occupancies)
+                || mv == Move::new_castle::<true, false>()
+                    && rights.can_castle_queenside::<true>()
+                    && Bitboard::is_clear_to_castle::<true, false>(occupancies)
+        } else {
+            mv == Move::new_castle::<false, true>()
+                && rights.can_castle_kingside::<false>()
+                && Bitboard::is_clear_to_castle::<false, true>(occupancies)
+                || mv == Move::new_castle::<false, false>()
+                    && rights.can_castle_queenside::<false>()
+                    && Bitboard::is_clear_to_castle::<false, false>(occupancies)
+        }
+    }
+
     /// Performs Static Exchange Evaluation (SEE) on the destination square of
-    /// the given move. Returns whether or not the resulting exchange is a net
-    /// material win.
-    pub fn is_winning_exchange(&self, mv: Move) -> bool {
+    /// the given move. Returns whether or not the resulting exchange gains at
+    /// least `threshold` material.
+    pub fn see_ge(&self, mv: Move, threshold: Eval) -> bool {
         let origin = mv.start();
         let target = mv.end();
         let mut us = self.side_to_move();
@@ -782,6 +1178,11 @@ impl Board {
             // swap the pawn vaue with the promotion piece value
             see_value += mv.promotion_piece().see_bonus() - PieceType::PAWN.see_bonus();
         }
+        see_value -= threshold;
+        // if we're down material even if they don't recapture
+        if see_value < 0 {
+            return false;
+        }
 
         let mut attacker_type = if mv.is_promotion() {
             mv.promotion_piece()
@@ -906,3 +1307,393 @@ impl CastlingRights {
         *self &= !right;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use oorandom::Rand64;
+
+    use super::Board;
+    use crate::{
+        bitboard::Bitboard,
+        defs::{MoveType, PieceType, Side, Square},
+        error::ParseError,
+        movegen::{generate_moves, Move},
+    };
+
+    /// Checks that pinned pieces can only move along the pin and that
+    /// unrestricted pieces can make all their moves.
+    #[test]
+    fn legal_moves_from() {
+        let board = "4k3/4r3/8/3N4/8/8/4R3/4K3 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+
+        // the rook on e2 is pinned, so it can only move up to e7
+        assert_eq!(board.legal_moves_from(Square::E2).len(), 5, "Pinned rook");
+        assert_eq!(board.legal_moves_from(Square::D5).len(), 8, "Centre knight");
+        assert_eq!(board.legal_moves_from(Square::A1).len(), 0, "Empty square");
+    }
+
+    /// Checks the Polyglot key of every position published in the Polyglot
+    /// book format specification.
+    #[test]
+    fn polyglot_key() {
+        for (fen, key) in [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                0x463b_9618_1691_fc9c,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                0x823c_9b50_fd11_4196,
+            ),
+            (
+                "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+                0x0756_b944_61c5_0fb0,
+            ),
+            (
+                "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2",
+                0x662f_afb9_65db_29d4,
+            ),
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+                0x22a4_8b5a_8e47_ff78,
+            ),
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3",
+                0x652a_607c_a3f2_42c1,
+            ),
+            (
+                "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4",
+                0x00fd_d303_c946_bdd9,
+            ),
+            (
+                "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+                0x3c81_23ea_7b06_7637,
+            ),
+            (
+                "rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4",
+                0x5c3f_9b82_9b27_9560,
+            ),
+        ] {
+            let board = fen.parse::<Board>().expect(fen);
+            assert_eq!(board.polyglot_key(), key, "{fen}");
+        }
+    }
+
+    /// Checks that malformed FEN strings and impossible positions are
+    /// rejected with the right error.
+    #[test]
+    fn validate() {
+        let error = |fen: &str| {
+            fen.parse::<Board>()
+                .and_then(|board| board.validate())
+                .expect_err(fen)
+        };
+
+        assert!(
+            matches!(
+                error("4k3/8/8/8/8/8/8/4K3/8 w - - 0 1"),
+                ParseError::WrongRankCount(9)
+            ),
+            "9 ranks"
+        );
+        assert!(
+            matches!(
+                error("4k3/8/8/8/8/8/PPPPPPPPPPPPPP/4K3 w - - 0 1"),
+                ParseError::WrongRankLength {
+                    rank: 2,
+                    squares: 14
+                }
+            ),
+            "Rank with 14 pieces"
+        );
+        assert!(
+            matches!(
+                error("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+                ParseError::KingCount { count: 0, .. }
+            ),
+            "Missing king"
+        );
+        assert!(
+            matches!(
+                error("4k3/8/8/8/8/8/8/P3K3 w - - 0 1"),
+                ParseError::PawnOnBackRank(Square::A1)
+            ),
+            "Pawn on the 1st rank"
+        );
+        assert!(
+            matches!(
+                error("4k2R/8/8/8/8/8/8/4K2r w - - 0 1"),
+                ParseError::OpponentInCheck
+            ),
+            "Both kings in check"
+        );
+        assert!(
+            matches!(
+                error("4k3/8/8/8/8/8/8/4K3 w K - 0 1"),
+                ParseError::InvalidCastlingRights('K')
+            ),
+            "Castling without a rook"
+        );
+        assert!(
+            matches!(
+                error("4k3/8/8/8/8/8/8/4K3 w - e6 0 1"),
+                ParseError::InvalidEpSquare(Square::E6)
+            ),
+            "En passant without a pawn"
+        );
+        assert!(
+            Board::default().validate().is_ok(),
+            "The starting position is valid"
+        );
+    }
+
+    /// Checks that the checkers are found in positions with zero, one and two
+    /// checkers.
+    #[test]
+    fn checkers() {
+        let no_check = Board::default();
+        let single_check = "4k3/8/8/8/8/8/8/R3K2r w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let double_check = "4k3/8/8/8/8/3n4/8/4K2r w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+
+        assert!(no_check.checkers().is_empty(), "No checkers");
+        assert!(!no_check.is_in_check(), "Not in check");
+
+        assert_eq!(
+            single_check.checkers(),
+            Bitboard::from(Square::H1),
+            "Single checker"
+        );
+        assert!(single_check.is_in_check(), "In check");
+
+        assert_eq!(
+            double_check.checkers(),
+            Bitboard::from(Square::H1) | Bitboard::from(Square::D3),
+            "Double checkers"
+        );
+        assert_eq!(
+            double_check.checkers().into_iter().count(),
+            2,
+            "Two checkers"
+        );
+        assert!(double_check.is_in_check(), "In check");
+    }
+
+    /// Checks the piece counts and material of the starting position and an
+    /// endgame.
+    #[test]
+    fn piece_count_and_material() {
+        let startpos = Board::default();
+        let endgame = "4k3/8/8/8/8/8/4P3/3RK3 b - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+
+        for side in [Side::WHITE, Side::BLACK] {
+            assert_eq!(startpos.piece_count(PieceType::PAWN, side), 8, "Pawns");
+            assert_eq!(startpos.piece_count(PieceType::KNIGHT, side), 2, "Knights");
+            assert_eq!(startpos.piece_count(PieceType::QUEEN, side), 1, "Queens");
+            assert_eq!(startpos.piece_count(PieceType::KING, side), 1, "Kings");
+            assert_eq!(startpos.material(side), 3_900, "Startpos material");
+        }
+
+        assert_eq!(endgame.piece_count(PieceType::ROOK, Side::WHITE), 1, "Rook");
+        assert_eq!(
+            endgame.piece_count(PieceType::PAWN, Side::BLACK),
+            0,
+            "Pawns"
+        );
+        assert_eq!(endgame.material(Side::WHITE), 600, "White material");
+        assert_eq!(endgame.material(Side::BLACK), 0, "Black material");
+        assert_eq!(
+            endgame.non_pawn_material(Side::WHITE),
+            500,
+            "White non-pawn material"
+        );
+        assert_eq!(
+            endgame.non_pawn_material(Side::BLACK),
+            0,
+            "Black only has a king"
+        );
+    }
+
+    /// Checks that a king doesn't block the attacks of a slider behind it.
+    #[test]
+    fn attacked_squares_xray_king() {
+        let board = "8/8/8/3k4/8/8/3R4/4K3 b - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let attacked = board.attacked_squares(Side::WHITE);
+
+        for square in [Square::D1, Square::D4, Square::D5, Square::D6, Square::D8] {
+            assert!(
+                !(attacked & Bitboard::from(square)).is_empty(),
+                "{square} should be attacked"
+            );
+        }
+        for square in [Square::C6, Square::E6, Square::C4, Square::E4] {
+            assert!(
+                (attacked & Bitboard::from(square)).is_empty(),
+                "{square} should not be attacked"
+            );
+        }
+        // Black's king is not transparent to its own attacks
+        assert!(
+            (board.attacked_squares(Side::BLACK) & Bitboard::from(Square::D2)).is_empty(),
+            "The Black king should not attack d2"
+        );
+    }
+
+    /// Checks that making a move from a different position is caught in
+    /// debug.
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic = "is not pseudolegal"]
+    fn stale_move_panics() {
+        let mut board = Board::default();
+        let stale_move = Move::new(Square::E2, Square::E4);
+        assert!(board.make_move(stale_move), "e2e4 is legal in startpos");
+        // it's now Black to move, so e2e4 can't be made again
+        board.make_move(stale_move);
+    }
+
+    /// Checks that pawn moves on the a- and h-files can't wrap around to the
+    /// other side of the board.
+    #[test]
+    fn pawn_moves_dont_wrap() {
+        let white = "4k3/p7/8/7P/P6p/8/8/4K3 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let black = "4k3/p7/8/7P/P6p/8/8/4K3 b - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+
+        assert!(
+            white.is_pseudolegal(Move::new(Square::A4, Square::A5)),
+            "a5"
+        );
+        assert!(
+            white.is_pseudolegal(Move::new(Square::H5, Square::H6)),
+            "h6"
+        );
+        assert!(
+            !white.is_pseudolegal(Move::new(Square::A4, Square::H4)),
+            "a4xh4"
+        );
+        assert!(
+            !white.is_pseudolegal(Move::new(Square::H5, Square::A7)),
+            "h5xa7"
+        );
+        assert!(
+            !black.is_pseudolegal(Move::new(Square::H4, Square::A4)),
+            "h4xa4"
+        );
+        assert!(
+            !black.is_pseudolegal(Move::new(Square::A7, Square::H5)),
+            "a7xh5"
+        );
+    }
+
+    /// Checks that, in many positions reached by random playouts, every move
+    /// that can be encoded is pseudolegal if and only if it's generated.
+    #[test]
+    fn is_pseudolegal_matches_movegen() {
+        let mut rng = Rand64::new(0x00C0_FFEE);
+        let starts = [
+            Board::default(),
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+                .parse::<Board>()
+                .expect("Valid FEN"),
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"
+                .parse::<Board>()
+                .expect("Valid FEN"),
+        ];
+        let castles = [
+            Move::new_castle::<true, true>(),
+            Move::new_castle::<true, false>(),
+            Move::new_castle::<false, true>(),
+            Move::new_castle::<false, false>(),
+        ];
+
+        for start_board in starts {
+            for _ in 0..5 {
+                let mut board = start_board;
+                for _ in 0..30 {
+                    let generated = generate_moves::<{ MoveType::ALL }>(&board).collect::<Vec<_>>();
+                    check_every_encoding(&board, &generated, &castles);
+
+                    let legal = generated
+                        .iter()
+                        .copied()
+                        .filter(|&mv| {
+                            let mut copy = board;
+                            copy.make_move(mv)
+                        })
+                        .collect::<Vec<_>>();
+                    if legal.is_empty() {
+                        break;
+                    }
+                    let index = rng.rand_range(0..legal.len() as u64) as usize;
+                    assert!(board.make_move(legal[index]), "Move should be legal");
+                }
+            }
+        }
+    }
+
+    /// Asserts that every move that can be encoded in `board` is pseudolegal
+    /// if and only if it's in `generated`.
+    fn check_every_encoding(board: &Board, generated: &[Move], castles: &[Move]) {
+        let promotions = [
+            PieceType::KNIGHT,
+            PieceType::BISHOP,
+            PieceType::ROOK,
+            PieceType::QUEEN,
+        ];
+        let check = |mv: Move| {
+            assert_eq!(
+                board.is_pseudolegal(mv),
+                generated.contains(&mv),
+                "is_pseudolegal disagrees with movegen on {mv} in {board}",
+            );
+        };
+
+        for start in 0..Square::TOTAL as u8 {
+            for end in 0..Square::TOTAL as u8 {
+                let (start, end) = (Square(start), Square(end));
+                check(Move::new(start, end));
+                check(Move::new_en_passant(start, end));
+                for piece_type in promotions {
+                    check(Move::new_promo_any(start, end, piece_type));
+                }
+            }
+        }
+        for &castle in castles {
+            check(castle);
+        }
+    }
+
+    /// Checks that bare kings, a single minor piece and bishops on one colour
+    /// are insufficient material, but bishops on both colours aren't.
+    #[test]
+    fn insufficient_material() {
+        let positions = [
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", true),
+            // light-squared bishops on c8, b1 and d1
+            ("2b1k3/8/8/8/8/8/8/1B1BK3 w - - 0 1", true),
+            // the bishop on d8 is on a dark square
+            ("2bbk3/8/8/8/8/8/8/1B1BK3 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/8/3NKN2 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", false),
+        ];
+
+        for (fen, is_insufficient) in positions {
+            let board = fen.parse::<Board>().expect("Valid FEN");
+            assert_eq!(board.is_insufficient_material(), is_insufficient, "{fen}");
+        }
+    }
+}
@@ -19,9 +19,9 @@
 use super::{Board, CastlingRights, Key};
 use crate::{
     cfor,
-    defs::{Piece, Square},
-    evaluation::{piece_phase, piece_score, Phase, Score},
-    util::get_unchecked,
+    defs::{Piece, Side, Square},
+    evaluation::{piece_non_pawn_material, piece_phase, piece_score, Eval, Phase, Score},
+    util::{get_unchecked, insert_unchecked},
 };
 
 #[allow(clippy::doc_markdown)]
@@ -65,11 +65,30 @@ impl Board {
         self.score
     }
 
+    /// Returns the accumulated material of `side`, excluding pawns and the
+    /// king.
+    pub fn non_pawn_material(&self, side: Side) -> Eval {
+        *get_unchecked(&self.non_pawn_material, side.to_index())
+    }
+
     /// Gets the zobrist key.
     pub const fn zobrist(&self) -> Key {
         self.zobrist
     }
 
+    /// Gets the zobrist key of the pawns.
+    pub const fn pawn_zobrist(&self) -> Key {
+        self.pawn_zobrist
+    }
+
+    /// Calculates how the zobrist key changes when `piece` moves from `start`
+    /// to `end` without capturing anything or changing the castling rights or
+    /// en passant square.
+    pub const fn quiet_move_key(piece: Piece, start: Square, end: Square) -> Key {
+        let piece_table = &ZOBRIST_KEYS.piece_and_side[piece.to_index()];
+        piece_table[start.to_index()] ^ piece_table[end.to_index()] ^ ZOBRIST_KEYS.side_key()
+    }
+
     /// Moves the accumulated `piece` from `start` to `end`.
     pub fn move_accumulated_piece(&mut self, start: Square, end: Square, piece: Piece) {
         self.move_piece_score(start, end, piece);
@@ -79,6 +98,7 @@ impl Board {
     /// Adds `piece` on `square` to the accumulators.
     pub fn add_accumulated_piece(&mut self, square: Square, piece: Piece) {
         self.add_piece_phase(piece);
+        self.add_piece_material(piece);
         self.add_piece_score(square, piece);
         self.toggle_piece_zobrist(square, piece);
     }
@@ -86,6 +106,7 @@ impl Board {
     /// Removes `piece` on `square` from the accumulators.
     pub fn remove_accumulated_piece(&mut self, square: Square, piece: Piece) {
         self.remove_piece_phase(piece);
+        self.remove_piece_material(piece);
         self.remove_piece_score(square, piece);
         self.toggle_piece_zobrist(square, piece);
     }
@@ -100,6 +121,24 @@ impl Board {
         self.phase -= piece_phase(piece);
     }
 
+    /// Adds the material of `piece` to the non-pawn material accumulator of
+    /// its side.
+    fn add_piece_material(&mut self, piece: Piece) {
+        let side = Side::from(piece).to_index();
+        let material =
+            *get_unchecked(&self.non_pawn_material, side) + piece_non_pawn_material(piece);
+        insert_unchecked(&mut self.non_pawn_material, side, material);
+    }
+
+    /// Removes the material of `piece` from the non-pawn material accumulator
+    /// of its side.
+    fn remove_piece_material(&mut self, piece: Piece) {
+        let side = Side::from(piece).to_index();
+        let material =
+            *get_unchecked(&self.non_pawn_material, side) - piece_non_pawn_material(piece);
+        insert_unchecked(&mut self.non_pawn_material, side, material);
+    }
+
     /// Adds the value of `piece` to the score accumulator.
     fn move_piece_score(&mut self, start: Square, end: Square, piece: Piece) {
         self.remove_piece_score(start, piece);
@@ -122,11 +161,16 @@ impl Board {
         self.toggle_piece_zobrist(end, piece);
     }
 
-    /// Toggles the zobrist key of the given piece on the given square.
+    /// Toggles the zobrist key of the given piece on the given square, and the
+    /// pawn key too if it's a pawn.
     ///
     /// `piece` can be [`Piece::NONE`] but `square` has to be a valid square.
     fn toggle_piece_zobrist(&mut self, square: Square, piece: Piece) {
-        self.zobrist ^= ZOBRIST_KEYS.piece_key(square, piece);
+        let key = ZOBRIST_KEYS.piece_key(square, piece);
+        self.zobrist ^= key;
+        if piece == Piece::WPAWN || piece == Piece::BPAWN {
+            self.pawn_zobrist ^= key;
+        }
     }
 
     /// Toggles the side to move zobrist key.
@@ -0,0 +1,126 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::{Board, CastlingRights};
+use crate::{
+    bitboard::Bitboard,
+    defs::{Piece, Side, Square},
+    error::ParseError,
+};
+
+/// A board packed into [`PackedBoard::SIZE`] bytes, for storing many
+/// positions compactly.
+///
+/// The bytes are, in order: the occupancies as a little-endian bitboard, the
+/// piece on each occupied square from a1 to h8 as 1 nibble each (low nibble
+/// first), the castling rights in the low nibble of 1 byte with the side to
+/// move in the bit above, the en passant square (64 if there isn't one), the
+/// halfmoves and the fullmoves as a little-endian `u16`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PackedBoard([u8; Self::SIZE]);
+
+impl PackedBoard {
+    /// The number of bytes of a packed board.
+    pub const SIZE: usize = 29;
+    /// The most pieces a packed board can hold.
+    const MAX_PIECES: usize = 32;
+    /// The bit of the flags byte set if White is to move.
+    const WHITE_TO_MOVE: u8 = 0b1_0000;
+
+    /// Creates a packed board from its bytes.
+    pub const fn from_bytes(bytes: [u8; Self::SIZE]) -> Self {
+        Self(bytes)
+    }
+
+    /// Returns the bytes of the packed board.
+    pub const fn to_bytes(self) -> [u8; Self::SIZE] {
+        self.0
+    }
+
+    /// Unpacks the board.
+    ///
+    /// Returns [`ParseError::InvalidToken`] if a piece or square is invalid.
+    /// Like parsing a FEN string, this doesn't check that the position makes
+    /// sense.
+    pub fn unpack(&self) -> Result<Board, ParseError> {
+        let bytes = &self.0;
+        let mut board = Board::new();
+
+        let mut occupancies = [0; 8];
+        occupancies.copy_from_slice(&bytes[0..8]);
+        for (index, square) in Bitboard(u64::from_le_bytes(occupancies))
+            .into_iter()
+            .enumerate()
+        {
+            let piece = (bytes[8 + index / 2] >> (index % 2 * 4)) & 0xF;
+            if index >= Self::MAX_PIECES || usize::from(piece) >= Piece::TOTAL {
+                return Err(ParseError::InvalidToken);
+            }
+            board.add_piece(square, Piece(piece));
+        }
+
+        let flags = bytes[24];
+        board.set_side_to_move(if flags & Self::WHITE_TO_MOVE == 0 {
+            Side::BLACK
+        } else {
+            Side::WHITE
+        });
+        board.add_castling_rights(CastlingRights(flags & 0xF));
+
+        let ep_square = Square(bytes[25]);
+        if ep_square.0 > Square::NONE.0 {
+            return Err(ParseError::InvalidToken);
+        }
+        board.set_ep_square(ep_square);
+        board.set_halfmoves(bytes[26]);
+        board.set_fullmoves(u16::from_le_bytes([bytes[27], bytes[28]]));
+
+        Ok(board)
+    }
+}
+
+impl Board {
+    /// Packs the board into a [`PackedBoard`].
+    ///
+    /// A legal position has at most 32 pieces: any more aren't packed.
+    pub fn pack(&self) -> PackedBoard {
+        let mut bytes = [0; PackedBoard::SIZE];
+        let mut occupancies = Bitboard::empty();
+
+        for (index, square) in self
+            .occupancies()
+            .into_iter()
+            .take(PackedBoard::MAX_PIECES)
+            .enumerate()
+        {
+            occupancies |= Bitboard::from(square);
+            bytes[8 + index / 2] |= self.piece_on(square).0 << (index % 2 * 4);
+        }
+        bytes[0..8].copy_from_slice(&occupancies.0.to_le_bytes());
+
+        bytes[24] = self.castling_rights().0;
+        if self.side_to_move() == Side::WHITE {
+            bytes[24] |= PackedBoard::WHITE_TO_MOVE;
+        }
+        bytes[25] = self.ep_square().0;
+        bytes[26] = self.halfmoves();
+        bytes[27..29].copy_from_slice(&self.fullmoves().to_le_bytes());
+
+        PackedBoard(bytes)
+    }
+}
@@ -0,0 +1,444 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use binary::{write_record, RecordReader};
+use oorandom::Rand64;
+
+use crate::{
+    board::{Board, Key},
+    defs::Side,
+    engine::uci::{Output, UciOptions},
+    error::ParseError,
+    evaluation::{is_mate, pawns::PawnTable, Eval},
+    fen_generation::{random_opening, MAX_PLY, MIN_PLY},
+    search::{search_sync, Limits},
+    transposition_table::TranspositionTable,
+};
+
+/// A compact binary format for the positions.
+pub mod binary;
+
+/// The default number of threads playing games.
+pub const THREADS: usize = 1;
+/// The default number of nodes searched for each move.
+pub const NODES: u64 = 5000;
+/// The default file the positions are appended to.
+pub const OUTPUT: &str = "data.txt";
+/// The largest static evaluation an opening may have, so that games don't
+/// start already decided.
+const MAX_OPENING_EVAL: Eval = 300;
+/// The size of the transposition table of each thread, in MiB.
+const TT_SIZE: usize = 16;
+/// How many games are played between each progress report.
+const REPORT_INTERVAL: usize = 100;
+
+/// The options of [`datagen()`].
+struct DatagenOptions {
+    /// How many games to play in total.
+    games: usize,
+    /// How many threads to play the games on.
+    threads: usize,
+    /// How many nodes to search for each move.
+    nodes: u64,
+    /// The seed of the random openings.
+    seed: u128,
+    /// The file the positions are appended to.
+    output: String,
+    /// The format the positions are written in.
+    format: Format,
+}
+
+/// The format of a file of positions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Format {
+    /// `<fen> | <score> | <result>` lines.
+    Text,
+    /// Records of [`binary::RECORD_SIZE`] bytes.
+    Binary,
+}
+
+/// The result of a game.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// White won.
+    WhiteWin,
+    /// The game was drawn.
+    Draw,
+    /// Black won.
+    BlackWin,
+}
+
+/// A position of a game, recorded for training.
+pub struct Record {
+    /// The position.
+    pub board: Board,
+    /// The score of the search, from White's perspective.
+    pub score: Eval,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::WhiteWin => f.write_str("1.0"),
+            Self::Draw => f.write_str("0.5"),
+            Self::BlackWin => f.write_str("0.0"),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = ParseError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "text" => Ok(Self::Text),
+            "binary" => Ok(Self::Binary),
+            _ => Err(ParseError::InvalidToken),
+        }
+    }
+}
+
+impl FromStr for Outcome {
+    type Err = ParseError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "1.0" => Ok(Self::WhiteWin),
+            "0.5" => Ok(Self::Draw),
+            "0.0" => Ok(Self::BlackWin),
+            _ => Err(ParseError::InvalidToken),
+        }
+    }
+}
+
+/// Plays games against itself from random openings and appends the
+/// positions to a file for training the evaluation.
+///
+/// The options are `<games>`, optionally followed by `threads <threads>`,
+/// `nodes <nodes>`, `seed <seed>`, `output <file>` and `format
+/// <text|binary>` (default [`THREADS`], [`NODES`], 0, [`OUTPUT`] and `text`).
+/// Each line of a text file is `<fen> | <score> | <result>`, where the score
+/// is in centipawns and the result is `1.0`, `0.5` or `0.0`, both from
+/// White's perspective. A binary file has the same information in the
+/// records of [`binary`]. Positions in check, positions whose best move isn't
+/// quiet and positions with a mate score are skipped.
+pub fn datagen<'a, T>(tokens: T)
+where
+    T: Iterator<Item = &'a str>,
+{
+    let options = match parse_options(tokens) {
+        Ok(options) => options,
+        Err(message) => {
+            println!("info string {message}");
+            return;
+        }
+    };
+    let file = match File::options()
+        .create(true)
+        .append(true)
+        .open(&options.output)
+    {
+        Ok(file) => file,
+        Err(error) => {
+            println!("info string unable to open {}: {error}", options.output);
+            return;
+        }
+    };
+
+    let output = Mutex::new(BufWriter::new(file));
+    let games_played = AtomicUsize::new(0);
+    let positions_written = AtomicUsize::new(0);
+    let start = Instant::now();
+
+    thread::scope(|scope| {
+        for thread_id in 0..options.threads {
+            // the first threads play the games left over from the division
+            let games = options.games / options.threads
+                + usize::from(thread_id < options.games % options.threads);
+            let seed = options.seed.wrapping_add(thread_id as u128);
+            let (output, games_played, positions_written) =
+                (&output, &games_played, &positions_written);
+            let (nodes, format) = (options.nodes, options.format);
+
+            scope.spawn(move || {
+                let mut uci_options = UciOptions::new();
+                uci_options.set_output(Output::Silent);
+                let mut tt = TranspositionTable::with_capacity(TT_SIZE);
+                let mut rng = Rand64::new(seed);
+                let mut pawn_table = PawnTable::new();
+
+                for _ in 0..games {
+                    let board = loop {
+                        if let Some(board) = random_opening(
+                            &mut rng,
+                            &mut pawn_table,
+                            MIN_PLY..=MAX_PLY,
+                            MAX_OPENING_EVAL,
+                        ) {
+                            break board;
+                        }
+                    };
+
+                    tt.clear();
+                    let (records, outcome) = play_game(board, nodes, &uci_options, &tt);
+
+                    let mut output = output.lock().expect("A thread panicked");
+                    for record in &records {
+                        write(&mut *output, record, outcome, format)
+                            .expect("Unable to write to the output file");
+                    }
+                    drop(output);
+
+                    positions_written.fetch_add(records.len(), Ordering::Relaxed);
+                    let games_played = games_played.fetch_add(1, Ordering::Relaxed) + 1;
+                    if games_played % REPORT_INTERVAL == 0 {
+                        println!(
+                            "info string datagen {games_played} games {} positions {} s",
+                            positions_written.load(Ordering::Relaxed),
+                            start.elapsed().as_secs()
+                        );
+                    }
+                }
+            });
+        }
+    });
+
+    output
+        .into_inner()
+        .expect("A thread panicked")
+        .flush()
+        .expect("Unable to write to the output file");
+    println!(
+        "info string datagen finished: {} games {} positions {} s",
+        games_played.into_inner(),
+        positions_written.into_inner(),
+        start.elapsed().as_secs()
+    );
+}
+
+/// Parses the options of [`datagen()`], returning a description of the
+/// problem if they're invalid.
+fn parse_options<'a, T>(mut tokens: T) -> Result<DatagenOptions, &'static str>
+where
+    T: Iterator<Item = &'a str>,
+{
+    let usage = "Usage: datagen <games> [threads <threads>] [nodes <nodes>] [seed <seed>] \
+                 [output <file>] [format <text|binary>]";
+    let mut options = DatagenOptions {
+        games: tokens.next().and_then(|t| t.parse().ok()).ok_or(usage)?,
+        threads: THREADS,
+        nodes: NODES,
+        seed: 0,
+        output: OUTPUT.to_string(),
+        format: Format::Text,
+    };
+
+    while let Some(token) = tokens.next() {
+        let value = tokens.next().ok_or(usage)?;
+        match token {
+            "threads" => options.threads = value.parse().map_err(|_e| usage)?,
+            "nodes" => options.nodes = value.parse().map_err(|_e| usage)?,
+            "seed" => options.seed = value.parse().map_err(|_e| usage)?,
+            "output" => value.clone_into(&mut options.output),
+            "format" => options.format = value.parse().map_err(|_e| usage)?,
+            _ => return Err(usage),
+        }
+    }
+
+    if options.threads == 0 || options.nodes == 0 {
+        return Err("threads and nodes must be at least 1");
+    }
+    Ok(options)
+}
+
+/// Converts a file of positions written by [`datagen()`] into the other
+/// format.
+///
+/// The options are `<input> <output> [format]`, where `format` is the format
+/// of the output: `binary` (the default) for a text input or `text` for a
+/// binary input. The output is overwritten.
+pub fn convert<'a, T>(mut tokens: T)
+where
+    T: Iterator<Item = &'a str>,
+{
+    let usage = "Usage: convert <input> <output> [text|binary]";
+    let (Some(input), Some(output)) = (tokens.next(), tokens.next()) else {
+        println!("info string {usage}");
+        return;
+    };
+    let Ok(format) = tokens.next().unwrap_or("binary").parse::<Format>() else {
+        println!("info string {usage}");
+        return;
+    };
+
+    match convert_file(input, output, format) {
+        Ok(count) => println!("info string converted {count} positions"),
+        Err(error) => println!("info string unable to convert {input}: {error}"),
+    }
+}
+
+/// Converts the positions of the file `input` into `format` and writes them
+/// to `output`, returning how many positions there were.
+fn convert_file(input: &str, output: &str, format: Format) -> io::Result<usize> {
+    let reader = BufReader::new(File::open(input)?);
+    let mut writer = BufWriter::new(File::create(output)?);
+    let mut count = 0;
+
+    match format {
+        Format::Binary => {
+            for line in reader.lines() {
+                let line = line?;
+                let (record, outcome) = parse_line(&line).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid line \"{line}\""),
+                    )
+                })?;
+                write(&mut writer, &record, outcome, format)?;
+                count += 1;
+            }
+        }
+        Format::Text => {
+            for record in RecordReader::new(reader) {
+                let (record, outcome) = record?;
+                write(&mut writer, &record, outcome, format)?;
+                count += 1;
+            }
+        }
+    }
+
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Parses a `<fen> | <score> | <result>` line.
+fn parse_line(line: &str) -> Option<(Record, Outcome)> {
+    let mut parts = line.split('|').map(str::trim);
+    let board = parts.next()?.parse().ok()?;
+    let score = parts.next()?.parse().ok()?;
+    let outcome = parts.next()?.parse().ok()?;
+    Some((Record { board, score }, outcome))
+}
+
+/// Writes `record` and the outcome of its game to `writer` in `format`.
+fn write<W: Write>(
+    writer: &mut W,
+    record: &Record,
+    outcome: Outcome,
+    format: Format,
+) -> io::Result<()> {
+    match format {
+        Format::Text => writeln!(writer, "{} | {} | {outcome}", record.board, record.score),
+        Format::Binary => write_record(writer, record, outcome),
+    }
+}
+
+/// Plays a game against itself from `board`, searching `nodes` nodes for
+/// each move, and returns the positions worth training on and the result.
+///
+/// The game is adjudicated as a draw by the fifty-move rule, insufficient
+/// material or a threefold repetition.
+fn play_game(
+    mut board: Board,
+    nodes: u64,
+    options: &UciOptions,
+    tt: &TranspositionTable,
+) -> (Vec<Record>, Outcome) {
+    let mut limits = Limits::default();
+    limits.set_nodes(Some(nodes));
+    let mut records = Vec::new();
+    // the keys of the positions before `board` since the last irreversible
+    // move, which are the only ones it could repeat
+    let mut past_zobrists: Vec<Key> = Vec::new();
+
+    let outcome = loop {
+        if board.legal_moves().len() == 0 {
+            break if !board.is_in_check() {
+                Outcome::Draw
+            } else if board.side_to_move() == Side::WHITE {
+                Outcome::BlackWin
+            } else {
+                Outcome::WhiteWin
+            };
+        }
+        let zobrist = board.zobrist();
+        let repetitions = 1 + past_zobrists.iter().filter(|&&key| key == zobrist).count();
+        if board.halfmoves() >= 100 || board.is_insufficient_material() || repetitions >= 3 {
+            break Outcome::Draw;
+        }
+
+        let report = search_sync(board, limits, options, tt, &past_zobrists);
+        let mv = report.best_move();
+        if !board.is_in_check() && board.is_quiet(mv) && !is_mate(report.score) {
+            let score = if board.side_to_move() == Side::WHITE {
+                report.score
+            } else {
+                -report.score
+            };
+            records.push(Record { board, score });
+        }
+
+        past_zobrists.push(zobrist);
+        board.make_move(mv);
+        if board.halfmoves() == 0 {
+            past_zobrists.clear();
+        }
+    };
+
+    (records, outcome)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{play_game, Outcome};
+    use crate::{
+        board::Board,
+        engine::uci::{Output, UciOptions},
+        transposition_table::TranspositionTable,
+    };
+
+    /// Checks that a won position is played out to a win and that the
+    /// positions with a mate score aren't recorded.
+    #[test]
+    fn play_won_game() {
+        let board = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let mut options = UciOptions::new();
+        options.set_output(Output::Silent);
+        let tt = TranspositionTable::with_capacity(1);
+
+        let (records, outcome) = play_game(board, 1000, &options, &tt);
+
+        assert_eq!(outcome, Outcome::WhiteWin, "Rd8 is mate");
+        assert!(records.is_empty(), "The only position has a mate score");
+    }
+}
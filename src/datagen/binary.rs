@@ -0,0 +1,134 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::io::{self, ErrorKind, Read, Write};
+
+use super::{Outcome, Record};
+use crate::board::{packed::PackedBoard, Board};
+
+/// The number of bytes of a record: a [`PackedBoard`], the score as a
+/// little-endian `i16` and the outcome as 1 byte.
+pub const RECORD_SIZE: usize = PackedBoard::SIZE + 3;
+
+/// Reads records written by [`write_record()`] one at a time.
+pub struct RecordReader<R> {
+    /// Where the records are read from.
+    reader: R,
+}
+
+impl<R: Read> Iterator for RecordReader<R> {
+    type Item = io::Result<(Record, Outcome)>;
+
+    /// Reads the next record, returning [`None`] at the end of the input.
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut bytes = [0; RECORD_SIZE];
+        match self.reader.read_exact(&mut bytes) {
+            Ok(()) => Some(decode(&bytes)),
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => None,
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+
+impl<R: Read> RecordReader<R> {
+    /// Creates a reader of the records in `reader`.
+    ///
+    /// `reader` should be buffered: each record is a separate read.
+    pub const fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+/// Writes `record` and the outcome of its game to `writer` as
+/// [`RECORD_SIZE`] bytes.
+pub fn write_record<W: Write>(writer: &mut W, record: &Record, outcome: Outcome) -> io::Result<()> {
+    let mut bytes = [0; RECORD_SIZE];
+    bytes[..PackedBoard::SIZE].copy_from_slice(&record.board.pack().to_bytes());
+    bytes[PackedBoard::SIZE..RECORD_SIZE - 1].copy_from_slice(&record.score.to_le_bytes());
+    bytes[RECORD_SIZE - 1] = match outcome {
+        Outcome::BlackWin => 0,
+        Outcome::Draw => 1,
+        Outcome::WhiteWin => 2,
+    };
+    writer.write_all(&bytes)
+}
+
+/// Decodes the bytes of a record written by [`write_record()`].
+fn decode(bytes: &[u8; RECORD_SIZE]) -> io::Result<(Record, Outcome)> {
+    let invalid = |message: String| io::Error::new(ErrorKind::InvalidData, message);
+
+    let mut board_bytes = [0; PackedBoard::SIZE];
+    board_bytes.copy_from_slice(&bytes[..PackedBoard::SIZE]);
+    let board: Board = PackedBoard::from_bytes(board_bytes)
+        .unpack()
+        .map_err(|error| invalid(error.to_string()))?;
+    let score = i16::from_le_bytes([bytes[PackedBoard::SIZE], bytes[PackedBoard::SIZE + 1]]);
+    let outcome = match bytes[RECORD_SIZE - 1] {
+        0 => Outcome::BlackWin,
+        1 => Outcome::Draw,
+        2 => Outcome::WhiteWin,
+        byte => return Err(invalid(format!("invalid outcome {byte}"))),
+    };
+
+    Ok((Record { board, score }, outcome))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_record, RecordReader, RECORD_SIZE};
+    use crate::{
+        board::Board,
+        datagen::{Outcome, Record},
+    };
+
+    /// Checks that records are read back the same as they were written.
+    #[test]
+    fn round_trip() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w Kq - 0 10",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/8/4k3/8/2p5/8/B2P2K1/8 b - - 37 300",
+        ];
+        let outcomes = [Outcome::WhiteWin, Outcome::Draw, Outcome::BlackWin];
+        let mut bytes = Vec::new();
+        for (index, fen) in fens.iter().enumerate() {
+            let record = Record {
+                board: fen.parse::<Board>().expect("Valid FEN"),
+                score: index as i16 * 150 - 200,
+            };
+            write_record(&mut bytes, &record, outcomes[index % 3]).expect("Writing to a Vec");
+        }
+        assert_eq!(bytes.len(), fens.len() * RECORD_SIZE, "Wrong record size");
+
+        let records = RecordReader::new(bytes.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Valid records");
+        assert_eq!(records.len(), fens.len(), "Wrong number of records");
+        for (index, (record, outcome)) in records.into_iter().enumerate() {
+            assert_eq!(record.board.to_string(), fens[index], "Wrong board");
+            assert_eq!(
+                record.board.zobrist(),
+                fens[index].parse::<Board>().expect("Valid FEN").zobrist(),
+                "Wrong zobrist key"
+            );
+            assert_eq!(record.score, index as i16 * 150 - 200, "Wrong score");
+            assert_eq!(outcome, outcomes[index % 3], "Wrong outcome");
+        }
+    }
+}
@@ -56,11 +56,11 @@ pub struct PieceType(pub u8);
 pub struct Rank(pub u8);
 
 /// A side: 0 or 1 for a regular side or 2 for no side.
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct Side(pub u8);
 
 /// A square: with little-endian rank-file mapping: a1 = 0, b1 = 1, etc.
-#[derive(Clone, Copy, Eq, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd)]
 pub struct Square(pub u8);
 
 /// Most Valuable Victim (MVV): a bonus to capturing a piece, with a higher
@@ -77,6 +77,15 @@ static MVV_BONUS: [Eval; PieceType::TOTAL - 1] = [0, 300, 300, 500, 900];
 static PIECE_CHARS: [char; Piece::TOTAL + 1] = [
     'p', 'P', 'n', 'N', 'b', 'B', 'r', 'R', 'q', 'Q', 'k', 'K', '-',
 ];
+/// An array of Unicode chess symbols associated with each piece on both
+/// sides, with the character '-' at the end to allow conversion from
+/// [`Piece::NONE`].
+///
+/// e.g. `PIECE_SYMBOLS[Piece::WKNIGHT] == '♘'`; `PIECE_SYMBOLS[Piece::BKING]
+/// == '♚'`.
+static PIECE_SYMBOLS: [char; Piece::TOTAL + 1] = [
+    '♟', '♙', '♞', '♘', '♝', '♗', '♜', '♖', '♛', '♕', '♚', '♔', '-',
+];
 /// A bonus to a piece during SEE.
 // this can be tuned
 static SEE_VALUES: [Eval; PieceType::TOTAL + 1] = [100, 300, 300, 500, 900, 0, 0];
@@ -116,6 +125,8 @@ impl MoveType {
     pub const CAPTURES: u8 = 1;
     /// Check evasions: king moves and/or captures of checkers.
     pub const EVASIONS: u8 = 2;
+    /// Every move that isn't generated by [`Self::CAPTURES`].
+    pub const QUIETS: u8 = 3;
 }
 
 /// Piece enumerations for White and Black.
@@ -312,7 +323,7 @@ impl TryFrom<char> for Piece {
             'r' => Ok(Self::BROOK),
             'q' => Ok(Self::BQUEEN),
             'k' => Ok(Self::BKING),
-            _ => Err(ParseError),
+            _ => Err(ParseError::InvalidToken),
         }
     }
 }
@@ -331,7 +342,7 @@ impl TryFrom<char> for PieceType {
             'r' => Ok(Self::ROOK),
             'q' => Ok(Self::QUEEN),
             'k' => Ok(Self::KING),
-            _ => Err(ParseError),
+            _ => Err(ParseError::InvalidToken),
         }
     }
 }
@@ -366,7 +377,7 @@ impl FromStr for Side {
         match string {
             "w" => Ok(Self::WHITE),
             "b" => Ok(Self::BLACK),
-            _ => Err(ParseError),
+            _ => Err(ParseError::InvalidToken),
         }
     }
 }
@@ -405,7 +416,8 @@ impl FromStr for Square {
 
     /// Converts a string representation of a square (e.g. "e4") into a
     /// [`Square`]. Will return `Ok(Self)` if the square is valid,
-    /// `Ok(Self::NONE)` if the square is "-" and `Err(ParseError)` otherwise.
+    /// `Ok(Self::NONE)` if the square is "-" and
+    /// `Err(ParseError::InvalidToken)` otherwise.
     fn from_str(string: &str) -> Result<Self, Self::Err> {
         if string == "-" {
             return Ok(Self::NONE);
@@ -414,18 +426,18 @@ impl FromStr for Square {
         let mut square = 0;
         let mut iter = string.as_bytes().iter();
 
-        let file = iter.next().ok_or(ParseError)?;
+        let file = iter.next().ok_or(ParseError::InvalidToken)?;
         if (b'a'..=b'h').contains(file) {
             square += file - b'a';
         } else {
-            return Err(ParseError);
+            return Err(ParseError::InvalidToken);
         }
 
-        let rank = iter.next().ok_or(ParseError)?;
+        let rank = iter.next().ok_or(ParseError::InvalidToken)?;
         if (b'1'..=b'8').contains(rank) {
             square += (rank - b'1') * 8;
         } else {
-            return Err(ParseError);
+            return Err(ParseError::InvalidToken);
         }
 
         Ok(Self(square))
@@ -450,6 +462,12 @@ impl Piece {
     pub const fn to_index(self) -> usize {
         self.0 as usize
     }
+
+    /// Converts the piece into its Unicode chess symbol: '♙' for White pawn,
+    /// '♚' for Black king, etc.
+    pub fn to_unicode(self) -> char {
+        *get_unchecked(&PIECE_SYMBOLS, self.to_index())
+    }
 }
 
 impl PieceType {
@@ -467,6 +485,12 @@ impl PieceType {
     pub fn see_bonus(self) -> Eval {
         *get_unchecked(&SEE_VALUES, self.to_index())
     }
+
+    /// Converts the piece type into its Unicode chess symbol, using the
+    /// symbols of the Black pieces: '♟' for pawn to '♚' for king.
+    pub fn to_unicode(self) -> char {
+        Piece::from_piecetype(self, Side::BLACK).to_unicode()
+    }
 }
 
 impl Side {
@@ -497,3 +521,41 @@ impl Square {
         self.0 as usize
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Piece, PieceType};
+
+    /// Checks that each piece is converted to the right Unicode symbol.
+    #[test]
+    fn unicode_symbols() {
+        let expected = [
+            (Piece::WPAWN, '♙'),
+            (Piece::WKNIGHT, '♘'),
+            (Piece::WBISHOP, '♗'),
+            (Piece::WROOK, '♖'),
+            (Piece::WQUEEN, '♕'),
+            (Piece::WKING, '♔'),
+            (Piece::BPAWN, '♟'),
+            (Piece::BKNIGHT, '♞'),
+            (Piece::BBISHOP, '♝'),
+            (Piece::BROOK, '♜'),
+            (Piece::BQUEEN, '♛'),
+            (Piece::BKING, '♚'),
+            (Piece::NONE, '-'),
+        ];
+        for (piece, symbol) in expected {
+            assert_eq!(
+                piece.to_unicode(),
+                symbol,
+                "Wrong symbol for {}",
+                char::from(piece)
+            );
+        }
+        assert_eq!(
+            PieceType::KNIGHT.to_unicode(),
+            '♞',
+            "Wrong piece type symbol"
+        );
+    }
+}
@@ -18,26 +18,30 @@
 
 use std::{
     io::stdin,
+    iter::once,
+    ops::ControlFlow,
     str::FromStr,
     sync::{
         mpsc::{channel, Receiver},
         Mutex,
     },
-    thread::spawn,
+    thread::{spawn, JoinHandle},
     time::{Duration, Instant},
 };
 
 use crate::{
     board::{Board, Key},
-    defs::{MoveType, PieceType, Side, Square},
-    movegen::generate_moves,
-    perft::perft,
-    search::{iterative_deepening, Depth, Limits},
+    defs::{PieceType, Side, Square},
+    perft::{hashed_perft, perft, PerftTable},
+    search::{iterative_deepening, Depth, Limits, SearchStatus},
     transposition_table::TranspositionTable,
     util::Stack,
 };
-use uci::UciOptions;
+use history::GameHistory;
+use uci::{Opponent, UciOptions};
 
+/// The moves of the game so far, for taking them back.
+mod history;
 /// Items for handling UCI input.
 pub mod uci;
 
@@ -54,12 +58,19 @@ pub struct Engine {
     options: UciOptions,
     /// A receiver to receive UCI commands from.
     uci_rx: Mutex<Receiver<String>>,
+    /// The thread that reads UCI commands from stdin, if there is one.
+    ///
+    /// It finishes after forwarding `quit` or when stdin is closed.
+    input_thread: Option<JoinHandle<()>>,
     /// A stack of zobrist hashes of previous board states, beginning from the
     /// initial `position fen ...` command.
     ///
     /// The first (bottom) element is the initial board and the top element is
     /// the current board.
     past_zobrists: ZobristStack,
+    /// The moves of the current position and the boards they were made on,
+    /// beginning from the initial `position fen ...` command.
+    history: GameHistory,
     /// A hash table of previously-encountered positions.
     tt: TranspositionTable,
 }
@@ -72,36 +83,75 @@ impl Engine {
     pub fn new() -> Self {
         let (tx, rx) = channel();
 
-        spawn(move || {
+        let input_thread = spawn(move || {
             let stdin = stdin();
 
             for command in stdin.lines() {
                 let command = command.expect("Error while reading from stdin");
+                let is_quit = command.trim() == "quit";
                 tx.send(command).expect(
                     "It's not possible for this thread to exit later than the main thread.",
                 );
+                // nothing after `quit` will be read, so this thread can be
+                // joined instead of blocking on stdin forever
+                if is_quit {
+                    break;
+                }
             }
         });
 
         let options = UciOptions::new();
         Self {
             board: Board::new(),
-            options,
             uci_rx: Mutex::new(rx),
+            input_thread: Some(input_thread),
             past_zobrists: Stack::new(),
+            history: GameHistory::new(),
             tt: TranspositionTable::with_capacity(options.hash()),
+            options,
         }
     }
 
     /// Interprets and executes the `go` command.
-    pub fn go<'a, T>(&mut self, mut options: T)
+    ///
+    /// Returns [`ControlFlow::Break`] if the engine was told to quit during
+    /// the search.
+    pub fn go<'a, T>(&mut self, options: T) -> ControlFlow<()>
     where
         T: Iterator<Item = &'a str>,
     {
         let start = Instant::now();
+        let mut options = options.peekable();
         let mut limits = Limits::default();
+        let mut search_moves = Vec::new();
+        let mut is_ponder = false;
 
         while let Some(token) = options.next() {
+            // these don't take a value, so they mustn't consume the next token
+            match token {
+                "infinite" => {
+                    limits.set_infinite();
+                    continue;
+                }
+                "ponder" => {
+                    is_ponder = true;
+                    continue;
+                }
+                "searchmoves" => {
+                    // every legal move that follows is a search move
+                    let legal_moves = self.board().legal_moves().collect::<Vec<_>>();
+                    while let Some(&mv) = options
+                        .peek()
+                        .and_then(|token| legal_moves.iter().find(|mv| mv.to_string() == *token))
+                    {
+                        search_moves.push(mv);
+                        options.next();
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+
             let next = options.next();
 
             match token {
@@ -117,36 +167,53 @@ impl Engine {
                 "binc" if self.board().side_to_move() == Side::BLACK => {
                     limits.set_inc(parse_time(next));
                 }
-                "movestogo" => limits.set_moves_to_go(parse_into_nonzero_option(next)),
+                "movestogo" => limits.set_moves_to_go(parse_option(next)),
                 "depth" => limits.set_depth(parse_into_nonzero_option(next)),
                 "nodes" => limits.set_nodes(parse_into_nonzero_option(next)),
                 "movetime" => limits.set_movetime(parse_time(next)),
-                "infinite" => limits.set_infinite(),
+                "mate" => limits.set_mate(parse_into_nonzero_option(next)),
                 "perft" => {
                     if let Some(depth) = parse_into_nonzero_option(next) {
-                        perft::<true, true>(self.board(), depth);
+                        if options.next() == Some("hash") {
+                            let mut table = PerftTable::with_capacity(self.options().hash());
+                            hashed_perft::<true, true>(self.board(), depth, &mut table);
+                        } else {
+                            perft::<true, true>(self.board(), depth);
+                        }
                     }
-                    return;
+                    return ControlFlow::Continue(());
                 }
                 _ => (),
             }
         }
 
+        let is_pondering = is_ponder && self.options().ponder();
+        if is_ponder && !is_pondering {
+            println!("info string Ponder is disabled: searching normally");
+        }
+
         let board = *self.board();
-        let options = *self.options();
         let uci_rx = self.uci_rx();
         let mut past_zobrists = self.past_zobrists().clone();
         let tt = self.tt();
 
-        iterative_deepening(
+        let report = iterative_deepening(
             board,
             start,
             limits,
+            search_moves,
+            is_pondering,
             uci_rx,
             &mut past_zobrists,
-            options,
+            self.options(),
             tt,
         );
+
+        if report.status == SearchStatus::Quit {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
     }
 
     /// Sets the board to a position specified by the `position` command.
@@ -158,31 +225,23 @@ impl Engine {
         T: Iterator<Item = &'a str>,
     {
         let mut board = Board::new();
-        let mut zobrists = Stack::new();
+        let mut history = GameHistory::new();
 
         match tokens.next() {
             Some("startpos") => board.set_startpos(),
             Some("fen") => {
-                // Creating a new `String` is annoying, but probably not too
-                // expensive, considering this only happens a few tens of times
-                // per game.
-                let mut fen_str = String::with_capacity(128);
-
-                // The FEN string should have exactly 6 tokens - more or fewer
-                // should raise an error later or now respectively.
-                for _ in 0..6 {
-                    let Some(token) = tokens.next() else {
-                        return;
-                    };
-                    fen_str.push_str(token);
-                    fen_str.push(' ');
-                }
-
-                if let Ok(b) = fen_str.parse() {
-                    board = b;
-                } else {
+                let Some(b) = parse_fen(&mut tokens) else {
                     return;
-                }
+                };
+                board = b;
+            }
+            // some GUIs leave out the `fen` keyword, so if the first token
+            // looks like the board part of a FEN string, assume that it is
+            Some(placement) if placement.contains('/') => {
+                let Some(b) = parse_fen(once(placement).chain(&mut tokens)) else {
+                    return;
+                };
+                board = b;
             }
             _ => return,
         };
@@ -193,18 +252,17 @@ impl Engine {
                 return;
             }
         }
-        zobrists.push(board.zobrist());
 
         // if there are no moves to begin with, this loop will just be skipped
         for mv in tokens {
-            let mut moves = generate_moves::<{ MoveType::ALL }>(&board);
-
             let Some(start) = mv.get(0..=1) else {
                 return;
             };
             let Ok(start) = Square::from_str(start) else {
                 return;
             };
+            let mut moves = board.legal_moves_from(start);
+
             let Some(end) = mv.get(2..=3) else {
                 return;
             };
@@ -228,20 +286,27 @@ impl Engine {
                 return;
             };
 
+            let previous = board;
             if !board.make_move(mv) {
                 return;
             }
-
-            // we can safely discard all moves before an irreversible move
-            if board.halfmoves() == 0 {
-                zobrists.clear();
-            }
-
-            zobrists.push(board.zobrist());
+            history.push(previous, mv);
         }
 
+        *self.past_zobrists_mut() = history.zobrists(&board);
         *self.board_mut() = board;
-        *self.past_zobrists_mut() = zobrists;
+        self.history = history;
+    }
+
+    /// Takes back the last move of the current position, if there is one.
+    pub fn takeback(&mut self) {
+        let Some((board, mv)) = self.history.pop() else {
+            println!("info string no moves to take back");
+            return;
+        };
+        *self.past_zobrists_mut() = self.history.zobrists(&board);
+        *self.board_mut() = board;
+        println!("info string took back {mv}");
     }
 
     /// Sets a UCI option from a `setoption` command.
@@ -262,8 +327,18 @@ impl Engine {
                     return;
                 }
 
-                if let Some(d) = parse_option(tokens.next()) {
-                    self.options_mut().set_move_overhead(d);
+                let Some(millis) = parse_option::<u64>(tokens.next()) else {
+                    return;
+                };
+
+                if self.options_mut().set_move_overhead(millis) {
+                    let range = UciOptions::MOVE_OVERHEAD_RANGE;
+                    println!(
+                        "info string Move Overhead {millis} is out of the range {}-{} ms; clamped to {} ms",
+                        range.start(),
+                        range.end(),
+                        self.options().move_overhead().as_millis(),
+                    );
                 }
             }
             Some("Threads") => {
@@ -291,6 +366,101 @@ impl Engine {
                 }
                 self.tt_mut().clear();
             }
+            Some("Contempt") => {
+                if tokens.next() != Some("value") {
+                    return;
+                }
+
+                if let Some(c) = parse_option(tokens.next()) {
+                    self.options_mut().set_contempt(c);
+                }
+            }
+            Some("UCI_LimitStrength") => {
+                if tokens.next() != Some("value") {
+                    return;
+                }
+
+                if let Some(l) = parse_option(tokens.next()) {
+                    self.options_mut().set_limit_strength(l);
+                }
+            }
+            Some("UCI_Elo") => {
+                if tokens.next() != Some("value") {
+                    return;
+                }
+
+                if let Some(e) = parse_option(tokens.next()) {
+                    self.options_mut().set_elo(e);
+                }
+            }
+            Some("UCI_AnalyseMode") => {
+                if tokens.next() != Some("value") {
+                    return;
+                }
+
+                if let Some(a) = parse_option(tokens.next()) {
+                    self.options_mut().set_analyse_mode(a);
+                }
+            }
+            Some("NormalizeScore") => {
+                if tokens.next() != Some("value") {
+                    return;
+                }
+
+                if let Some(n) = parse_option(tokens.next()) {
+                    self.options_mut().set_normalize_score(n);
+                }
+            }
+            Some("UCI_ShowWDL") => {
+                if tokens.next() != Some("value") {
+                    return;
+                }
+
+                if let Some(s) = parse_option(tokens.next()) {
+                    self.options_mut().set_show_wdl(s);
+                }
+            }
+            Some("UCI_Opponent") => {
+                if tokens.next() != Some("value") {
+                    return;
+                }
+
+                let value = tokens.collect::<Vec<_>>().join(" ");
+                if let Ok(opponent) = value.parse::<Opponent>() {
+                    self.options_mut().set_opponent(opponent);
+                }
+                if let Some(opponent) = self.options().opponent() {
+                    println!("info string Opponent: {opponent}");
+                }
+            }
+            Some("MultiPV") => {
+                if tokens.next() != Some("value") {
+                    return;
+                }
+
+                if let Some(m) = parse_option(tokens.next()) {
+                    self.options_mut().set_multi_pv(m);
+                }
+            }
+            Some("Ponder") => {
+                if tokens.next() != Some("value") {
+                    return;
+                }
+
+                if let Some(p) = parse_option(tokens.next()) {
+                    self.options_mut().set_ponder(p);
+                }
+            }
+            #[cfg(feature = "tune")]
+            Some(name) => {
+                if tokens.next() != Some("value") {
+                    return;
+                }
+
+                if let Some(value) = parse_option(tokens.next()) {
+                    self.options_mut().set_search_param(name, value);
+                }
+            }
             _ => (),
         }
     }
@@ -302,7 +472,9 @@ impl Engine {
         self.past_zobrists_mut().clear();
         let board_zobrist = self.board().zobrist();
         self.past_zobrists_mut().push(board_zobrist);
-        self.tt_mut().clear();
+        self.history = GameHistory::new();
+        // clearing a large table takes a while, so just make it stale
+        self.tt().new_generation();
     }
 
     /// Returns a reference to the board.
@@ -353,6 +525,38 @@ impl Engine {
     }
 }
 
+/// Parses the next 6 tokens of `tokens` as a FEN string and checks that the
+/// position is valid.
+///
+/// Returns [`None`] if there are too few tokens or the FEN string or the
+/// position is invalid, printing why if it's the latter.
+fn parse_fen<'a, T>(mut tokens: T) -> Option<Board>
+where
+    T: Iterator<Item = &'a str>,
+{
+    // Creating a new `String` is annoying, but probably not too expensive,
+    // considering this only happens a few tens of times per game.
+    let mut fen_str = String::with_capacity(128);
+
+    // The FEN string should have exactly 6 tokens - more or fewer should
+    // raise an error later or now respectively.
+    for _ in 0..6 {
+        fen_str.push_str(tokens.next()?);
+        fen_str.push(' ');
+    }
+
+    match fen_str
+        .parse::<Board>()
+        .and_then(|board| board.validate().map(|()| board))
+    {
+        Ok(board) => Some(board),
+        Err(error) => {
+            println!("info string invalid FEN: {error}");
+            None
+        }
+    }
+}
+
 /// Parses an `Option<&str>` into an `Option<T>`.
 ///
 /// If the parse fails, it will return [`None`].
@@ -383,3 +587,317 @@ fn parse_time(num: Option<&str>) -> Option<Duration> {
         .map(|t| unsafe { u64::try_from(t).unwrap_unchecked()})
         .map(Duration::from_millis)
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            mpsc::{channel, Sender},
+            Mutex,
+        },
+        thread::spawn,
+        time::Duration,
+    };
+
+    use super::{
+        uci::{Output, UciOptions},
+        Engine, GameHistory,
+    };
+    #[cfg(feature = "tune")]
+    use crate::lookups::BaseReductions;
+    use crate::{board::Board, transposition_table::TranspositionTable, util::Stack};
+
+    /// Creates an [`Engine`] without spawning a thread to read from stdin.
+    fn engine() -> Engine {
+        engine_with_input().0
+    }
+
+    /// Creates an [`Engine`] that reads its commands from the returned
+    /// sender instead of stdin.
+    fn engine_with_input() -> (Engine, Sender<String>) {
+        let (tx, rx) = channel();
+        let engine = Engine {
+            board: Board::new(),
+            options: UciOptions::new(),
+            uci_rx: Mutex::new(rx),
+            input_thread: None,
+            past_zobrists: Stack::new(),
+            history: GameHistory::new(),
+            tt: TranspositionTable::with_capacity(1),
+        };
+        (engine, tx)
+    }
+
+    /// Checks that `quit` during a search still writes the best move.
+    #[test]
+    fn go_then_quit() {
+        let (mut engine, tx) = engine_with_input();
+        let (output, lines) = Output::buffer();
+        engine.options_mut().set_output(output);
+        engine.set_position("startpos".split_whitespace());
+
+        tx.send(String::from("quit"))
+            .expect("The receiver is alive");
+        assert!(
+            engine.go("infinite".split_whitespace()).is_break(),
+            "The engine should quit"
+        );
+        assert!(
+            lines
+                .lock()
+                .expect("The search has finished")
+                .iter()
+                .any(|line| line.starts_with("bestmove ")),
+            "The best move should have been written"
+        );
+    }
+
+    /// Checks that setting a late move reduction parameter rebuilds the base
+    /// reductions.
+    #[test]
+    #[cfg(feature = "tune")]
+    fn lmr_option_rebuilds_reductions() {
+        let mut engine = engine();
+        let (depth, total_moves) = (10, 30);
+        let default = engine.options().base_reductions().get(depth, total_moves);
+
+        engine.set_option("name LmrBase value 100".split_whitespace());
+
+        let expected = BaseReductions::new(1.0, 2.0).get(depth, total_moves);
+        assert_ne!(expected, default, "The base should change the reduction");
+        assert_eq!(
+            engine.options().base_reductions().get(depth, total_moves),
+            expected,
+            "The reductions weren't rebuilt"
+        );
+    }
+
+    /// Checks that `isready` is answered through the engine's output.
+    #[test]
+    fn isready() {
+        let (mut engine, tx) = engine_with_input();
+        let (output, lines) = Output::buffer();
+        engine.options_mut().set_output(output);
+
+        for command in ["isready", "quit"] {
+            tx.send(String::from(command))
+                .expect("The receiver is alive");
+        }
+        engine.main_loop().expect("The sender is alive");
+
+        assert_eq!(
+            *lines.lock().expect("The engine has quit"),
+            ["readyok"],
+            "Missing readyok"
+        );
+    }
+
+    /// Checks that `key` writes the keys of the current position.
+    #[test]
+    fn key_command() {
+        let (mut engine, tx) = engine_with_input();
+        let (output, lines) = Output::buffer();
+        engine.options_mut().set_output(output);
+
+        for command in ["position startpos moves e2e4", "key", "quit"] {
+            tx.send(String::from(command))
+                .expect("The receiver is alive");
+        }
+        engine.main_loop().expect("The sender is alive");
+
+        let board = engine.board();
+        let expected = format!(
+            "key {:016x} pawn key {:016x} polyglot key 823c9b50fd114196",
+            board.zobrist(),
+            board.pawn_zobrist()
+        );
+        assert_eq!(
+            *lines.lock().expect("The engine has quit"),
+            [expected],
+            "Key line"
+        );
+    }
+
+    /// Checks that the move overhead is a whole number of milliseconds and is
+    /// clamped when out of range.
+    #[test]
+    fn move_overhead() {
+        let mut engine = engine();
+
+        engine.set_option("name Move Overhead value 25".split_whitespace());
+        assert_eq!(
+            engine.options().move_overhead(),
+            Duration::from_millis(25),
+            "Overhead should be set"
+        );
+        // a spin option can't be fractional
+        engine.set_option("name Move Overhead value 12.5".split_whitespace());
+        assert_eq!(
+            engine.options().move_overhead(),
+            Duration::from_millis(25),
+            "Fractional overhead should be ignored"
+        );
+
+        assert!(
+            engine.options_mut().set_move_overhead(1_000_000),
+            "Overhead should have been clamped"
+        );
+        assert_eq!(
+            engine.options().move_overhead(),
+            Duration::from_millis(*UciOptions::MOVE_OVERHEAD_RANGE.end()),
+            "Overhead should be clamped to the maximum"
+        );
+        assert!(
+            !engine.options_mut().set_move_overhead(30),
+            "Overhead should not have been clamped"
+        );
+    }
+
+    /// Checks that `go ponder` only ponders if the Ponder option is set.
+    #[test]
+    fn ponder_option() {
+        let (mut engine, tx) = engine_with_input();
+        assert!(
+            !engine.options().ponder(),
+            "Ponder should be off by default"
+        );
+        engine.set_position("startpos".split_whitespace());
+
+        // a pondering search would only stop when told to, so it's given a
+        // deadline to stop on its own
+        let (result_tx, result_rx) = channel();
+        let search = spawn(move || {
+            let flow = engine.go("ponder depth 1".split_whitespace());
+            result_tx.send(flow).expect("The receiver is alive");
+            engine
+        });
+        let flow = result_rx.recv_timeout(Duration::from_secs(10));
+        // let the search finish either way so the thread can be joined
+        drop(tx.send(String::from("stop")));
+        let mut engine = search.join().expect("The search panicked");
+        assert!(
+            flow.is_ok_and(|flow| flow.is_continue()),
+            "Search should finish without pondering"
+        );
+
+        engine.set_option("name Ponder value true".split_whitespace());
+        assert!(engine.options().ponder(), "Ponder should be on");
+    }
+
+    /// Checks that `MultiPV` is clamped and that a search with several lines
+    /// finishes.
+    #[test]
+    fn multi_pv_option() {
+        let mut engine = engine();
+        engine.set_position("startpos".split_whitespace());
+
+        engine.set_option("name MultiPV value 0".split_whitespace());
+        assert_eq!(engine.options().multi_pv(), 1, "MultiPV should be clamped");
+
+        engine.set_option("name MultiPV value 3".split_whitespace());
+        assert_eq!(engine.options().multi_pv(), 3, "MultiPV should be set");
+        assert!(
+            engine.go("depth 2".split_whitespace()).is_continue(),
+            "Search should finish"
+        );
+    }
+
+    /// Checks that `searchmoves` only consumes legal moves and that it
+    /// combines with `MultiPV`, which can't report more lines than there are
+    /// search moves.
+    #[test]
+    fn searchmoves() {
+        let mut engine = engine();
+        engine.set_position("startpos".split_whitespace());
+        engine.set_option("name MultiPV value 3".split_whitespace());
+
+        // the search would never finish if `depth 2` were parsed as moves
+        assert!(
+            engine
+                .go("searchmoves e2e4 d2d4 depth 2".split_whitespace())
+                .is_continue(),
+            "Search should finish"
+        );
+    }
+
+    /// Checks that a search with debug diagnostics on finishes and writes
+    /// them.
+    #[test]
+    fn debug_search() {
+        let mut engine = engine();
+        let (output, lines) = Output::buffer();
+        engine.options_mut().set_output(output);
+        engine.set_position("startpos".split_whitespace());
+        assert!(!engine.options().debug(), "Debug should be off by default");
+
+        engine.options_mut().set_debug(true);
+        assert!(
+            engine
+                .go("wtime 1000 btime 1000".split_whitespace())
+                .is_continue(),
+            "Search should finish"
+        );
+
+        let lines = lines.lock().expect("The search has finished").clone();
+        for diagnostic in [
+            "info string time bounds: ",
+            "info string depth 1 hashfull ",
+            "info string pruning: ",
+        ] {
+            assert!(
+                lines.iter().any(|line| line.starts_with(diagnostic)),
+                "Missing \"{diagnostic}\""
+            );
+        }
+    }
+
+    /// Checks that `UCI_Opponent` is parsed and stored.
+    #[test]
+    fn uci_opponent() {
+        let mut engine = engine();
+
+        engine
+            .set_option("name UCI_Opponent value GM 2800 human Magnus Carlsen".split_whitespace());
+        let opponent = engine.options().opponent().expect("Opponent was set");
+        assert_eq!(opponent.title.as_deref(), Some("GM"), "Title");
+        assert_eq!(opponent.rating, Some(2800), "Rating");
+        assert!(!opponent.is_computer, "Human");
+        assert_eq!(opponent.name, "Magnus Carlsen", "Name");
+
+        engine.set_option("name UCI_Opponent value none none computer Crab".split_whitespace());
+        let opponent = engine.options().opponent().expect("Opponent was set");
+        assert_eq!(opponent.to_string(), "none none computer Crab", "Display");
+    }
+
+    /// Checks that `position` accepts a FEN string without the `fen` keyword.
+    #[test]
+    fn position_without_fen_keyword() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let expected = "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let mut with_keyword = engine();
+        let mut without_keyword = engine();
+
+        with_keyword.set_position(format!("fen {fen} moves e1g1").split_whitespace());
+        without_keyword.set_position(format!("{fen} moves e1g1").split_whitespace());
+
+        assert_eq!(
+            with_keyword.board().to_string(),
+            expected.to_string(),
+            "Position with the keyword"
+        );
+        assert_eq!(
+            without_keyword.board().to_string(),
+            expected.to_string(),
+            "Position without the keyword"
+        );
+
+        without_keyword.set_position("startpos".split_whitespace());
+        assert_eq!(
+            without_keyword.board().to_string(),
+            Board::default().to_string(),
+            "startpos"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::iter::once;
+
+use super::ZobristStack;
+use crate::{board::Board, movegen::Move};
+
+/// The moves of the game so far and the boards they were made on.
+///
+/// [`Board`] is copy-make, so the only way to undo a move is to go back to a
+/// copy of the board from before it.
+#[derive(Clone, Default)]
+pub struct GameHistory {
+    /// Each move of the game, along with the board before it was made.
+    entries: Vec<(Board, Move)>,
+}
+
+impl GameHistory {
+    /// Creates an empty [`GameHistory`].
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Records that `mv` was made on `board`.
+    pub fn push(&mut self, board: Board, mv: Move) {
+        self.entries.push((board, mv));
+    }
+
+    /// Removes the last move, returning it along with the board from before
+    /// it, or returns [`None`] if there are no moves.
+    pub fn pop(&mut self) -> Option<(Board, Move)> {
+        self.entries.pop()
+    }
+
+    /// Calculates the zobrist keys of every board of the game that can still
+    /// be repeated, ending with `current`.
+    ///
+    /// Every board before an irreversible move is left out, as is every board
+    /// more than 100 halfmoves ago: the fifty-move rule would end the game
+    /// before it could be repeated. This leaves most of the stack for the
+    /// search.
+    pub fn zobrists(&self, current: &Board) -> ZobristStack {
+        let boards = self
+            .entries
+            .iter()
+            .map(|entry| &entry.0)
+            .chain(once(current))
+            .collect::<Vec<_>>();
+        let first = boards
+            .iter()
+            .rposition(|board| board.halfmoves() == 0)
+            .unwrap_or(0)
+            .max(boards.len().saturating_sub(101));
+        boards
+            .iter()
+            .skip(first)
+            .map(|board| board.zobrist())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GameHistory;
+    use crate::{board::Board, defs::Square};
+
+    /// Checks that popping a move gives back the board from before it and
+    /// that the zobrist keys before an irreversible move are left out.
+    #[test]
+    fn pop_and_zobrists() {
+        let mut history = GameHistory::new();
+        let start = Board::default();
+        let mut board = start;
+        for (from, to) in [
+            (Square::G1, Square::F3),
+            (Square::G8, Square::F6),
+            (Square::E2, Square::E4),
+        ] {
+            let mv = board
+                .legal_moves_from(from)
+                .move_with(from, to)
+                .expect("Legal move");
+            history.push(board, mv);
+            assert!(board.make_move(mv), "Legal move");
+        }
+
+        assert_eq!(history.zobrists(&board).len(), 1, "Pawn move");
+        let (previous, _) = history.pop().expect("3 moves were made");
+        assert_eq!(history.zobrists(&previous).len(), 3, "Knight moves");
+        assert_eq!(
+            previous.zobrist(),
+            history.zobrists(&previous).peek(),
+            "Current board"
+        );
+    }
+
+    /// Checks that boards more than 100 halfmoves ago are left out.
+    #[test]
+    fn zobrists_limit() {
+        let mut history = GameHistory::new();
+        let mut board = "8/8/3k4/8/8/3K4/8/8 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        // the kings shuffle back and forth without an irreversible move
+        for (from, to) in [
+            (Square::D3, Square::E3),
+            (Square::D6, Square::E6),
+            (Square::E3, Square::D3),
+            (Square::E6, Square::D6),
+        ]
+        .into_iter()
+        .cycle()
+        .take(120)
+        {
+            let mv = board
+                .legal_moves_from(from)
+                .move_with(from, to)
+                .expect("Legal move");
+            history.push(board, mv);
+            assert!(board.make_move(mv), "Legal move");
+        }
+
+        let zobrists = history.zobrists(&board);
+        assert_eq!(zobrists.len(), 101, "Too many boards");
+        assert_eq!(zobrists.peek(), board.zobrist(), "Current board");
+    }
+}
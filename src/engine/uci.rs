@@ -16,13 +16,37 @@
  * Crab. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{ops::RangeInclusive, process::exit, sync::mpsc::RecvError, time::Duration};
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{stdout, Write},
+    ops::{ControlFlow, RangeInclusive},
+    str::FromStr,
+    sync::mpsc::RecvError,
+    time::Duration,
+};
+
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
 
 use super::Engine;
-use crate::{bench::bench, defs::PieceType, movegen::magic::find_magics};
+use crate::{
+    bench::bench,
+    defs::{File, PieceType, Rank, Square},
+    epd::epd,
+    error::ParseError,
+    evaluation::{psqt_value, Eval, EvalTrace},
+    fen_generation::genfens,
+    lookups::BaseReductions,
+    movegen::{magic::find_magics, MAX_LEGAL_MOVES},
+    perft::perft_bench,
+    search::{params::SearchParameters, strength::Strength},
+    selftest::selftest,
+};
 
 /// The UCI options this engine supports.
-#[derive(Clone, Copy)]
+// the bools are all independent settings
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone)]
 pub struct UciOptions {
     /// The overhead of sending a move from the engine to the GUI.
     move_overhead: Duration,
@@ -30,6 +54,67 @@ pub struct UciOptions {
     threads: usize,
     /// How large the transposition table should be, in MiB.
     hash: usize,
+    /// Whether or not the GUI may ask the engine to ponder.
+    ponder: bool,
+    /// Whether or not extra diagnostics should be printed, as set by the
+    /// `debug` command.
+    debug: bool,
+    /// Whether or not the helper threads should be kept from affecting the
+    /// result and node count of the main thread, as set by `bench`.
+    deterministic: bool,
+    /// Where the search writes its output.
+    output: Output,
+    /// How many of the best lines should be searched and reported.
+    multi_pv: usize,
+    /// Whether or not the GUI is analysing rather than playing a game.
+    analyse_mode: bool,
+    /// Whether or not the chances of a win, draw and loss should be reported
+    /// with the score.
+    show_wdl: bool,
+    /// Whether or not the reported score should be normalized so that 100
+    /// centipawns is an even chance of winning.
+    normalize_score: bool,
+    /// Whether or not the strength of the engine is limited to
+    /// [`elo`](Self::elo).
+    limit_strength: bool,
+    /// The Elo the engine plays at if its strength is limited.
+    elo: u16,
+    /// How much the engine dislikes a draw, in centipawns.
+    contempt: Eval,
+    /// The tunable parameters of the search.
+    search_params: SearchParameters,
+    /// The base late move reductions of
+    /// [`search_params`](Self::search_params), which are only rebuilt when
+    /// the parameters change.
+    base_reductions: BaseReductions,
+    /// The opponent, if the GUI has told us who it is.
+    opponent: Option<Opponent>,
+}
+
+/// Where the output of a search is written.
+#[derive(Clone)]
+pub enum Output {
+    /// Standard output, for the GUI.
+    Stdout,
+    /// Nowhere, for searches the engine runs for itself, such as `datagen`.
+    Silent,
+    /// A buffer that can be read back, one line at a time.
+    #[cfg(test)]
+    Buffer(Arc<Mutex<Vec<String>>>),
+}
+
+/// The opponent given by the `UCI_Opponent` option.
+#[derive(Clone)]
+pub struct Opponent {
+    /// The title of the opponent, such as "GM", or [`None`] if they don't
+    /// have one.
+    pub title: Option<String>,
+    /// The rating of the opponent, or [`None`] if it's unknown.
+    pub rating: Option<u16>,
+    /// Whether the opponent is a computer or a human.
+    pub is_computer: bool,
+    /// The name of the opponent.
+    pub name: String,
 }
 
 /// The name of the author of this engine.
@@ -42,20 +127,97 @@ const ID_VERSION: &str = env!("CARGO_PKG_VERSION");
 #[allow(clippy::missing_docs_in_private_items)]
 impl UciOptions {
     /// The range that the move overhead can take, in milliseconds.
-    pub const MOVE_OVERHEAD_RANGE: RangeInclusive<u64> = (0..=1_000);
+    ///
+    /// The maximum is large to allow for slow connections during network
+    /// play.
+    pub const MOVE_OVERHEAD_RANGE: RangeInclusive<u64> = (0..=10_000);
     /// The range that the number of threads can take.
-    pub const THREAD_RANGE: RangeInclusive<usize> = (1..=1);
+    pub const THREAD_RANGE: RangeInclusive<usize> = (1..=256);
+    /// The range that the number of lines in `MultiPV` mode can take.
+    pub const MULTI_PV_RANGE: RangeInclusive<usize> = (1..=MAX_LEGAL_MOVES);
+    /// The range that the contempt can take, in centipawns.
+    pub const CONTEMPT_RANGE: RangeInclusive<Eval> = (-200..=200);
+    /// The range that the Elo can take when the strength is limited.
+    pub const ELO_RANGE: RangeInclusive<u16> = (Strength::MIN_ELO..=Strength::MAX_ELO);
     /// The range that the hash size can take.
     // hardware limit: 48-bit pointers
     pub const HASH_RANGE: RangeInclusive<usize> = (1..=2_usize.pow(48) / (1024 * 1024));
 }
 
+impl Display for Opponent {
+    /// Displays the opponent in the same format as the value of
+    /// `UCI_Opponent`: `<title> <rating> <computer|human> <name>`, with
+    /// "none" for a missing title or rating.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let title = self.title.as_deref().unwrap_or("none");
+        let kind = if self.is_computer {
+            "computer"
+        } else {
+            "human"
+        };
+        write!(f, "{title} ")?;
+        match self.rating {
+            Some(rating) => write!(f, "{rating}")?,
+            None => write!(f, "none")?,
+        }
+        write!(f, " {kind} {}", self.name)
+    }
+}
+
+impl FromStr for Opponent {
+    type Err = ParseError;
+
+    /// Parses the value of `UCI_Opponent`: `<title> <rating> <computer|human>
+    /// <name>`, where the title and rating can be "none" and the name can
+    /// contain spaces.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut tokens = string.split_whitespace();
+
+        let title = match tokens.next().ok_or(ParseError::InvalidToken)? {
+            "none" => None,
+            title => Some(String::from(title)),
+        };
+        let rating = match tokens.next().ok_or(ParseError::InvalidToken)? {
+            "none" => None,
+            rating => Some(rating.parse()?),
+        };
+        let is_computer = match tokens.next().ok_or(ParseError::InvalidToken)? {
+            "computer" => true,
+            "human" => false,
+            _ => return Err(ParseError::InvalidToken),
+        };
+        let name = tokens.collect::<Vec<_>>().join(" ");
+
+        Ok(Self {
+            title,
+            rating,
+            is_computer,
+            name,
+        })
+    }
+}
+
 impl Default for UciOptions {
     fn default() -> Self {
+        let search_params = SearchParameters::default();
         Self {
             move_overhead: Duration::from_millis(1),
             threads: 1,
             hash: 32,
+            ponder: false,
+            debug: false,
+            deterministic: false,
+            output: Output::Stdout,
+            multi_pv: 1,
+            analyse_mode: false,
+            show_wdl: false,
+            normalize_score: true,
+            limit_strength: false,
+            elo: Strength::MAX_ELO,
+            contempt: 0,
+            search_params,
+            base_reductions: BaseReductions::new(search_params.lmr_base, search_params.lmr_divisor),
+            opponent: None,
         }
     }
 }
@@ -73,6 +235,9 @@ impl UciOptions {
         let move_overhead_range = Self::MOVE_OVERHEAD_RANGE;
         let thread_range = Self::THREAD_RANGE;
         let hash_range = Self::HASH_RANGE;
+        let multi_pv_range = Self::MULTI_PV_RANGE;
+        let contempt_range = Self::CONTEMPT_RANGE;
+        let elo_range = Self::ELO_RANGE;
 
         println!("id name {ID_NAME} {ID_VERSION}");
         println!("id author {ID_AUTHOR}");
@@ -95,15 +260,58 @@ impl UciOptions {
             hash_range.end(),
         );
         println!("option name Clear Hash type button");
+        println!(
+            "option name Ponder type check default {}",
+            defaults.ponder()
+        );
+        println!(
+            "option name MultiPV type spin default {} min {} max {}",
+            defaults.multi_pv(),
+            multi_pv_range.start(),
+            multi_pv_range.end(),
+        );
+        println!(
+            "option name Contempt type spin default {} min {} max {}",
+            defaults.contempt(),
+            contempt_range.start(),
+            contempt_range.end(),
+        );
+        println!(
+            "option name UCI_AnalyseMode type check default {}",
+            defaults.analyse_mode()
+        );
+        println!(
+            "option name UCI_ShowWDL type check default {}",
+            defaults.show_wdl()
+        );
+        println!(
+            "option name NormalizeScore type check default {}",
+            defaults.normalize_score()
+        );
+        println!(
+            "option name UCI_LimitStrength type check default {}",
+            defaults.limit_strength()
+        );
+        println!(
+            "option name UCI_Elo type spin default {} min {} max {}",
+            defaults.elo(),
+            elo_range.start(),
+            elo_range.end(),
+        );
+        println!("option name UCI_Opponent type string default");
+        #[cfg(feature = "tune")]
+        defaults.search_params.print_options();
     }
 
     /// Sets the move overhead, in milliseconds, clamped in the range
     /// [`MOVE_OVERHEAD_RANGE`](Self::MOVE_OVERHEAD_RANGE).
-    pub fn set_move_overhead(&mut self, duration: u64) {
-        self.move_overhead = Duration::from_millis(duration.clamp(
-            *Self::MOVE_OVERHEAD_RANGE.start(),
-            *Self::MOVE_OVERHEAD_RANGE.end(),
-        ));
+    ///
+    /// Returns `true` if it had to be clamped.
+    pub fn set_move_overhead(&mut self, millis: u64) -> bool {
+        let range = Self::MOVE_OVERHEAD_RANGE;
+        let clamped = millis.clamp(*range.start(), *range.end());
+        self.move_overhead = Duration::from_millis(clamped);
+        !range.contains(&millis)
     }
 
     /// Sets the thread range, clamped in the range
@@ -118,6 +326,73 @@ impl UciOptions {
         self.hash = hash.clamp(*Self::HASH_RANGE.start(), *Self::HASH_RANGE.end());
     }
 
+    /// Sets whether or not the GUI may ask the engine to ponder.
+    pub const fn set_ponder(&mut self, ponder: bool) {
+        self.ponder = ponder;
+    }
+
+    /// Sets whether or not extra diagnostics should be printed.
+    pub const fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+
+    /// Sets whether or not the helper threads should be kept from affecting
+    /// the main thread.
+    pub const fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    /// Sets where the search writes its output.
+    // it can't be `const` when `Output` holds a buffer
+    #[cfg_attr(not(test), allow(clippy::missing_const_for_fn))]
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    /// Sets the number of lines in `MultiPV` mode, clamped in the range
+    /// [`MULTI_PV_RANGE`](Self::MULTI_PV_RANGE).
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.multi_pv = multi_pv.clamp(*Self::MULTI_PV_RANGE.start(), *Self::MULTI_PV_RANGE.end());
+    }
+
+    /// Sets the contempt, in centipawns, clamped in the range
+    /// [`CONTEMPT_RANGE`](Self::CONTEMPT_RANGE).
+    pub fn set_contempt(&mut self, contempt: Eval) {
+        self.contempt = contempt.clamp(*Self::CONTEMPT_RANGE.start(), *Self::CONTEMPT_RANGE.end());
+    }
+
+    /// Sets whether or not the GUI is analysing.
+    pub const fn set_analyse_mode(&mut self, analyse_mode: bool) {
+        self.analyse_mode = analyse_mode;
+    }
+
+    /// Sets whether or not the chances of a win, draw and loss should be
+    /// reported.
+    pub const fn set_show_wdl(&mut self, show_wdl: bool) {
+        self.show_wdl = show_wdl;
+    }
+
+    /// Sets whether or not the reported score should be normalized.
+    pub const fn set_normalize_score(&mut self, normalize_score: bool) {
+        self.normalize_score = normalize_score;
+    }
+
+    /// Sets whether or not the strength of the engine is limited.
+    pub const fn set_limit_strength(&mut self, limit_strength: bool) {
+        self.limit_strength = limit_strength;
+    }
+
+    /// Sets the Elo to play at if the strength is limited, clamped in the
+    /// range [`ELO_RANGE`](Self::ELO_RANGE).
+    pub fn set_elo(&mut self, elo: u16) {
+        self.elo = elo.clamp(*Self::ELO_RANGE.start(), *Self::ELO_RANGE.end());
+    }
+
+    /// Sets the opponent.
+    pub fn set_opponent(&mut self, opponent: Opponent) {
+        self.opponent = Some(opponent);
+    }
+
     /// Returns the move overhead.
     pub const fn move_overhead(&self) -> Duration {
         self.move_overhead
@@ -132,6 +407,113 @@ impl UciOptions {
     pub const fn hash(&self) -> usize {
         self.hash
     }
+
+    /// Returns whether or not the GUI may ask the engine to ponder.
+    pub const fn ponder(&self) -> bool {
+        self.ponder
+    }
+
+    /// Returns whether or not extra diagnostics should be printed.
+    pub const fn debug(&self) -> bool {
+        self.debug
+    }
+
+    /// Returns whether or not the helper threads should be kept from
+    /// affecting the main thread.
+    pub const fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Returns where the search writes its output.
+    pub const fn output(&self) -> &Output {
+        &self.output
+    }
+
+    /// Returns the number of lines in `MultiPV` mode.
+    pub const fn multi_pv(&self) -> usize {
+        self.multi_pv
+    }
+
+    /// Returns the contempt, in centipawns.
+    pub const fn contempt(&self) -> Eval {
+        self.contempt
+    }
+
+    /// Returns whether or not the GUI is analysing.
+    pub const fn analyse_mode(&self) -> bool {
+        self.analyse_mode
+    }
+
+    /// Returns whether or not the chances of a win, draw and loss should be
+    /// reported.
+    pub const fn show_wdl(&self) -> bool {
+        self.show_wdl
+    }
+
+    /// Returns whether or not the reported score should be normalized.
+    pub const fn normalize_score(&self) -> bool {
+        self.normalize_score
+    }
+
+    /// Returns whether or not the strength of the engine is limited.
+    pub const fn limit_strength(&self) -> bool {
+        self.limit_strength
+    }
+
+    /// Returns the Elo to play at if the strength is limited.
+    pub const fn elo(&self) -> u16 {
+        self.elo
+    }
+
+    /// Returns the opponent, if the GUI has given one.
+    pub const fn opponent(&self) -> Option<&Opponent> {
+        self.opponent.as_ref()
+    }
+
+    /// Returns the search parameters.
+    pub const fn search_params(&self) -> SearchParameters {
+        self.search_params
+    }
+
+    /// Returns the base late move reductions of the search parameters.
+    pub const fn base_reductions(&self) -> &BaseReductions {
+        &self.base_reductions
+    }
+
+    /// Sets the search parameter of the option called `name` to `value`,
+    /// clamped in the range of the option, and rebuilds the base late move
+    /// reductions.
+    ///
+    /// Returns `false` if there's no option called `name`.
+    #[cfg(feature = "tune")]
+    pub fn set_search_param(&mut self, name: &str, value: i32) -> bool {
+        let is_option = self.search_params.set_option(name, value);
+        self.base_reductions =
+            BaseReductions::new(self.search_params.lmr_base, self.search_params.lmr_divisor);
+        is_option
+    }
+}
+
+impl Output {
+    /// Creates an [`Output::Buffer`] and returns it along with its lines.
+    #[cfg(test)]
+    pub fn buffer() -> (Self, Arc<Mutex<Vec<String>>>) {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        (Self::Buffer(Arc::clone(&lines)), lines)
+    }
+
+    /// Writes `line` as a line of output.
+    pub fn write_line<T: Display>(&self, line: T) {
+        match *self {
+            Self::Stdout => println!("{line}"),
+            Self::Silent => (),
+            #[cfg(test)]
+            Self::Buffer(ref lines) => lines
+                .lock()
+                .expect("A thread panicked while writing")
+                .push(line.to_string()),
+        }
+    }
 }
 
 impl Engine {
@@ -139,41 +521,96 @@ impl Engine {
     /// protocol.
     ///
     /// Will run until [`recv()`](std::sync::mpsc::Receiver::recv) on the UCI
-    /// receiver returns an error or the process exits. I would make the [`Ok`]
-    /// type a never type, but that's experimental.
+    /// receiver returns an error or the engine is told to quit, including
+    /// during a search. Quitting returns instead of exiting the process
+    /// directly so everything gets flushed.
     pub fn main_loop(&mut self) -> Result<(), RecvError> {
         loop {
             // the sender will never hang up
             let command = self.uci_rx().lock().map_err(|_e| RecvError)?.recv()?;
-            self.handle_command(&command);
+            if self.handle_command(&command).is_break() {
+                self.quit();
+                return Ok(());
+            }
+        }
+    }
+
+    /// Makes sure everything has been written to stdout and waits for the
+    /// input thread to finish.
+    fn quit(&mut self) {
+        // the GUI may be waiting for the `bestmove` of the last search
+        stdout().flush().expect("Error while writing to stdout");
+        if let Some(input_thread) = self.input_thread.take() {
+            input_thread.join().expect("The input thread panicked");
         }
     }
 
     /// Interprets the command given by `line`.
-    fn handle_command(&mut self, command: &str) {
+    ///
+    /// Returns [`ControlFlow::Break`] if the engine should quit.
+    fn handle_command(&mut self, command: &str) -> ControlFlow<()> {
         let mut tokens = command.split_whitespace();
 
         match tokens.next() {
             Some("bench") => bench(tokens),
+            Some("debug") => match tokens.next() {
+                Some("on") => self.options_mut().set_debug(true),
+                Some("off") => self.options_mut().set_debug(false),
+                _ => (),
+            },
+            Some("epd") => epd(tokens),
+            Some("eval") => println!("{}", EvalTrace::new(self.board())),
             Some("f") => {
                 find_magics::<{ PieceType::BISHOP.0 }>();
                 find_magics::<{ PieceType::ROOK.0 }>();
             }
+            Some("genfens") => genfens(tokens),
             Some("go") => {
-                self.go(tokens);
+                return self.go(tokens);
             }
-            Some("isready") => {
-                println!("readyok");
+            Some("isready") => self.options().output().write_line("readyok"),
+            Some("key") => {
+                let board = self.board();
+                self.options().output().write_line(format_args!(
+                    "key {:016x} pawn key {:016x} polyglot key {:016x}",
+                    board.zobrist(),
+                    board.pawn_zobrist(),
+                    board.polyglot_key()
+                ));
             }
             Some("p") => {
-                self.board().pretty_print();
+                self.board().pretty_print(tokens.next() == Some("unicode"));
+            }
+            Some("perft") => {
+                if tokens.next() == Some("bench") {
+                    perft_bench();
+                } else {
+                    println!("info string Usage: perft bench");
+                }
             }
             Some("position") => {
                 self.set_position(tokens);
             }
+            Some("psqt") => {
+                let piece_type = tokens
+                    .next()
+                    .and_then(|token| token.chars().next())
+                    .and_then(|piece| PieceType::try_from(piece).ok());
+                match piece_type {
+                    Some(piece_type) => self.print_psqt(piece_type),
+                    None => println!("info string Usage: psqt <p|n|b|r|q|k>"),
+                }
+            }
+            Some("selftest") => selftest(),
             Some("setoption") => {
                 self.set_option(tokens);
             }
+            Some("stat") => match tokens.next() {
+                Some("on") => self.tt_mut().set_track_stats(true),
+                Some("off") => self.tt_mut().set_track_stats(false),
+                _ => self.print_stats(),
+            },
+            Some("takeback") => self.takeback(),
             Some("uci") => {
                 UciOptions::print();
                 println!("uciok");
@@ -182,12 +619,46 @@ impl Engine {
                 self.reset();
             }
             Some("quit") => {
-                exit(0);
+                return ControlFlow::Break(());
             }
             Some(other) => {
                 println!("info string Unrecognised command \"{other}\".");
             }
             _ => (),
         }
+
+        ControlFlow::Continue(())
+    }
+
+    /// Prints the value of a White piece of type `piece_type` on each square,
+    /// at the phase of the current board.
+    fn print_psqt(&self, piece_type: PieceType) {
+        let phase = self.board().phase();
+        for rank in (0..Rank::TOTAL as u8).rev() {
+            print!("{} |", rank + 1);
+            for file in 0..File::TOTAL as u8 {
+                let square = Square::from_pos(Rank(rank), File(file));
+                print!(" {:>5}", psqt_value(piece_type, square, phase));
+            }
+            println!();
+        }
+        println!("    -----------------------------------------------");
+        println!("        a     b     c     d     e     f     g     h");
+        println!();
+        println!("Phase: {phase}");
+    }
+
+    /// Prints statistics about the transposition table.
+    fn print_stats(&self) {
+        let tt = self.tt();
+        print!("info string hashfull {}", tt.estimate_hashfull());
+        if tt.is_tracking_stats() {
+            print!(
+                " collisions {} overwrites {}",
+                tt.collisions(),
+                tt.overwrites()
+            );
+        }
+        println!();
     }
 }
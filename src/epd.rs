@@ -0,0 +1,324 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{fmt::Write, fs::read_to_string, str::FromStr, time::Duration};
+
+use crate::{
+    board::Board,
+    defs::{File, PieceType, Rank},
+    engine::uci::UciOptions,
+    error::ParseError,
+    movegen::Move,
+    search::{search_sync, Depth, Limits},
+    transposition_table::TranspositionTable,
+};
+
+/// The default time spent on each position, in milliseconds.
+pub const MOVETIME: u64 = 1000;
+/// The hash size used for each position.
+const TT_SIZE: usize = 32;
+
+/// A position from an EPD file and the moves that solve it.
+pub struct EpdPosition {
+    /// The name of the position, or an empty string if it doesn't have one.
+    id: String,
+    /// The position.
+    board: Board,
+    /// The moves given by the `bm` opcode.
+    best_moves: Vec<Move>,
+    /// The moves given by the `am` opcode, which must not be played.
+    avoid_moves: Vec<Move>,
+}
+
+/// How quickly a position was solved.
+pub struct Solution {
+    /// The depth at which a best move was first found and kept.
+    pub depth: Depth,
+    /// How many positions had been searched when it was first found.
+    pub nodes: u64,
+    /// How long the search had taken when it was first found.
+    pub time: Duration,
+}
+
+impl FromStr for EpdPosition {
+    type Err = ParseError;
+
+    /// Parses a line of an EPD file: the first 4 fields of a FEN string
+    /// followed by opcodes separated by semicolons.
+    ///
+    /// Only the `bm`, `am` and `id` opcodes are used and at least one of
+    /// `bm` and `am` must be present. The moves can be in either standard
+    /// algebraic notation or long algebraic notation.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut tokens = string.splitn(5, ' ');
+        let mut fen = String::new();
+        for _ in 0..4 {
+            fen.push_str(tokens.next().ok_or(ParseError::MissingToken)?);
+            fen.push(' ');
+        }
+        fen.push_str("0 1");
+        let board = fen.parse::<Board>()?;
+        board.validate()?;
+
+        let mut id = String::new();
+        let mut best_moves = Vec::new();
+        let mut avoid_moves = Vec::new();
+        for operation in tokens.next().unwrap_or_default().split(';') {
+            let mut operands = operation.split_whitespace();
+            match operands.next() {
+                Some("bm") => {
+                    for operand in operands {
+                        best_moves
+                            .push(parse_move(&board, operand).ok_or(ParseError::InvalidToken)?);
+                    }
+                }
+                Some("am") => {
+                    for operand in operands {
+                        avoid_moves
+                            .push(parse_move(&board, operand).ok_or(ParseError::InvalidToken)?);
+                    }
+                }
+                Some("id") => {
+                    id = operands.collect::<Vec<_>>().join(" ").replace('"', "");
+                }
+                _ => (),
+            }
+        }
+
+        if best_moves.is_empty() && avoid_moves.is_empty() {
+            return Err(ParseError::InvalidToken);
+        }
+
+        Ok(Self {
+            id,
+            board,
+            best_moves,
+            avoid_moves,
+        })
+    }
+}
+
+/// Searches every position in the EPD file given by the first option for the
+/// number of milliseconds given by the second option, or [`MOVETIME`] if
+/// there isn't one.
+///
+/// Once every position has been searched, prints a CSV line for each one with
+/// whether or not it was solved and, if it was, the depth, nodes and time in
+/// milliseconds at which the search first found a best move and kept it,
+/// followed by how many were solved and how long they took on average.
+pub fn epd<'a, T>(mut options: T)
+where
+    T: Iterator<Item = &'a str>,
+{
+    let Some(path) = options.next() else {
+        println!("info string Usage: epd <file> [movetime]");
+        return;
+    };
+    let Ok(contents) = read_to_string(path) else {
+        println!("info string Could not read \"{path}\"");
+        return;
+    };
+    let movetime = options
+        .next()
+        .and_then(|m| m.parse::<u64>().ok())
+        .unwrap_or(MOVETIME);
+    let mut limits = Limits::default();
+    limits.set_movetime(Some(Duration::from_millis(movetime)));
+    let mut tt = TranspositionTable::with_capacity(TT_SIZE);
+
+    // printed at the end so the search output doesn't get in the way
+    let mut csv = String::from("id,solved,depth,nodes,time\n");
+    let mut positions = 0;
+    let mut solutions = Vec::new();
+    for (line_number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(position) = line.parse::<EpdPosition>() else {
+            println!("info string Could not parse line {}", line_number + 1);
+            continue;
+        };
+
+        tt.clear();
+        let id = if position.id.is_empty() {
+            (line_number + 1).to_string()
+        } else {
+            position.id.clone()
+        };
+        positions += 1;
+        match solve(&position, limits, &tt) {
+            Some(solution) => {
+                writeln!(
+                    csv,
+                    "{id},true,{},{},{}",
+                    solution.depth,
+                    solution.nodes,
+                    solution.time.as_millis()
+                )
+                .expect("Writing to a String can't fail");
+                solutions.push(solution);
+            }
+            None => writeln!(csv, "{id},false,,,").expect("Writing to a String can't fail"),
+        }
+    }
+
+    print!("{csv}");
+    let total_time = solutions
+        .iter()
+        .map(|solution| solution.time)
+        .sum::<Duration>();
+    let average_time = total_time
+        .checked_div(solutions.len() as u32)
+        .unwrap_or_default();
+    println!(
+        "solved {}/{positions}, unsolved {}, average solution time {} ms",
+        solutions.len(),
+        positions - solutions.len(),
+        average_time.as_millis()
+    );
+}
+
+/// Searches `position` with the given limits and returns how quickly it was
+/// solved, or [`None`] if the final best move isn't one of the best moves of
+/// the position or is one of the moves to avoid.
+fn solve(position: &EpdPosition, limits: Limits, tt: &TranspositionTable) -> Option<Solution> {
+    let report = search_sync(position.board, limits, &UciOptions::default(), tt, &[]);
+    let best_move = report.best_move();
+    let is_best = position.best_moves.is_empty() || position.best_moves.contains(&best_move);
+    let is_avoided = position.avoid_moves.contains(&best_move);
+
+    (is_best && !is_avoided).then_some(Solution {
+        depth: report.best_move_depth,
+        nodes: report.best_move_nodes,
+        time: report.best_move_time,
+    })
+}
+
+/// Finds the legal move in `board` given by `string`, in either standard or
+/// long algebraic notation.
+fn parse_move(board: &Board, string: &str) -> Option<Move> {
+    let string = string.trim_end_matches(['+', '#', '!', '?']);
+    let legal_moves = board.legal_moves().collect::<Vec<_>>();
+
+    legal_moves.iter().copied().find(|&mv| {
+        mv.to_string() == string || to_san(board, mv, &legal_moves) == string.replace('0', "O")
+    })
+}
+
+/// Converts `mv` into standard algebraic notation, without a check or mate
+/// suffix.
+///
+/// `legal_moves` must be all the legal moves in `board` and is used to
+/// disambiguate pieces of the same type that could move to the same square.
+fn to_san(board: &Board, mv: Move, legal_moves: &[Move]) -> String {
+    let (start, end) = (mv.start(), mv.end());
+    if mv.is_castling() {
+        return if File::from(end).0 > File::from(start).0 {
+            String::from("O-O")
+        } else {
+            String::from("O-O-O")
+        };
+    }
+
+    let piece_type = PieceType::from(board.piece_on(start));
+    let mut san = String::new();
+
+    if piece_type == PieceType::PAWN {
+        if board.is_capture(mv) {
+            san.push(char::from(File::from(start)));
+        }
+    } else {
+        san.push(char::from(piece_type).to_ascii_uppercase());
+
+        let rivals = legal_moves
+            .iter()
+            .filter(|&&other| {
+                other.end() == end
+                    && other.start() != start
+                    && PieceType::from(board.piece_on(other.start())) == piece_type
+            })
+            .map(|other| other.start())
+            .collect::<Vec<_>>();
+        if !rivals.is_empty() {
+            if rivals
+                .iter()
+                .all(|&s| File::from(s).0 != File::from(start).0)
+            {
+                san.push(char::from(File::from(start)));
+            } else if rivals
+                .iter()
+                .all(|&s| Rank::from(s).0 != Rank::from(start).0)
+            {
+                san.push(char::from(Rank::from(start)));
+            } else {
+                san.push_str(&start.to_string());
+            }
+        }
+    }
+
+    if board.is_capture(mv) {
+        san.push('x');
+    }
+    san.push_str(&end.to_string());
+    if mv.is_promotion() {
+        san.push('=');
+        san.push(char::from(mv.promotion_piece()).to_ascii_uppercase());
+    }
+
+    san
+}
+
+#[cfg(test)]
+mod test {
+    use super::{solve, EpdPosition};
+    use crate::{search::Limits, transposition_table::TranspositionTable};
+
+    /// Checks that a mate in 1 is solved at a low depth and with few nodes.
+    #[test]
+    fn solve_mate_in_one() {
+        let position = "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; id \"back rank\";"
+            .parse::<EpdPosition>()
+            .expect("Valid EPD");
+        let tt = TranspositionTable::with_capacity(1);
+        let mut limits = Limits::default();
+        limits.set_depth(Some(4));
+
+        let solution = solve(&position, limits, &tt).expect("Ra8# should be found");
+
+        assert_eq!(position.id, "back rank", "Wrong id");
+        assert_eq!(solution.depth, 1, "Ra8# should be found at depth 1");
+        assert!(solution.nodes < 1_000, "Too many nodes: {}", solution.nodes);
+    }
+
+    /// Checks that a position with only an `am` opcode is unsolved if the
+    /// search plays the move to avoid.
+    #[test]
+    fn avoid_move() {
+        let position = "6k1/5ppp/8/8/8/8/8/R5K1 w - - am Ra8#;"
+            .parse::<EpdPosition>()
+            .expect("Valid EPD");
+        let tt = TranspositionTable::with_capacity(1);
+        let mut limits = Limits::default();
+        limits.set_depth(Some(4));
+
+        assert!(
+            solve(&position, limits, &tt).is_none(),
+            "Ra8# was the move to avoid"
+        );
+    }
+}
@@ -16,15 +16,79 @@
  * Crab. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::num::ParseIntError;
+use std::{
+    fmt::{self, Display, Formatter},
+    num::ParseIntError,
+};
 
-/// An error that occurs when a string cannot be parsed.
+use crate::defs::{Side, Square};
+
+/// An error that occurs when a string cannot be parsed, or when it can be
+/// parsed but describes an impossible position.
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
-pub struct ParseError;
+pub enum ParseError {
+    /// A token couldn't be parsed.
+    InvalidToken,
+    /// A token that was needed wasn't there.
+    MissingToken,
+    /// The board part of a FEN string has the given number of ranks instead
+    /// of 8.
+    WrongRankCount(usize),
+    /// A rank of a FEN string doesn't have 8 squares.
+    WrongRankLength {
+        /// The rank, counted from 1.
+        rank: u8,
+        /// How many squares the rank has.
+        squares: u8,
+    },
+    /// A side doesn't have 1 king.
+    KingCount {
+        /// The side.
+        side: Side,
+        /// How many kings the side has.
+        count: u32,
+    },
+    /// There's a pawn on the given square of the first or last rank.
+    PawnOnBackRank(Square),
+    /// The side that isn't moving is in check.
+    OpponentInCheck,
+    /// A castling right is set but the king or rook isn't on its starting
+    /// square.
+    InvalidCastlingRights(char),
+    /// The en passant square couldn't have been passed over by a pawn.
+    InvalidEpSquare(Square),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::InvalidToken => f.write_str("invalid token"),
+            Self::MissingToken => f.write_str("missing token"),
+            Self::WrongRankCount(count) => write!(f, "{count} ranks instead of 8"),
+            Self::WrongRankLength { rank, squares } => {
+                write!(f, "rank {rank} has {squares} squares instead of 8")
+            }
+            Self::KingCount { side, count } => {
+                let side = if side == Side::WHITE {
+                    "White"
+                } else {
+                    "Black"
+                };
+                write!(f, "{side} has {count} kings instead of 1")
+            }
+            Self::PawnOnBackRank(square) => write!(f, "pawn on {square}"),
+            Self::OpponentInCheck => f.write_str("the side not to move is in check"),
+            Self::InvalidCastlingRights(right) => {
+                write!(f, "castling right {right} without its king and rook")
+            }
+            Self::InvalidEpSquare(square) => write!(f, "impossible en passant square {square}"),
+        }
+    }
+}
 
 impl From<ParseIntError> for ParseError {
     fn from(_parse_int_error: ParseIntError) -> Self {
-        Self
+        Self::InvalidToken
     }
 }
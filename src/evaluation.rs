@@ -16,19 +16,27 @@
  * Crab. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::ops::{Add, AddAssign, Neg, SubAssign};
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign},
+};
 
 use crate::{
     board::Board,
-    defs::{Piece, Side, Square},
+    defs::{Piece, PieceType, Side, Square},
     search::Depth,
     util::get_unchecked,
 };
 
-use values::create_piece_square_tables;
+use pawns::{PawnTable, PawnTerms, PawnWeights};
+use values::{create_piece_square_tables, piece_value, TEMPO};
 
+/// Pawn structure evaluation and its cache.
+pub mod pawns;
 /// Values related to evaluation.
 pub mod values;
+/// A model of the chances of a win, draw and loss.
+pub mod wdl;
 
 /// The result of an evaluation.
 pub type Eval = i16;
@@ -64,6 +72,15 @@ static PIECE_SQUARE_TABLES: [[Score; Square::TOTAL]; Piece::TOTAL + 1] =
 /// [`Side::WHITE`] and [`Side::BLACK`]. An extra `0` is added at the end to
 /// allow [`Piece::NONE`] to index into it.
 static PHASE_WEIGHTS: [Phase; Piece::TOTAL + 1] = [0, 0, 1, 1, 1, 1, 2, 2, 4, 4, 0, 0, 0];
+/// The material value of each piece that isn't a pawn or a king, using the
+/// values of the static exchange evaluation.
+///
+/// The order is the same as [`PHASE_WEIGHTS`].
+static NON_PAWN_MATERIAL: [Eval; Piece::TOTAL + 1] =
+    [0, 0, 300, 300, 300, 300, 500, 500, 900, 900, 0, 0, 0];
+/// What the evaluation is divided by when the side that's ahead has no pawns
+/// and at most a minor piece's worth of extra material.
+const DRAWISH_ENDGAME_DIVISOR: Eval = 4;
 
 /// A blend between a middlegame and endgame value.
 #[derive(Clone, Copy)]
@@ -84,6 +101,14 @@ impl AddAssign for Score {
     }
 }
 
+impl Mul<Eval> for Score {
+    type Output = Self;
+
+    fn mul(self, rhs: Eval) -> Self::Output {
+        Self(self.0 * rhs, self.1 * rhs)
+    }
+}
+
 impl Neg for Score {
     type Output = Self;
 
@@ -94,6 +119,14 @@ impl Neg for Score {
     }
 }
 
+impl Sub for Score {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(self.0 - rhs.0, self.1 - rhs.1)
+    }
+}
+
 impl SubAssign for Score {
     fn sub_assign(&mut self, rhs: Self) {
         self.0 -= rhs.0;
@@ -111,17 +144,266 @@ impl Score {
     }
 }
 
+/// A term-by-term breakdown of [`evaluate()`].
+///
+/// The scores of each side are from that side's point of view.
+pub struct EvalTrace {
+    /// The material of each piece type of each side, indexed by side then
+    /// piece type.
+    material: [[Score; PieceType::TOTAL]; Side::TOTAL],
+    /// The piece-square bonuses of each piece type of each side, excluding
+    /// material, indexed by side then piece type.
+    psqt: [[Score; PieceType::TOTAL]; Side::TOTAL],
+    /// The pawn structure terms of each side, indexed by side.
+    pawns: [PawnTerms; Side::TOTAL],
+    /// The phase of the board.
+    phase: Phase,
+    /// The side to move, which gets [`TEMPO`].
+    side_to_move: Side,
+    /// The board, for recognising and scaling drawish endgames.
+    board: Board,
+}
+
+impl Display for EvalTrace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let white = Side::WHITE.to_index();
+        let black = Side::BLACK.to_index();
+        let names = ["Pawn", "Knight", "Bishop", "Rook", "Queen", "King"];
+
+        writeln!(
+            f,
+            "{:<16}|     White     |     Black     |     Total",
+            "Term"
+        )?;
+        writeln!(
+            f,
+            "{:<16}|    mg      eg |    mg      eg |    mg      eg",
+            ""
+        )?;
+        writeln!(f, "{}", "-".repeat(64))?;
+        for (term, scores) in [("material", &self.material), ("PSQT", &self.psqt)] {
+            for (piece_type, name) in names.iter().enumerate() {
+                let white_score = scores[white][piece_type];
+                let black_score = scores[black][piece_type];
+                writeln!(
+                    f,
+                    "{:<16}| {:>5}   {:>5} | {:>5}   {:>5} | {:>5}   {:>5}",
+                    format!("{name} {term}"),
+                    white_score.0,
+                    white_score.1,
+                    black_score.0,
+                    black_score.1,
+                    white_score.0 - black_score.0,
+                    white_score.1 - black_score.1,
+                )?;
+            }
+        }
+        for (term, white_score, black_score) in [
+            (
+                "Passed pawns",
+                self.pawns[white].passed,
+                self.pawns[black].passed,
+            ),
+            (
+                "Isolated pawns",
+                self.pawns[white].isolated,
+                self.pawns[black].isolated,
+            ),
+            (
+                "Doubled pawns",
+                self.pawns[white].doubled,
+                self.pawns[black].doubled,
+            ),
+            (
+                "Backward pawns",
+                self.pawns[white].backward,
+                self.pawns[black].backward,
+            ),
+        ] {
+            writeln!(
+                f,
+                "{:<16}| {:>5}   {:>5} | {:>5}   {:>5} | {:>5}   {:>5}",
+                term,
+                white_score.0,
+                white_score.1,
+                black_score.0,
+                black_score.1,
+                white_score.0 - black_score.0,
+                white_score.1 - black_score.1,
+            )?;
+        }
+        let tempo = self.tempo();
+        writeln!(
+            f,
+            "{:<16}|               |               | {:>5}   {:>5}",
+            "Tempo", tempo.0, tempo.1
+        )?;
+        writeln!(f, "{}", "-".repeat(64))?;
+        let total = self.terms() + self.tempo();
+        writeln!(
+            f,
+            "{:<16}|               |               | {:>5}   {:>5}",
+            "Total", total.0, total.1
+        )?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {} (24 is the middlegame)", self.phase)?;
+        let eval = self.eval();
+        let white_eval = if self.side_to_move == Side::WHITE {
+            eval
+        } else {
+            -eval
+        };
+        write!(
+            f,
+            "Evaluation: {white_eval} (White's point of view), {eval} (side to move)"
+        )
+    }
+}
+
+impl EvalTrace {
+    /// Breaks the evaluation of `board` down into its terms.
+    pub fn new(board: &Board) -> Self {
+        let mut material = [[Score(0, 0); PieceType::TOTAL]; Side::TOTAL];
+        let mut psqt = [[Score(0, 0); PieceType::TOTAL]; Side::TOTAL];
+
+        for side in [Side::WHITE, Side::BLACK] {
+            for piece_type in 0..PieceType::TOTAL {
+                let piece_type = PieceType(piece_type as u8);
+                let piece = Piece::from_piecetype(piece_type, side);
+                let value = piece_value(piece_type);
+                let pieces = board.piece_any(piece_type) & board.side_any(side);
+                for square in pieces {
+                    // the tables are from White's point of view
+                    let mut score = piece_score(square, piece);
+                    if side == Side::BLACK {
+                        score = -score;
+                    }
+                    score -= value;
+                    material[side.to_index()][piece_type.to_index()] += value;
+                    psqt[side.to_index()][piece_type.to_index()] += score;
+                }
+            }
+        }
+
+        Self {
+            material,
+            psqt,
+            pawns: [
+                PawnTerms::new(board, Side::BLACK, &PawnWeights::default()),
+                PawnTerms::new(board, Side::WHITE, &PawnWeights::default()),
+            ],
+            phase: board.phase(),
+            side_to_move: board.side_to_move(),
+            board: *board,
+        }
+    }
+
+    /// Returns the final evaluation, relative to the side to move.
+    ///
+    /// This is always the same as [`evaluate()`].
+    pub fn eval(&self) -> Eval {
+        if is_drawn_endgame(&self.board) {
+            return DRAW;
+        }
+
+        // the terms are lerped separately, exactly like `evaluate()`, so that
+        // the rounding is the same
+        let eval = scale_endgame(&self.board, self.terms().lerp_to(self.phase));
+        let tempo = TEMPO.lerp_to(self.phase);
+        if self.side_to_move == Side::WHITE {
+            eval + tempo
+        } else {
+            -eval + tempo
+        }
+    }
+
+    /// Returns the tempo bonus from White's point of view.
+    fn tempo(&self) -> Score {
+        if self.side_to_move == Side::WHITE {
+            TEMPO
+        } else {
+            -TEMPO
+        }
+    }
+
+    /// Returns the sum of every term except the tempo bonus from White's point
+    /// of view.
+    fn terms(&self) -> Score {
+        let mut total = Score(0, 0);
+        for side in [Side::WHITE, Side::BLACK] {
+            let mut score = Score(0, 0);
+            for piece_type in 0..PieceType::TOTAL {
+                score += self.material[side.to_index()][piece_type];
+                score += self.psqt[side.to_index()][piece_type];
+            }
+            score += self.pawns[side.to_index()].total();
+            if side == Side::WHITE {
+                total += score;
+            } else {
+                total -= score;
+            }
+        }
+        total
+    }
+}
+
 /// Calculates a static evaluation of the current board.
-pub fn evaluate(board: &Board) -> Eval {
+///
+/// The pawn structure is looked up in (or added to) `pawn_table`.
+pub fn evaluate(board: &Board, pawn_table: &mut PawnTable) -> Eval {
+    if is_drawn_endgame(board) {
+        return DRAW;
+    }
+
     let phase = board.phase();
-    let score = board.score();
+    let score = board.score() + pawn_table.probe(board);
 
-    let eval = score.lerp_to(phase);
+    let eval = scale_endgame(board, score.lerp_to(phase));
+    let tempo = TEMPO.lerp_to(phase);
 
     if board.side_to_move() == Side::WHITE {
-        eval
+        eval + tempo
     } else {
-        -eval
+        -eval + tempo
+    }
+}
+
+/// Checks if neither side can force checkmate: either there isn't enough
+/// material to checkmate at all or one side has only two knights against a
+/// bare king.
+fn is_drawn_endgame(board: &Board) -> bool {
+    if board.is_insufficient_material() {
+        return true;
+    }
+
+    let knights = board.piece::<{ PieceType::KNIGHT.to_index() }>();
+    let kings = board.piece::<{ PieceType::KING.to_index() }>();
+    knights.count() == 2
+        && knights | kings == board.occupancies()
+        && [Side::WHITE, Side::BLACK]
+            .into_iter()
+            .any(|side| board.piece_count(PieceType::KNIGHT, side) == 2)
+}
+
+/// Scales `eval`, from the perspective of White, towards a draw if the side
+/// that's ahead is unlikely to be able to win.
+///
+/// Without pawns, at least about a rook's worth of extra material is needed
+/// to force checkmate.
+fn scale_endgame(board: &Board, eval: Eval) -> Eval {
+    let (strong, weak) = if eval > 0 {
+        (Side::WHITE, Side::BLACK)
+    } else {
+        (Side::BLACK, Side::WHITE)
+    };
+
+    if board.piece_count(PieceType::PAWN, strong) == 0
+        && board.non_pawn_material(strong) - board.non_pawn_material(weak)
+            <= PieceType::BISHOP.see_bonus()
+    {
+        eval / DRAWISH_ENDGAME_DIVISOR
+    } else {
+        eval
     }
 }
 
@@ -161,9 +443,123 @@ pub fn piece_score(square: Square, piece: Piece) -> Score {
     *get_unchecked(piece_table, square.to_index())
 }
 
+/// Returns the value of a White piece of the given type on the given square at
+/// the given phase, including its material value.
+pub fn psqt_value(piece_type: PieceType, square: Square, phase: Phase) -> Eval {
+    piece_score(square, Piece::from_piecetype(piece_type, Side::WHITE)).lerp_to(phase)
+}
+
 /// Returns the phase of the given piece.
 ///
 /// The piece can be any type (even [`Piece::NONE`]).
 pub fn piece_phase(piece: Piece) -> Phase {
     *get_unchecked(&PHASE_WEIGHTS, piece.to_index())
 }
+
+/// Returns the material value of the given piece if it isn't a pawn or a king.
+///
+/// The piece can be any type (even [`Piece::NONE`]).
+pub fn piece_non_pawn_material(piece: Piece) -> Eval {
+    *get_unchecked(&NON_PAWN_MATERIAL, piece.to_index())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        evaluate, is_drawn_endgame, pawns::PawnTable, psqt_value, scale_endgame, values::TEMPO,
+        EvalTrace, DRAW,
+    };
+    use crate::{
+        board::Board,
+        defs::{PieceType, Square},
+    };
+
+    /// Checks that endgames where neither side can force checkmate are
+    /// evaluated as exact draws.
+    #[test]
+    fn drawn_endgames() {
+        let mut pawn_table = PawnTable::new();
+        for fen in [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1N2K3 b - - 0 1",
+            "4k3/8/8/8/8/8/8/1N2K1N1 w - - 0 1",
+        ] {
+            let board = fen.parse::<Board>().expect("Valid FEN");
+            assert_eq!(evaluate(&board, &mut pawn_table), DRAW, "{fen}");
+        }
+
+        // a knight each can still checkmate
+        let board = "4k1n1/8/8/8/8/8/8/1N2K3 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        assert!(!is_drawn_endgame(&board), "KNvKN");
+    }
+
+    /// Checks that an extra minor piece without pawns is scaled towards a
+    /// draw but an extra queen against a rook isn't.
+    #[test]
+    fn endgame_scaling() {
+        let rook_v_bishop = "4k3/8/8/3b4/8/8/8/3RK3 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let queen_v_rook = "4k3/8/8/3r4/8/8/8/3QK3 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+
+        assert_eq!(scale_endgame(&rook_v_bishop, 200), 50, "KRvKB");
+        assert_eq!(scale_endgame(&rook_v_bishop.mirrored(), -200), -50, "KBvKR");
+        assert_eq!(scale_endgame(&queen_v_rook, 400), 400, "KQvKR");
+    }
+
+    /// Checks that the tempo bonus is given to the side to move.
+    #[test]
+    fn tempo() {
+        let white = "rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR w KQkq - 2 3"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let black = "rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let tempo = TEMPO.lerp_to(white.phase());
+        let mut pawn_table = PawnTable::new();
+
+        // `evaluate()` is relative to the side to move, so flip Black's
+        // evaluation to make both relative to White
+        assert_eq!(
+            evaluate(&white, &mut pawn_table) - -evaluate(&black, &mut pawn_table),
+            2 * tempo,
+            "The difference should be twice the tempo"
+        );
+    }
+
+    /// Checks that the trace adds up to the same evaluation as `evaluate()`.
+    #[test]
+    fn trace_matches_evaluate() {
+        let mut pawn_table = PawnTable::new();
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+            "4k3/8/8/3b4/8/8/8/3RK3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1N2K1N1 b - - 0 1",
+        ] {
+            let board = fen.parse::<Board>().expect("Valid FEN");
+            assert_eq!(
+                EvalTrace::new(&board).eval(),
+                evaluate(&board, &mut pawn_table),
+                "{fen}"
+            );
+        }
+    }
+
+    /// Checks that the PSQT values include the material value and are lerped
+    /// between the middlegame and the endgame.
+    #[test]
+    fn psqt_value_phases() {
+        let a2 = "a2".parse::<Square>().expect("Valid square");
+
+        assert_eq!(psqt_value(PieceType::PAWN, a2, 24), 82 - 35, "Middlegame");
+        assert_eq!(psqt_value(PieceType::PAWN, a2, 0), 94 + 13, "Endgame");
+    }
+}
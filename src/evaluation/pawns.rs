@@ -0,0 +1,252 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    values::{BACKWARD_PAWN, DOUBLED_PAWN, ISOLATED_PAWN, PASSED_PAWN},
+    Eval, Score,
+};
+use crate::{
+    bitboard::Bitboard,
+    board::{Board, Key},
+    defs::{File, PieceType, Rank, Side, Square},
+    movegen::LOOKUPS,
+    util::get_unchecked,
+};
+
+/// The number of entries in a [`PawnTable`]. Must be a power of 2.
+const PAWN_TABLE_SIZE: usize = 1 << 12;
+
+/// The pawn structure terms of one side, from that side's point of view.
+#[derive(Clone, Copy)]
+pub struct PawnTerms {
+    /// The bonuses for passed pawns.
+    pub passed: Score,
+    /// The penalties for isolated pawns.
+    pub isolated: Score,
+    /// The penalties for doubled pawns.
+    pub doubled: Score,
+    /// The penalties for backward pawns.
+    pub backward: Score,
+}
+
+/// The weights of the pawn structure terms.
+///
+/// They're separate from the constants in [`values`](super::values) so that
+/// they can be tuned as search parameters.
+#[derive(Clone, Copy)]
+pub struct PawnWeights {
+    /// The bonus for a passed pawn, indexed by its rank from the point of
+    /// view of its side.
+    pub passed: [Score; Rank::TOTAL],
+    /// The penalty for an isolated pawn.
+    pub isolated: Score,
+    /// The penalty for each extra pawn on a file.
+    pub doubled: Score,
+    /// The penalty for a backward pawn.
+    pub backward: Score,
+}
+
+/// An entry of a [`PawnTable`].
+#[derive(Clone, Copy)]
+struct PawnEntry {
+    /// The pawn key of the board the entry was stored from.
+    key: Key,
+    /// The pawn structure score of that board.
+    score: Score,
+}
+
+/// A cache of pawn structure scores, indexed by the pawn key of the board.
+///
+/// The pawns of a board change far less often than the rest of it, so almost
+/// every probe hits.
+pub struct PawnTable {
+    /// The entries of the table.
+    entries: Vec<PawnEntry>,
+    /// The weights the scores of the entries are calculated with.
+    weights: PawnWeights,
+}
+
+impl Default for PawnWeights {
+    fn default() -> Self {
+        Self {
+            passed: PASSED_PAWN,
+            isolated: ISOLATED_PAWN,
+            doubled: DOUBLED_PAWN,
+            backward: BACKWARD_PAWN,
+        }
+    }
+}
+
+impl PawnTerms {
+    /// Calculates the pawn structure terms of `side` on `board` with the
+    /// given weights.
+    pub fn new(board: &Board, side: Side, weights: &PawnWeights) -> Self {
+        let pawns = board.piece_any(PieceType::PAWN);
+        let our_pawns = pawns & board.side_any(side);
+        let their_pawns = pawns & board.side_any(side.flip());
+        let mut terms = Self {
+            passed: Score(0, 0),
+            isolated: Score(0, 0),
+            doubled: Score(0, 0),
+            backward: Score(0, 0),
+        };
+
+        for file in 0..File::TOTAL as u8 {
+            let count = (our_pawns & Bitboard::file_bb(File(file))).count();
+            if count > 1 {
+                terms.doubled += weights.doubled * (count - 1) as Eval;
+            }
+        }
+
+        for square in our_pawns {
+            let rank = square.0 >> 3;
+            let relative_rank = if side == Side::WHITE { rank } else { 7 - rank };
+            let neighbours = adjacent_files(square);
+
+            if (their_pawns & (neighbours | file_of(square)) & ranks_ahead(side, rank)).is_empty() {
+                terms.passed += *get_unchecked(&weights.passed, relative_rank as usize);
+            }
+
+            if (our_pawns & neighbours).is_empty() {
+                terms.isolated += weights.isolated;
+                continue;
+            }
+
+            // no pawn can come up to support it and it can't advance safely
+            let stop = if side == Side::WHITE {
+                Square(square.0 + 8)
+            } else {
+                Square(square.0 - 8)
+            };
+            let is_unsupported = (our_pawns & neighbours & ranks_behind(side, rank)).is_empty();
+            let is_stop_attacked = !(LOOKUPS.pawn_attacks(side, stop) & their_pawns).is_empty();
+            if is_unsupported && is_stop_attacked {
+                terms.backward += weights.backward;
+            }
+        }
+
+        terms
+    }
+
+    /// Returns the sum of the terms.
+    pub fn total(self) -> Score {
+        self.passed + self.isolated + self.doubled + self.backward
+    }
+}
+
+impl PawnTable {
+    /// Creates a new, empty [`PawnTable`] with the default weights.
+    pub fn new() -> Self {
+        Self::with_weights(PawnWeights::default())
+    }
+
+    /// Creates a new, empty [`PawnTable`] that calculates its scores with
+    /// `weights`.
+    pub fn with_weights(weights: PawnWeights) -> Self {
+        // a board without pawns has a key of 0, which also has a score of 0,
+        // so the empty entries are correct
+        Self {
+            entries: vec![
+                PawnEntry {
+                    key: 0,
+                    score: Score(0, 0),
+                };
+                PAWN_TABLE_SIZE
+            ],
+            weights,
+        }
+    }
+
+    /// Returns the pawn structure score of `board` from White's point of
+    /// view, calculating and storing it if it isn't in the table.
+    pub fn probe(&mut self, board: &Board) -> Score {
+        let key = board.pawn_zobrist();
+        let index = key as usize & (PAWN_TABLE_SIZE - 1);
+        let entry = &mut self.entries[index];
+        if entry.key != key {
+            *entry = PawnEntry {
+                key,
+                score: pawn_structure(board, &self.weights),
+            };
+        }
+        entry.score
+    }
+}
+
+/// Calculates the pawn structure score of `board` from White's point of view
+/// with the given weights.
+pub fn pawn_structure(board: &Board, weights: &PawnWeights) -> Score {
+    let mut score = PawnTerms::new(board, Side::WHITE, weights).total();
+    score -= PawnTerms::new(board, Side::BLACK, weights).total();
+    score
+}
+
+/// Returns the file of `square` as a bitboard.
+const fn file_of(square: Square) -> Bitboard {
+    Bitboard::file_bb(File(square.0 & 7))
+}
+
+/// Returns the files either side of the file of `square` as a bitboard.
+fn adjacent_files(square: Square) -> Bitboard {
+    let file = file_of(square);
+    file.east() | file.west()
+}
+
+/// Returns the ranks in front of `rank` from the point of view of `side`.
+const fn ranks_ahead(side: Side, rank: u8) -> Bitboard {
+    if side.0 == Side::WHITE.0 {
+        Bitboard(u64::MAX << (8 * rank) << 8)
+    } else {
+        Bitboard(!(u64::MAX << (8 * rank)))
+    }
+}
+
+/// Returns `rank` and the ranks behind it from the point of view of `side`.
+const fn ranks_behind(side: Side, rank: u8) -> Bitboard {
+    Bitboard(!ranks_ahead(side, rank).0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PawnTerms, PawnWeights};
+    use crate::{board::Board, defs::Side};
+
+    /// Checks each term on positions with every kind of pawn.
+    #[test]
+    fn pawn_terms() {
+        // White: a passed and isolated pawn on a5, an isolated pawn on c2 and
+        // doubled pawns on the f-file, with g2 supporting both of them
+        let board = "4k3/7p/5p2/P7/6p1/5P2/2P2PP1/4K3 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let white = PawnTerms::new(&board, Side::WHITE, &PawnWeights::default());
+
+        assert!(white.passed.0 > 0, "a5 is passed");
+        assert!(white.isolated.0 < 0, "a5 and c2 are isolated");
+        assert!(white.doubled.0 < 0, "f2 and f3 are doubled");
+        assert!(white.backward.0 == 0, "No pawn is backward");
+
+        // White: c3 can't be supported by b4 and d5 stops it from advancing
+        let board = "4k3/8/8/3p4/1P6/2P5/8/4K3 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let white = PawnTerms::new(&board, Side::WHITE, &PawnWeights::default());
+
+        assert!(white.backward.0 < 0, "c3 is backward");
+    }
+}
@@ -19,7 +19,8 @@
 use super::Score;
 use crate::{
     cfor,
-    defs::{Piece, PieceType, Side, Square},
+    defs::{Piece, PieceType, Rank, Side, Square},
+    util::get_unchecked,
 };
 
 /// Values in centipawns for each piece.
@@ -30,6 +31,33 @@ const BASE_PIECE_VALUES: [Score; PieceType::TOTAL] = [
     Score(82, 94), Score(337, 281), Score(365, 297), Score(477, 512), Score(1025, 936), Score(10_000, 10_000),
 ];
 
+/// Returns the material value of a piece of the given type, without any
+/// piece-square bonus.
+pub fn piece_value(piece_type: PieceType) -> Score {
+    *get_unchecked(&BASE_PIECE_VALUES, piece_type.to_index())
+}
+
+/// The bonus for a passed pawn, indexed by its rank from the point of view of
+/// its side.
+#[rustfmt::skip]
+pub const PASSED_PAWN: [Score; Rank::TOTAL] = [
+    Score(0, 0), Score(2, 8), Score(4, 12), Score(10, 24), Score(24, 45), Score(44, 80), Score(70, 120), Score(0, 0),
+];
+/// The penalty for a pawn without any pawns of its side on the files either
+/// side of it.
+pub const ISOLATED_PAWN: Score = Score(-12, -14);
+/// The penalty for each pawn on the same file as another pawn of its side.
+pub const DOUBLED_PAWN: Score = Score(-8, -22);
+/// The penalty for a pawn that can't be supported by the pawns of its side
+/// and can't advance without being captured.
+pub const BACKWARD_PAWN: Score = Score(-8, -8);
+
+/// A bonus for the side to move.
+///
+/// Having the move is usually worth a little, and including it stops the
+/// evaluation from oscillating between odd and even depths.
+pub const TEMPO: Score = Score(12, 6);
+
 /// Piece-square tables, copied verbatim from
 /// <https://www.chessprogramming.org/PeSTO%27s_Evaluation_Function>.
 ///
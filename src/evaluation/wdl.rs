@@ -0,0 +1,171 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fmt::{self, Display, Formatter};
+
+use super::{is_mate, Eval, Phase};
+
+/// The parameters of the model at phases 0, 4, 8, ..., 24, as `(a, b)`.
+///
+/// The chance of winning with a score of `s` is `1 / (1 + e^((a - s) / b))`,
+/// so `a` is the score with an even chance of winning and `b` is how quickly
+/// the chance changes around it. They were fitted to the results of over
+/// 1000 games of the engine against itself at 5000 nodes per move: scores
+/// are harder to convert into wins as the material comes off the board.
+const PARAMETERS: [(f64, f64); 7] = [
+    (430.0, 220.0),
+    (300.0, 175.0),
+    (175.0, 135.0),
+    (130.0, 140.0),
+    (115.0, 170.0),
+    (110.0, 190.0),
+    (110.0, 190.0),
+];
+/// The number of phases between each entry of [`PARAMETERS`].
+const PHASE_STEP: Phase = 4;
+
+/// The chances of a win, draw and loss for the side to move, per mille.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Wdl {
+    /// The chance of a win.
+    win: u16,
+    /// The chance of a draw.
+    draw: u16,
+    /// The chance of a loss.
+    loss: u16,
+}
+
+impl Display for Wdl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "wdl {} {} {}", self.win, self.draw, self.loss)
+    }
+}
+
+impl Wdl {
+    /// Estimates the chances of a win, draw and loss from a score and the
+    /// phase of the position it was found in.
+    ///
+    /// A mate score is a certain win or loss.
+    pub fn new(score: Eval, phase: Phase) -> Self {
+        if is_mate(score) {
+            return if score > 0 {
+                Self {
+                    win: 1000,
+                    draw: 0,
+                    loss: 0,
+                }
+            } else {
+                Self {
+                    win: 0,
+                    draw: 0,
+                    loss: 1000,
+                }
+            };
+        }
+
+        let win = win_rate(score, phase);
+        let loss = win_rate(-score, phase);
+        Self {
+            win,
+            draw: 1000 - win - loss,
+            loss,
+        }
+    }
+}
+
+/// Scales `score` at `phase` so that a score of 100 has an even chance of
+/// winning.
+///
+/// The evaluation drifts as it's tuned and a pawn is worth more in some
+/// phases than others, so this gives the GUI a consistent meaning of a pawn.
+/// Mate scores aren't changed.
+pub fn normalize(score: Eval, phase: Phase) -> Eval {
+    if is_mate(score) {
+        return score;
+    }
+    let (a, _) = parameters(phase);
+    (f64::from(score) * 100.0 / a).round() as Eval
+}
+
+/// Returns the chance of winning with `score` at `phase`, per mille.
+fn win_rate(score: Eval, phase: Phase) -> u16 {
+    let (a, b) = parameters(phase);
+    (1000.0 / (1.0 + ((a - f64::from(score)) / b).exp())).round() as u16
+}
+
+/// Returns the parameters of the model at `phase`.
+fn parameters(phase: Phase) -> (f64, f64) {
+    let phase = phase.min(PHASE_STEP * (PARAMETERS.len() as Phase - 1));
+    let index = usize::from(phase / PHASE_STEP);
+    let (a, b) = PARAMETERS[index];
+    // interpolate to the next entry so that the chances are smooth
+    PARAMETERS
+        .get(index + 1)
+        .map_or((a, b), |&(next_a, next_b)| {
+            let t = f64::from(phase % PHASE_STEP) / f64::from(PHASE_STEP);
+            ((next_a - a).mul_add(t, a), (next_b - b).mul_add(t, b))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{normalize, Wdl};
+    use crate::evaluation::MATE;
+
+    /// Checks that the chances add up, favour the side that's ahead and are
+    /// certain for mates, and that a normalized score of 100 is an even
+    /// chance of winning.
+    #[test]
+    fn chances() {
+        for phase in 0..=24 {
+            for score in (-1000..=1000).step_by(50) {
+                let Wdl { win, draw, loss } = Wdl::new(score, phase);
+                assert_eq!(win + draw + loss, 1000, "Score {score}, phase {phase}");
+                assert_eq!(
+                    Wdl::new(-score, phase),
+                    Wdl {
+                        win: loss,
+                        draw,
+                        loss: win,
+                    },
+                    "Score {score}, phase {phase} isn't symmetrical"
+                );
+            }
+            assert!(
+                Wdl::new(100, phase).win < Wdl::new(200, phase).win,
+                "A higher score should win more at phase {phase}"
+            );
+        }
+        assert!(
+            Wdl::new(200, 24).win > Wdl::new(200, 0).win,
+            "A score should be easier to convert with more material"
+        );
+        for phase in [0, 7, 24] {
+            let even = (-1000..1000)
+                .find(|&score| Wdl::new(score, phase).win >= 500)
+                .expect("Some score is an even chance");
+            assert!(
+                (99..=101).contains(&normalize(even, phase)),
+                "An even chance should be 100cp at phase {phase}"
+            );
+        }
+        assert_eq!(normalize(MATE - 3, 10), MATE - 3, "Mates aren't normalized");
+        assert_eq!(Wdl::new(MATE - 3, 10).to_string(), "wdl 1000 0 0", "Mate");
+        assert_eq!(Wdl::new(-MATE + 4, 10).to_string(), "wdl 0 0 1000", "Mated");
+    }
+}
@@ -0,0 +1,247 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::ops::RangeInclusive;
+
+use oorandom::Rand64;
+
+use crate::{
+    board::Board,
+    evaluation::{evaluate, pawns::PawnTable, Eval, INF_EVAL},
+};
+
+/// The default minimum number of random plies played from the starting
+/// position.
+pub const MIN_PLY: usize = 8;
+/// The default maximum number of random plies played from the starting
+/// position.
+pub const MAX_PLY: usize = 9;
+/// The default largest static evaluation a position may have: by default,
+/// nothing is filtered out.
+pub const MAX_EVAL: Eval = INF_EVAL;
+/// The largest maximum number of random plies that may be asked for.
+const MAX_PLY_LIMIT: usize = 200;
+/// How many random openings may be tried for each FEN asked for before
+/// giving up, in case the options are too strict to ever be satisfied.
+const ATTEMPTS_PER_FEN: usize = 1000;
+
+/// The options of [`genfens()`].
+struct GenfensOptions {
+    /// How many FENs to generate.
+    count: usize,
+    /// The seed of the random moves.
+    seed: u128,
+    /// The minimum number of random plies.
+    min_ply: usize,
+    /// The maximum number of random plies.
+    max_ply: usize,
+    /// The largest absolute static evaluation a position may have.
+    max_eval: Eval,
+}
+
+/// Generates random openings for `OpenBench`, printing each one as
+/// `info string genfens <fen>`.
+///
+/// The options are `<count> seed <seed> book <book>`, as sent by `OpenBench`,
+/// optionally followed by `minply <plies>`, `maxply <plies>` and `maxeval
+/// <centipawns>`. Each opening plays a random number of random moves from the
+/// starting position within `[minply, maxply]` (default [`MIN_PLY`] and
+/// [`MAX_PLY`]), and is only kept if it has a legal move and its static
+/// evaluation is within `maxeval` (default [`MAX_EVAL`]) of 0. Books and
+/// Chess960 aren't supported. If too few openings satisfy the options,
+/// nothing is printed but an error.
+pub fn genfens<'a, T>(tokens: T)
+where
+    T: Iterator<Item = &'a str>,
+{
+    match parse_options(tokens) {
+        Ok(options) => match generate_fens(&options) {
+            Ok(fens) => {
+                for board in fens {
+                    println!("info string genfens {board}");
+                }
+            }
+            Err(message) => println!("info string {message}"),
+        },
+        Err(message) => println!("info string {message}"),
+    }
+}
+
+/// Parses the options of [`genfens()`], returning a description of the
+/// problem if they're invalid.
+fn parse_options<'a, T>(mut tokens: T) -> Result<GenfensOptions, &'static str>
+where
+    T: Iterator<Item = &'a str>,
+{
+    let usage = "Usage: genfens <count> seed <seed> book None [minply <plies>] \
+                 [maxply <plies>] [maxeval <centipawns>]";
+    let mut options = GenfensOptions {
+        count: tokens.next().and_then(|t| t.parse().ok()).ok_or(usage)?,
+        seed: 0,
+        min_ply: MIN_PLY,
+        max_ply: MAX_PLY,
+        max_eval: MAX_EVAL,
+    };
+
+    while let Some(token) = tokens.next() {
+        let value = tokens.next().ok_or(usage)?;
+        match token {
+            "seed" => options.seed = value.parse().map_err(|_e| usage)?,
+            "book" if value != "None" => return Err("genfens doesn't support books"),
+            "book" => (),
+            "minply" => options.min_ply = value.parse().map_err(|_e| usage)?,
+            "maxply" => options.max_ply = value.parse().map_err(|_e| usage)?,
+            "maxeval" => options.max_eval = value.parse().map_err(|_e| usage)?,
+            "frc" | "dfrc" => return Err("genfens doesn't support Chess960"),
+            _ => return Err(usage),
+        }
+    }
+
+    if options.min_ply > options.max_ply {
+        return Err("minply can't be greater than maxply");
+    }
+    if options.max_ply > MAX_PLY_LIMIT {
+        return Err("maxply can't be greater than 200");
+    }
+    if options.max_eval < 0 {
+        return Err("maxeval can't be negative");
+    }
+    Ok(options)
+}
+
+/// Generates the openings described by `options`, returning a description of
+/// the problem if too few random openings satisfy them.
+fn generate_fens(options: &GenfensOptions) -> Result<Vec<Board>, &'static str> {
+    let mut rng = Rand64::new(options.seed);
+    let mut pawn_table = PawnTable::new();
+    let mut fens = Vec::with_capacity(options.count);
+    let mut attempts_left = options.count.saturating_mul(ATTEMPTS_PER_FEN);
+
+    while fens.len() < options.count {
+        if attempts_left == 0 {
+            return Err("genfens gave up: too few openings satisfy the options");
+        }
+        attempts_left -= 1;
+
+        if let Some(board) = random_opening(
+            &mut rng,
+            &mut pawn_table,
+            options.min_ply..=options.max_ply,
+            options.max_eval,
+        ) {
+            fens.push(board);
+        }
+    }
+
+    Ok(fens)
+}
+
+/// Plays a random number of random moves within `plies` from the starting
+/// position.
+///
+/// Returns [`None`] if the resulting position has no legal moves or its
+/// static evaluation is further than `max_eval` from 0.
+pub fn random_opening(
+    rng: &mut Rand64,
+    pawn_table: &mut PawnTable,
+    plies: RangeInclusive<usize>,
+    max_eval: Eval,
+) -> Option<Board> {
+    let plies = rng.rand_range(*plies.start() as u64..*plies.end() as u64 + 1);
+    let mut board = Board::default();
+    for _ in 0..plies {
+        let moves = board.legal_moves();
+        let index = rng.rand_range(0..moves.len().max(1) as u64) as usize;
+        // no moves means checkmate or stalemate
+        board.make_move(moves.iter().nth(index)?);
+    }
+
+    (board.legal_moves().len() > 0 && evaluate(&board, pawn_table).abs() <= max_eval)
+        .then_some(board)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{generate_fens, parse_options};
+    use crate::{
+        defs::Side,
+        evaluation::{evaluate, pawns::PawnTable},
+    };
+
+    /// Checks that the openings are within the ply and evaluation limits and
+    /// are the same for the same seed.
+    #[test]
+    fn generate() {
+        let options =
+            parse_options("20 seed 7 book None minply 4 maxply 6 maxeval 50".split_whitespace())
+                .expect("Valid options");
+        let fens = generate_fens(&options).expect("Enough openings");
+        let mut pawn_table = PawnTable::new();
+
+        assert_eq!(fens.len(), 20, "Wrong number of FENs");
+        for board in &fens {
+            // the fullmove number starts at 1 and goes up after Black moves
+            let plies = usize::from(board.fullmoves() - 1) * 2
+                + usize::from(board.side_to_move() == Side::BLACK);
+            assert!((4..=6).contains(&plies), "{board} has {plies} plies");
+            assert!(
+                evaluate(board, &mut pawn_table).abs() <= 50,
+                "{board} is too unbalanced"
+            );
+        }
+        assert_eq!(
+            generate_fens(&options)
+                .expect("Enough openings")
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            fens.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            "The same seed should give the same FENs"
+        );
+
+        assert!(
+            parse_options("1 seed 1 book openings.epd".split_whitespace()).is_err(),
+            "Books aren't supported"
+        );
+    }
+
+    /// Checks that options that could never be satisfied are rejected instead
+    /// of looping forever.
+    #[test]
+    fn impossible_options() {
+        for (options, reason) in [
+            ("1 seed 1 book None maxeval -1", "Negative maxeval"),
+            ("1 seed 1 book None maxply 201", "maxply above the limit"),
+            (
+                "1 seed 1 book None maxply 18446744073709551615",
+                "maxply that would overflow",
+            ),
+        ] {
+            assert!(
+                parse_options(options.split_whitespace()).is_err(),
+                "{reason}"
+            );
+        }
+
+        // every first move changes the evaluation of the starting position
+        let options =
+            parse_options("1 seed 1 book None minply 1 maxply 1 maxeval 0".split_whitespace())
+                .expect("Valid options");
+        assert!(generate_fens(&options).is_err(), "No opening can be found");
+    }
+}
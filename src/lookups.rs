@@ -16,22 +16,215 @@
  * Crab. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::mem::transmute;
+use crate::{
+    board::{Board, Key},
+    cfor,
+    defs::{Piece, PieceType, Square},
+    movegen::Move,
+    search::Depth,
+    util::get_unchecked,
+};
 
-use crate::{search::Depth, util::get_unchecked};
+/// The number of entries in the cuckoo tables. Must be a power of 2.
+const CUCKOO_SIZE: usize = 0x2000;
+
+/// The cuckoo tables of every reversible move.
+pub static CUCKOO: Cuckoo = Cuckoo::new();
 
 /// A table of base late move reductions.
 ///
 /// Indexed by the depth then number of legal moves.
-type BaseReductions = [[Depth; 128]; 64];
+#[derive(Clone)]
+pub struct BaseReductions {
+    /// The reductions.
+    table: [[Depth; 128]; 64],
+}
 
-/// A table of base late move reductions.
-static BASE_REDUCTIONS: BaseReductions =
-    unsafe { transmute(*include_bytes!("../binaries/base_reductions.bin")) };
-
-/// Finds the base late move reduction for the given number of moves and the
-/// given depth.
-pub fn base_reductions(depth: Depth, total_moves: u8) -> Depth {
-    let move_table = get_unchecked(&BASE_REDUCTIONS, usize::from(depth).min(63));
-    *get_unchecked(move_table, usize::from(total_moves).min(127))
+impl BaseReductions {
+    /// Creates a new table of base reductions with the formula
+    /// `base + ln(depth) * ln(total_moves) / divisor`.
+    ///
+    /// Each reduction is clamped within `[0, depth - 1]`.
+    pub fn new(base: f32, divisor: f32) -> Self {
+        let mut table = [[0; 128]; 64];
+
+        // a depth or move count of 0 never gets reduced, so the table is left
+        // as 0 for those
+        for (depth, move_table) in table.iter_mut().enumerate().skip(1) {
+            let ln_depth = (depth as f32).ln();
+            let max_reduction = (depth - 1) as f32;
+            for (total_moves, reduction) in move_table.iter_mut().enumerate().skip(1) {
+                let ln_total_moves = (total_moves as f32).ln();
+                *reduction =
+                    (base + ln_depth * ln_total_moves / divisor).clamp(0.0, max_reduction) as Depth;
+            }
+        }
+
+        Self { table }
+    }
+
+    /// Finds the base late move reduction for the given number of moves and
+    /// the given depth.
+    pub fn get(&self, depth: Depth, total_moves: u8) -> Depth {
+        let move_table = get_unchecked(&self.table, usize::from(depth).min(63));
+        *get_unchecked(move_table, usize::from(total_moves).min(127))
+    }
+}
+
+/// Tables of the change in zobrist key of every move a knight, bishop, rook,
+/// queen or king can make on an empty board, stored with cuckoo hashing.
+///
+/// If the keys of two positions differ by one of these, a single move might
+/// turn one into the other. See
+/// <https://web.archive.org/web/20201107002606/https://marcelk.net/2013-04-06/paper/upcoming-rep-v2.pdf>.
+pub struct Cuckoo {
+    /// The change in key of each move. An empty entry is 0.
+    keys: [Key; CUCKOO_SIZE],
+    /// The move corresponding to each key, from the lower square to the
+    /// higher square.
+    moves: [Move; CUCKOO_SIZE],
+}
+
+impl Cuckoo {
+    /// Generates the tables.
+    #[allow(clippy::large_stack_arrays)]
+    const fn new() -> Self {
+        let mut keys = [0; CUCKOO_SIZE];
+        let mut moves = [Move::null(); CUCKOO_SIZE];
+
+        cfor!(let mut piece = 0; piece < Piece::TOTAL; piece += 1; {
+            let piece = Piece(piece as u8);
+            cfor!(let mut start = 0; start < Square::TOTAL; start += 1; {
+                cfor!(let mut end = start + 1; end < Square::TOTAL; end += 1; {
+                    let (start, end) = (Square(start as u8), Square(end as u8));
+                    if is_empty_board_move(piece, start, end) {
+                        let mut key = Board::quiet_move_key(piece, start, end);
+                        let mut mv = Move::new(start, end);
+                        let mut index = cuckoo_index_1(key);
+                        // keep kicking the entry out of one slot into its
+                        // other slot until an empty slot is found
+                        loop {
+                            (keys[index], key) = (key, keys[index]);
+                            (moves[index], mv) = (mv, moves[index]);
+                            if key == 0 {
+                                break;
+                            }
+                            index = if index == cuckoo_index_1(key) {
+                                cuckoo_index_2(key)
+                            } else {
+                                cuckoo_index_1(key)
+                            };
+                        }
+                    }
+                });
+            });
+        });
+
+        Self { keys, moves }
+    }
+
+    /// Finds the move that changes the zobrist key by `key`, if there is one.
+    pub fn probe(&self, key: Key) -> Option<Move> {
+        [cuckoo_index_1(key), cuckoo_index_2(key)]
+            .into_iter()
+            .find(|&index| *get_unchecked(&self.keys, index) == key)
+            .map(|index| *get_unchecked(&self.moves, index))
+    }
+}
+
+/// Calculates the first index of `key` in the cuckoo tables.
+const fn cuckoo_index_1(key: Key) -> usize {
+    key as usize & (CUCKOO_SIZE - 1)
+}
+
+/// Calculates the second index of `key` in the cuckoo tables.
+const fn cuckoo_index_2(key: Key) -> usize {
+    (key >> 16) as usize & (CUCKOO_SIZE - 1)
+}
+
+/// Checks if `piece` can move from `start` to `end` on an empty board.
+///
+/// Pawns are treated as never being able to move, because their moves can't
+/// be reversed.
+const fn is_empty_board_move(piece: Piece, start: Square, end: Square) -> bool {
+    let rank_diff = (start.0 >> 3).abs_diff(end.0 >> 3);
+    let file_diff = (start.0 & 7).abs_diff(end.0 & 7);
+    let is_diagonal = rank_diff == file_diff && rank_diff > 0;
+    let is_orthogonal = (rank_diff == 0) != (file_diff == 0);
+
+    // `PieceType::from()` isn't const
+    match PieceType(piece.0 >> 1) {
+        PieceType::KNIGHT => rank_diff * file_diff == 2,
+        PieceType::BISHOP => is_diagonal,
+        PieceType::ROOK => is_orthogonal,
+        PieceType::QUEEN => is_diagonal || is_orthogonal,
+        PieceType::KING => rank_diff <= 1 && file_diff <= 1 && (rank_diff | file_diff) != 0,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BaseReductions, CUCKOO};
+    use crate::{board::Board, defs::Square, movegen::Move};
+
+    /// Checks that every reversible move is in the table and that the change
+    /// in key from a knight move is found.
+    #[test]
+    fn cuckoo() {
+        assert_eq!(
+            CUCKOO.keys.iter().filter(|&&key| key != 0).count(),
+            3668,
+            "Wrong number of moves"
+        );
+
+        let board = Board::default();
+        let mut copy = board;
+        assert!(copy.make_move(Move::new(Square::G1, Square::F3)), "Legal");
+
+        assert_eq!(
+            CUCKOO.probe(board.zobrist() ^ copy.zobrist()),
+            Some(Move::new(Square::G1, Square::F3)),
+            "Missing Nf3"
+        );
+        assert_eq!(
+            CUCKOO.probe(board.zobrist() ^ copy.zobrist() ^ 1),
+            None,
+            "Invalid key"
+        );
+    }
+
+    /// Checks that the coefficients change the table and that the reductions
+    /// never exceed `depth - 1`.
+    #[test]
+    fn base_reductions_coefficients() {
+        let default = BaseReductions::new(0.0, 2.0);
+        let aggressive = BaseReductions::new(1.0, 1.0);
+
+        assert!(
+            default.table != aggressive.table,
+            "Different coefficients should give different tables"
+        );
+        assert!(
+            aggressive.get(10, 30) > default.get(10, 30),
+            "A larger base and smaller divisor should reduce more"
+        );
+
+        for reductions in [&default, &aggressive, &BaseReductions::new(100.0, 0.1)] {
+            for depth in 0..=u8::MAX {
+                for total_moves in 0..=u8::MAX {
+                    let reduction = reductions.get(depth, total_moves);
+                    assert!(
+                        reduction <= depth.saturating_sub(1),
+                        "Reduction {reduction} too large at depth {depth}"
+                    );
+                }
+            }
+        }
+        assert_eq!(
+            BaseReductions::new(-100.0, 2.0).get(10, 30),
+            0,
+            "Reductions can't be negative"
+        );
+    }
 }
@@ -19,31 +19,74 @@
 //! Crab, a UCI-compatible chess engine written in Rust.
 //!
 //! Accepted commands:
-//! - `bench [TT size] [limit] [limit type]`: run a benchmark. The default
+//! - `bench [TT size] [threads] [limit] [limit type]`: run a benchmark,
+//!   printing the nodes of each position and then the total. The default
 //!   options are [`TT_SIZE`](crate::bench::TT_SIZE),
-//!   [`LIMIT`](crate::bench::LIMIT) and
-//!   [`LIMIT_TYPE`](crate::bench::LIMIT_TYPE) respectively.
+//!   [`THREADS`](crate::bench::THREADS), [`LIMIT`](crate::bench::LIMIT) and
+//!   [`LIMIT_TYPE`](crate::bench::LIMIT_TYPE) respectively. The limit type
+//!   can be `depth`, `nodes` or `movetime`, but only the first two give the
+//!   same node count on every run.
+//! - `debug [on|off]`: print diagnostics of the search as `info string`s
+//! - `epd <file> [movetime]`: search each position of an EPD file for
+//!   `movetime` milliseconds (default [`MOVETIME`](crate::epd::MOVETIME)),
+//!   print how quickly each `bm` was found (and each `am` avoided) as CSV,
+//!   then print how many were solved and their average solution time
+//! - `eval`: print the static evaluation of the current board, broken down
+//!   into its terms
 //! - `f`: find magics for the bishop and rook
+//! - `genfens <count> seed <seed> book None [minply <plies>] [maxply <plies>]
+//!   [maxeval <centipawns>]`: print `<count>` random openings for `OpenBench`:
+//!   see [`genfens()`](crate::fen_generation::genfens)
 //! - `go` with the options `wtime`, `btime`, `winc`, `binc`, `movestogo`,
-//!   `depth`, `nodes`, `movetime` and `infinite`. There's also a special
-//!   option `perft <depth>`, which overrides the regular search to run perft
-//!   to `<depth>`.
+//!   `depth`, `nodes`, `movetime`, `mate`, `infinite`, `searchmoves` and
+//!   `ponder`, the last of which is ignored unless the `Ponder` option is
+//!   set. There's also a special option `perft <depth> [hash]`, which
+//!   overrides the regular search to run perft to `<depth>`, optionally
+//!   with a table the size of the `Hash` option.
 //! - `isready`
-//! - `p`: pretty-print the current board
-//! - `position`
+//! - `key`: print the zobrist key and pawn zobrist key of the current board
+//!   in hexadecimal
+//! - `p [unicode]`: pretty-print the current board, optionally with Unicode
+//!   chess symbols
+//! - `ponderhit`: during a search started with `go ponder`
+//! - `perft bench`: run perft on a standard suite of positions and check
+//!   the results
+//! - `position`, optionally without the `fen` keyword before a FEN string
+//! - `psqt <piece>`: print the value of a White piece of the given type on
+//!   each square, at the phase of the current board
+//! - `selftest`: check that the evaluation is symmetrical, that the
+//!   incrementally-updated parts of the board match ones calculated from
+//!   scratch and that perft gives the right results
 //! - `setoption`: see output of `uci` command for more detail
+//! - `stat [on|off]`: print transposition table statistics, or turn
+//!   counting collisions and overwrites on or off
 //! - `stop`
+//! - `takeback`: take back the last move of the current position
 //! - `uci`
 //! - `ucinewgame`
 //! - `quit`
 //!
-//! This program also accepts `bench` as a command-line argument, which it will
-//! process and execute instead of running the UCI loop.
+//! This program also accepts `bench`, `epdtest <file> [movetime]` and
+//! `genfens ...` as command-line arguments, which it will process and execute
+//! instead of running the UCI loop. `epdtest` does the same as the `epd`
+//! command. `genfens` may also be given as a single argument, as `OpenBench`
+//! does.
+//!
+//! `datagen <games> [threads <threads>] [nodes <nodes>] [seed <seed>]
+//! [output <file>] [format <text|binary>]` and `convert <input> <output>
+//! [text|binary]` are only accepted on the command line. `datagen` plays
+//! games against itself from random openings and appends the positions to a
+//! file for training, and `convert` converts such a file between its text
+//! and binary formats. See [`datagen()`](crate::datagen::datagen) and
+//! [`convert()`](crate::datagen::convert).
 
 use std::{env::args, sync::mpsc::RecvError};
 
 use bench::bench;
+use datagen::{convert, datagen};
 use engine::Engine;
+use epd::epd;
+use fen_generation::genfens;
 
 /// Unit testing.
 mod bench;
@@ -51,14 +94,20 @@ mod bench;
 mod bitboard;
 /// Items associated with [`Board`](board::Board).
 mod board;
+/// Generating training data from games against itself.
+mod datagen;
 /// Definitions and enumerations.
 mod defs;
 /// Items associated with [`Engine`].
 mod engine;
+/// Solving EPD test suites.
+mod epd;
 /// Error variants.
 mod error;
 /// Items related to evaluation.
 mod evaluation;
+/// Generating random openings.
+mod fen_generation;
 /// Static lookup items.
 mod lookups;
 /// Items related to move generation.
@@ -67,6 +116,8 @@ mod movegen;
 mod perft;
 /// Items related to searching.
 mod search;
+/// Checking the consistency of the engine.
+mod selftest;
 /// A transposition table.
 mod transposition_table;
 /// Utility.
@@ -76,14 +127,35 @@ fn main() -> Result<(), RecvError> {
     let mut args = args();
     args.next();
 
-    // if it's on the command line, execute the `bench` command and return.
-    // Otherwise, continue as normal
-    if args.next().is_some_and(|s| s == "bench") {
-        // there's practically no difference between deallocating at the end of
-        // `bench()` and at the end of the program
-        bench(args.map(|s| s.leak() as &str));
-        Ok(())
-    } else {
-        Engine::new().main_loop()
+    // if it's on the command line, execute the `bench` or `epdtest` command
+    // and return. Otherwise, continue as normal
+    // there's practically no difference between deallocating at the end of
+    // the command and at the end of the program
+    match args.next().as_deref() {
+        Some("bench") => {
+            bench(args.map(|s| s.leak() as &str));
+            Ok(())
+        }
+        Some("convert") => {
+            convert(args.map(|s| s.leak() as &str));
+            Ok(())
+        }
+        Some("datagen") => {
+            datagen(args.map(|s| s.leak() as &str));
+            Ok(())
+        }
+        Some("epdtest") => {
+            epd(args.map(|s| s.leak() as &str));
+            Ok(())
+        }
+        Some("genfens") => {
+            genfens(args.map(|s| s.leak() as &str));
+            Ok(())
+        }
+        Some(command) if command.starts_with("genfens ") => {
+            genfens(command.split_whitespace().skip(1));
+            Ok(())
+        }
+        _ => Engine::new().main_loop(),
     }
 }
@@ -28,6 +28,8 @@ use crate::{
 use magic::{Magic, BISHOP_MAGICS, ROOK_MAGICS};
 use util::{bitboard_from_square, east, north, sliding_attacks, south, west};
 
+/// Generating strictly legal moves.
+pub mod legal;
 /// Items related to magic bitboards.
 pub mod magic;
 /// Useful functions for move generation.
@@ -57,6 +59,10 @@ pub struct Lookup {
     ///
     /// See <https://www.chessprogramming.org/Magic_Bitboards>.
     rook_magics: [Magic; Square::TOTAL],
+    /// The squares strictly between two squares. `between[start][end] ==
+    /// squares between start and end`, or empty if they're not on the same
+    /// rank, file or diagonal.
+    between: [[Bitboard; Square::TOTAL]; Square::TOTAL],
 }
 
 /// A wrapper for a move and associated methods.
@@ -151,6 +157,7 @@ impl Lookup {
         let king_attacks = Self::init_king_attacks();
         let knight_attacks = Self::init_knight_attacks();
         let (magic_table, bishop_magics, rook_magics) = Self::init_magics();
+        let between = Self::init_between();
 
         Self {
             pawn_attacks,
@@ -159,6 +166,7 @@ impl Lookup {
             magic_table,
             bishop_magics,
             rook_magics,
+            between,
         }
     }
 
@@ -214,6 +222,34 @@ impl Lookup {
         king_attacks
     }
 
+    /// Calculates and returns a lookup table of the squares between each pair
+    /// of squares.
+    #[allow(clippy::large_stack_arrays)]
+    const fn init_between() -> [[Bitboard; Square::TOTAL]; Square::TOTAL] {
+        let mut between = [[Bitboard::empty(); Square::TOTAL]; Square::TOTAL];
+        cfor!(let mut start = 0; start < Square::TOTAL; start += 1; {
+            cfor!(let mut end = 0; end < Square::TOTAL; end += 1; {
+                let rank_diff = (end / 8) as i8 - (start / 8) as i8;
+                let file_diff = (end % 8) as i8 - (start % 8) as i8;
+                let is_aligned = rank_diff == 0
+                    || file_diff == 0
+                    || rank_diff.abs() == file_diff.abs();
+
+                if start != end && is_aligned {
+                    let step = rank_diff.signum() * 8 + file_diff.signum();
+                    let mut square = start as i8 + step;
+                    let mut squares = 0;
+                    while square != end as i8 {
+                        squares |= bitboard_from_square(square as u8);
+                        square += step;
+                    }
+                    between[start][end] = Bitboard(squares);
+                }
+            });
+        });
+        between
+    }
+
     /// Calculates and returns the magic lookup table and magic structs.
     ///
     /// `init_magics() == (magic_table, bishop_magics, rook_magics)`.
@@ -292,6 +328,24 @@ impl Lookup {
         *get_unchecked(&self.king_attacks, square.to_index())
     }
 
+    /// Finds the squares strictly between `start` and `end`.
+    ///
+    /// Will be empty if the squares are not on the same rank, file or
+    /// diagonal.
+    pub fn between(&self, start: Square, end: Square) -> Bitboard {
+        let end_table = get_unchecked(&self.between, start.to_index());
+        *get_unchecked(end_table, end.to_index())
+    }
+
+    /// Finds the squares between `start` and `end`, including both `start`
+    /// and `end`.
+    ///
+    /// If the squares are not on the same rank, file or diagonal, this will
+    /// only be `start` and `end`.
+    pub fn between_inclusive(&self, start: Square, end: Square) -> Bitboard {
+        self.between(start, end) | Bitboard::from(start) | Bitboard::from(end)
+    }
+
     /// Finds the bishop attacks from `square` with the given blockers.
     pub fn bishop_attacks(&self, square: Square, blockers: Bitboard) -> Bitboard {
         let index = get_unchecked(&self.bishop_magics, square.to_index()).get_table_index(blockers);
@@ -475,20 +529,100 @@ pub fn generate_moves<const MOVE_TYPE: u8>(board: &Board) -> Moves {
         generate_pawn_moves::<true, MOVE_TYPE>(board, &mut moves);
         generate_non_sliding_moves::<true, MOVE_TYPE>(board, &mut moves);
         generate_sliding_moves::<true, MOVE_TYPE>(board, &mut moves);
-        if MOVE_TYPE == MoveType::ALL {
+        if MOVE_TYPE == MoveType::ALL || MOVE_TYPE == MoveType::QUIETS {
             generate_castling::<true>(board, &mut moves);
         }
     } else {
         generate_pawn_moves::<false, MOVE_TYPE>(board, &mut moves);
         generate_non_sliding_moves::<false, MOVE_TYPE>(board, &mut moves);
         generate_sliding_moves::<false, MOVE_TYPE>(board, &mut moves);
-        if MOVE_TYPE == MoveType::ALL {
+        if MOVE_TYPE == MoveType::ALL || MOVE_TYPE == MoveType::QUIETS {
             generate_castling::<false>(board, &mut moves);
         }
     }
     moves
 }
 
+/// Calculates the quiet moves for the current position of the given board
+/// that give direct check.
+///
+/// Discovered checks, castling and promotions are not included.
+pub fn generate_quiet_checks(board: &Board) -> Moves {
+    let mut moves = Moves::new();
+    if board.side_to_move() == Side::WHITE {
+        generate_quiet_checks_for::<true>(board, &mut moves);
+    } else {
+        generate_quiet_checks_for::<false>(board, &mut moves);
+    }
+    moves
+}
+
+/// Calculates the quiet moves that give direct check for the given side and
+/// puts them in `moves`.
+fn generate_quiet_checks_for<const IS_WHITE: bool>(board: &Board, moves: &mut Moves) {
+    let them = if IS_WHITE { Side::BLACK } else { Side::WHITE };
+    let penultimate_rank = if IS_WHITE {
+        Bitboard::rank_bb(Rank::RANK7)
+    } else {
+        Bitboard::rank_bb(Rank::RANK2)
+    };
+    let double_push_rank = if IS_WHITE {
+        Bitboard::rank_bb(Rank::RANK4)
+    } else {
+        Bitboard::rank_bb(Rank::RANK5)
+    };
+    let forward = if IS_WHITE { Direction::N } else { Direction::S };
+    let us_bb = board.side::<IS_WHITE>();
+    let occupancies = board.occupancies();
+    let empty = !occupancies;
+    let king = Square::from(board.piece::<{ PieceType::KING.to_index() }>() & board.side_any(them));
+
+    // the empty squares from which each type of piece would attack the king
+    let pawn_checks = LOOKUPS.pawn_attacks(them, king) & empty;
+    let knight_checks = LOOKUPS.knight_attacks(king) & empty;
+    let bishop_checks = LOOKUPS.bishop_attacks(king, occupancies) & empty;
+    let rook_checks = LOOKUPS.rook_attacks(king, occupancies) & empty;
+
+    let pawns = board.piece::<{ PieceType::PAWN.to_index() }>() & us_bb & !penultimate_rank;
+    let single_push = pawns.pawn_push::<IS_WHITE>() & empty;
+    let double_push = single_push.pawn_push::<IS_WHITE>() & empty & double_push_rank;
+    for dest_pawn in single_push & pawn_checks {
+        moves.push(Move::new(dest_pawn - forward, dest_pawn));
+    }
+    for dest_pawn in double_push & pawn_checks {
+        moves.push(Move::new(dest_pawn - forward - forward, dest_pawn));
+    }
+
+    let knights = board.piece::<{ PieceType::KNIGHT.to_index() }>() & us_bb;
+    for knight in knights {
+        for target in LOOKUPS.knight_attacks(knight) & knight_checks {
+            moves.push(Move::new(knight, target));
+        }
+    }
+
+    let bishops = board.piece::<{ PieceType::BISHOP.to_index() }>() & us_bb;
+    for bishop in bishops {
+        for target in LOOKUPS.bishop_attacks(bishop, occupancies) & bishop_checks {
+            moves.push(Move::new(bishop, target));
+        }
+    }
+
+    let rooks = board.piece::<{ PieceType::ROOK.to_index() }>() & us_bb;
+    for rook in rooks {
+        for target in LOOKUPS.rook_attacks(rook, occupancies) & rook_checks {
+            moves.push(Move::new(rook, target));
+        }
+    }
+
+    let queens = board.piece::<{ PieceType::QUEEN.to_index() }>() & us_bb;
+    for queen in queens {
+        let targets = LOOKUPS.queen_attacks(queen, occupancies) & (bishop_checks | rook_checks);
+        for target in targets {
+            moves.push(Move::new(queen, target));
+        }
+    }
+}
+
 /// Calculates all legal pawn moves for `board` and puts them in `moves`.
 // god dammit this function could be so much shorter if full const generics
 // existed
@@ -512,7 +646,12 @@ fn generate_pawn_moves<const IS_WHITE: bool, const MOVE_TYPE: u8>(
     let forward_left = if IS_WHITE { Direction::NW } else { Direction::SW };
     let us_bb = board.side::<IS_WHITE>();
     let occupancies = board.occupancies();
-    let them_bb = occupancies ^ us_bb;
+    // quiet moves can't capture anything
+    let them_bb = if MOVE_TYPE == MoveType::QUIETS {
+        Bitboard::empty()
+    } else {
+        occupancies ^ us_bb
+    };
     let empty = !occupancies;
     let ep_square = board.ep_square();
     let pawns = board.piece::<{ PieceType::PAWN.to_index() }>() & us_bb;
@@ -521,7 +660,7 @@ fn generate_pawn_moves<const IS_WHITE: bool, const MOVE_TYPE: u8>(
     let promotion_pawns = pawns & penultimate_rank;
 
     // regular pushes
-    if MOVE_TYPE == MoveType::ALL {
+    if MOVE_TYPE == MoveType::ALL || MOVE_TYPE == MoveType::QUIETS {
         let single_push = normal_pawns.pawn_push::<IS_WHITE>() & empty;
         let double_push = single_push.pawn_push::<IS_WHITE>() & empty & double_push_rank;
 
@@ -553,7 +692,7 @@ fn generate_pawn_moves<const IS_WHITE: bool, const MOVE_TYPE: u8>(
     }
 
     // en passant
-    if ep_square != Square::NONE {
+    if MOVE_TYPE != MoveType::QUIETS && ep_square != Square::NONE {
         let attackers = if IS_WHITE {
             LOOKUPS.pawn_attacks(Side::BLACK, ep_square) & normal_pawns
         } else {
@@ -580,7 +719,7 @@ fn generate_pawn_moves<const IS_WHITE: bool, const MOVE_TYPE: u8>(
 
     for dest_pawn in single_push {
         let origin = dest_pawn - forward;
-        if MOVE_TYPE == MoveType::ALL {
+        if MOVE_TYPE == MoveType::ALL || MOVE_TYPE == MoveType::QUIETS {
             moves.push(Move::new_promo::<{ PieceType::KNIGHT.0 }>(origin, dest_pawn));
             moves.push(Move::new_promo::<{ PieceType::BISHOP.0 }>(origin, dest_pawn));
             moves.push(Move::new_promo::<{ PieceType::ROOK.0 }>(origin, dest_pawn));
@@ -624,10 +763,14 @@ fn generate_non_sliding_moves<const IS_WHITE: bool, const MOVE_TYPE: u8>(
                 board.side::<true>()
             }
         }
+        MoveType::QUIETS => !board.occupancies(),
         _ => unreachable!(),
     };
     let king_target_squares = if MOVE_TYPE == MoveType::EVASIONS {
-        !us_bb
+        // most king moves out of check are into another attacked square, so
+        // it's worth not generating them in the first place
+        let them = if IS_WHITE { Side::BLACK } else { Side::WHITE };
+        !us_bb & !board.attacked_squares(them)
     } else {
         knight_target_squares
     };
@@ -660,11 +803,11 @@ fn generate_sliding_moves<const IS_WHITE: bool, const MOVE_TYPE: u8>(
 ) {
     let us_bb = board.side::<IS_WHITE>();
     let occupancies = board.occupancies();
-    let target_squares = if MOVE_TYPE == MoveType::CAPTURES {
+    let target_squares = match MOVE_TYPE {
         // the bitboard of our opponent
-        us_bb ^ occupancies
-    } else {
-        !us_bb
+        MoveType::CAPTURES => us_bb ^ occupancies,
+        MoveType::QUIETS => !occupancies,
+        _ => !us_bb,
     };
 
     let bishops = board.piece::<{ PieceType::BISHOP.to_index() }>() & us_bb;
@@ -707,3 +850,206 @@ fn generate_castling<const IS_WHITE: bool>(board: &Board, moves: &mut Moves) {
         moves.push(Move::new_castle::<IS_WHITE, false>());
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{generate_moves, generate_quiet_checks, Move, LOOKUPS};
+    use crate::{
+        bitboard::Bitboard,
+        board::Board,
+        defs::{MoveType, PieceType, Square},
+    };
+
+    /// Checks that every kind of move from every start square to every end
+    /// square can be unpacked into what it was created with.
+    ///
+    /// The layout of [`Move`] is fragile, so this guards against a refactor
+    /// silently corrupting it.
+    #[test]
+    fn move_packing() {
+        for start in (0..Square::TOTAL as u8).map(Square) {
+            for end in (0..Square::TOTAL as u8).map(Square) {
+                let normal = Move::new(start, end);
+                assert!(normal.start() == start, "Normal {start}{end}: start");
+                assert!(normal.end() == end, "Normal {start}{end}: end");
+                assert!(
+                    !normal.is_castling() && !normal.is_en_passant() && !normal.is_promotion(),
+                    "Normal {start}{end}: flags"
+                );
+                assert!(
+                    normal.is_moving_from_to(start, end),
+                    "Normal {start}{end}: from-to"
+                );
+
+                let en_passant = Move::new_en_passant(start, end);
+                assert!(en_passant.start() == start, "EP {start}{end}: start");
+                assert!(en_passant.end() == end, "EP {start}{end}: end");
+                assert!(
+                    !en_passant.is_castling()
+                        && en_passant.is_en_passant()
+                        && !en_passant.is_promotion(),
+                    "EP {start}{end}: flags"
+                );
+                assert!(
+                    en_passant.is_moving_from_to(start, end),
+                    "EP {start}{end}: from-to"
+                );
+
+                for piece in [
+                    PieceType::KNIGHT,
+                    PieceType::BISHOP,
+                    PieceType::ROOK,
+                    PieceType::QUEEN,
+                ] {
+                    let promotion = Move::new_promo_any(start, end, piece);
+                    assert!(promotion.start() == start, "Promo {promotion}: start");
+                    assert!(promotion.end() == end, "Promo {promotion}: end");
+                    assert!(
+                        !promotion.is_castling()
+                            && !promotion.is_en_passant()
+                            && promotion.is_promotion(),
+                        "Promo {promotion}: flags"
+                    );
+                    assert!(
+                        promotion.promotion_piece() == piece,
+                        "Promo {promotion}: piece"
+                    );
+                    assert!(
+                        promotion.is_moving_from_to(start, end),
+                        "Promo {promotion}: from-to"
+                    );
+                }
+            }
+        }
+
+        for (castle, start, end, rook_offset) in [
+            (Move::new_castle::<true, true>(), Square::E1, Square::G1, 1),
+            (
+                Move::new_castle::<true, false>(),
+                Square::E1,
+                Square::C1,
+                -2,
+            ),
+            (Move::new_castle::<false, true>(), Square::E8, Square::G8, 1),
+            (
+                Move::new_castle::<false, false>(),
+                Square::E8,
+                Square::C8,
+                -2,
+            ),
+        ] {
+            assert!(castle.start() == start, "Castle {castle}: start");
+            assert!(castle.end() == end, "Castle {castle}: end");
+            assert!(
+                castle.is_castling() && !castle.is_en_passant() && !castle.is_promotion(),
+                "Castle {castle}: flags"
+            );
+            assert_eq!(
+                castle.rook_offset(),
+                rook_offset,
+                "Castle {castle}: rook offset"
+            );
+        }
+    }
+
+    /// Checks the squares between pairs of squares on the same rank, file and
+    /// diagonal, and on none of them.
+    #[test]
+    fn between() {
+        let squares = |squares: &[Square]| {
+            squares
+                .iter()
+                .fold(Bitboard::empty(), |bb, &square| bb | Bitboard::from(square))
+        };
+
+        assert_eq!(
+            LOOKUPS.between(Square::E1, Square::H1),
+            squares(&[Square::F1, Square::G1]),
+            "Same rank"
+        );
+        assert_eq!(
+            LOOKUPS.between(Square::D7, Square::D4),
+            squares(&[Square::D6, Square::D5]),
+            "Same file"
+        );
+        assert_eq!(
+            LOOKUPS.between(Square::H8, Square::E5),
+            squares(&[Square::G7, Square::F6]),
+            "Same diagonal"
+        );
+        assert_eq!(
+            LOOKUPS.between(Square::B1, Square::C3),
+            Bitboard::empty(),
+            "Not aligned"
+        );
+        assert_eq!(
+            LOOKUPS.between(Square::E4, Square::E5),
+            Bitboard::empty(),
+            "Adjacent"
+        );
+
+        assert_eq!(
+            LOOKUPS.between_inclusive(Square::A1, Square::C3),
+            squares(&[Square::A1, Square::B2, Square::C3]),
+            "Same diagonal, inclusive"
+        );
+        assert_eq!(
+            LOOKUPS.between_inclusive(Square::B1, Square::C3),
+            squares(&[Square::B1, Square::C3]),
+            "Not aligned, inclusive"
+        );
+    }
+
+    /// Checks that none of the king moves generated as evasions are into
+    /// check.
+    #[test]
+    fn evasions_exclude_attacked_king_squares() {
+        // the Black king on d5 is checked by the knight on e3, which also
+        // covers c4
+        let board = "8/8/8/3k4/8/4N3/8/4K3 b - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let is_legal = |mv| {
+            let mut copy = board;
+            copy.make_move(mv)
+        };
+
+        let king_moves = generate_moves::<{ MoveType::EVASIONS }>(&board)
+            .filter(|mv| mv.start() == Square::D5)
+            .collect::<Vec<_>>();
+        let legal_king_moves = generate_moves::<{ MoveType::ALL }>(&board)
+            .filter(|&mv| mv.start() == Square::D5 && is_legal(mv))
+            .count();
+
+        assert!(
+            king_moves.iter().all(|&mv| is_legal(mv)),
+            "An evasion moves the king into check"
+        );
+        assert_eq!(king_moves.len(), legal_king_moves, "Missing evasions");
+        assert_eq!(legal_king_moves, 7, "Wrong number of king moves");
+    }
+
+    /// Checks that the generated quiet checks are exactly the quiet moves that
+    /// give check in a position without any possible discovered checks.
+    #[test]
+    fn quiet_checks() {
+        let board = "4k3/8/8/8/8/8/3P1P2/RNBQKBNR w KQ - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let gives_check = |mv| {
+            let mut copy = board;
+            copy.make_move(mv) && copy.is_in_check()
+        };
+
+        let mut quiet_checks = generate_quiet_checks(&board).collect::<Vec<_>>();
+        let mut expected = generate_moves::<{ MoveType::ALL }>(&board)
+            .filter(|&mv| board.is_quiet(mv) && !mv.is_castling() && gives_check(mv))
+            .collect::<Vec<_>>();
+        quiet_checks.sort_by_key(ToString::to_string);
+        expected.sort_by_key(ToString::to_string);
+
+        // Ra8, Qa4, Qe2, Qh5, Bb5 and Rh8
+        assert_eq!(quiet_checks.len(), 6, "Wrong number of quiet checks");
+        assert_eq!(quiet_checks, expected, "Wrong quiet checks");
+    }
+}
@@ -0,0 +1,167 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::{generate_moves, Move, Moves, LOOKUPS};
+use crate::{
+    bitboard::Bitboard,
+    board::Board,
+    defs::{MoveType, PieceType, Square},
+};
+
+/// What's needed to tell if a pseudolegal move is legal without making it.
+struct Legality {
+    /// The square of the king of the side to move.
+    king: Square,
+    /// The pieces giving check to the king.
+    checkers: Bitboard,
+    /// The squares a piece other than the king can move to without leaving
+    /// the king in check: every square when not in check, the checker and
+    /// the squares between it and the king when in single check and no
+    /// squares when in double check.
+    check_mask: Bitboard,
+    /// The pieces of the side to move that are pinned to its king.
+    pinned: Bitboard,
+    /// The squares attacked by the opponent, seeing through the king.
+    king_danger: Bitboard,
+}
+
+impl Legality {
+    /// Calculates the masks of `board`.
+    fn new(board: &Board) -> Self {
+        let us = board.side_to_move();
+        let them = us.flip();
+        let us_bb = board.side_any(us);
+        let them_bb = board.side_any(them);
+        let occupancies = us_bb | them_bb;
+        let king = Square::from(board.piece::<{ PieceType::KING.to_index() }>() & us_bb);
+        let checkers = board.checkers();
+
+        let check_mask = match checkers.count() {
+            0 => !Bitboard::empty(),
+            1 => LOOKUPS.between_inclusive(king, Square::from(checkers)),
+            _ => Bitboard::empty(),
+        };
+
+        // the sliders that would attack the king if there was nothing in the
+        // way: any single piece of ours in the way is pinned
+        let queens = board.piece::<{ PieceType::QUEEN.to_index() }>();
+        let diagonal_sliders = board.piece::<{ PieceType::BISHOP.to_index() }>() | queens;
+        let orthogonal_sliders = board.piece::<{ PieceType::ROOK.to_index() }>() | queens;
+        let snipers = (LOOKUPS.bishop_attacks(king, them_bb) & diagonal_sliders
+            | LOOKUPS.rook_attacks(king, them_bb) & orthogonal_sliders)
+            & them_bb;
+        let mut pinned = Bitboard::empty();
+        for sniper in snipers {
+            let blockers = LOOKUPS.between(king, sniper) & occupancies;
+            if blockers.count() == 1 {
+                pinned |= blockers & us_bb;
+            }
+        }
+
+        Self {
+            king,
+            checkers,
+            check_mask,
+            pinned,
+            king_danger: board.attacked_squares(them),
+        }
+    }
+
+    /// Checks if the pseudolegal move `mv` is legal on `board`.
+    fn is_legal(&self, board: &Board, mv: Move) -> bool {
+        let start = mv.start();
+        let end = mv.end();
+
+        if start == self.king {
+            if mv.is_castling() {
+                // the king can't castle out of, through or into check
+                return self.checkers.is_empty()
+                    && (LOOKUPS.between_inclusive(start, end) & self.king_danger).is_empty();
+            }
+            return (Bitboard::from(end) & self.king_danger).is_empty();
+        }
+
+        // en passant removes two pieces from the same rank, which can expose
+        // the king in ways the pins don't catch, but it's rare enough to just
+        // try
+        if mv.is_en_passant() {
+            let mut copy = *board;
+            return copy.make_move(mv);
+        }
+
+        if (Bitboard::from(end) & self.check_mask).is_empty() {
+            return false;
+        }
+
+        // a pinned piece can only move along the line of its pin
+        (Bitboard::from(start) & self.pinned).is_empty()
+            || !(LOOKUPS.between(self.king, end) & Bitboard::from(start)).is_empty()
+            || !(LOOKUPS.between(self.king, start) & Bitboard::from(end)).is_empty()
+    }
+}
+
+/// Calculates all legal moves for the current position of the given board.
+///
+/// Unlike [`generate_moves()`], no move needs to be made to find out if it's
+/// legal: pins, checks and the squares the king can't move to are worked out
+/// once beforehand.
+pub fn generate_legal_moves(board: &Board) -> Moves {
+    let legality = Legality::new(board);
+    generate_moves::<{ MoveType::ALL }>(board)
+        .filter(|&mv| legality.is_legal(board, mv))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::generate_legal_moves;
+    use crate::{board::Board, defs::MoveType, movegen::generate_moves, perft::SUITE};
+
+    /// Checks that the legal moves are exactly the pseudolegal moves that
+    /// don't leave the king in check, throughout the first few plies of
+    /// positions with pins, checks, en passant and castling.
+    #[test]
+    fn matches_make_move() {
+        /// Compares the moves of `board` and every position up to `depth`
+        /// plies after it.
+        fn compare(board: &Board, depth: u8) {
+            let mut expected = generate_moves::<{ MoveType::ALL }>(board)
+                .filter(|&mv| {
+                    let mut copy = *board;
+                    copy.make_move(mv)
+                })
+                .collect::<Vec<_>>();
+            let mut legal_moves = generate_legal_moves(board).collect::<Vec<_>>();
+            expected.sort_by_key(ToString::to_string);
+            legal_moves.sort_by_key(ToString::to_string);
+            assert_eq!(legal_moves, expected, "Wrong legal moves for {board}");
+
+            if depth > 0 {
+                for mv in legal_moves {
+                    let mut copy = *board;
+                    copy.make_move(mv);
+                    compare(&copy, depth - 1);
+                }
+            }
+        }
+
+        for (fen, _, _) in SUITE {
+            compare(&fen.parse().expect("Valid FEN"), 2);
+        }
+    }
+}
@@ -16,56 +16,236 @@
  * Crab. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::time::Instant;
+use std::{mem::size_of, time::Instant};
 
-use crate::{board::Board, defs::MoveType, movegen::generate_moves};
+use crate::{
+    board::{Board, Key},
+    movegen::legal::generate_legal_moves,
+};
+
+/// The positions of [`perft_bench()`]: their FEN string, the depth to search
+/// them to and the expected number of leaf nodes.
+pub const SUITE: [(&str, u8, u64); 6] = [
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        5,
+        4_865_609,
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        4,
+        4_085_603,
+    ),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 5, 674_624),
+    (
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        4,
+        422_333,
+    ),
+    (
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        4,
+        2_103_487,
+    ),
+    (
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        4,
+        3_894_594,
+    ),
+];
+
+/// A perft result of a position.
+#[derive(Clone, Copy, Default)]
+struct PerftEntry {
+    /// The key of the position.
+    key: Key,
+    /// The depth that the position was searched to.
+    depth: u8,
+    /// The number of leaf nodes.
+    nodes: u64,
+}
+
+/// A table of perft results, so that transpositions don't have to be counted
+/// again.
+pub struct PerftTable {
+    /// The entries of the table.
+    entries: Vec<PerftEntry>,
+}
+
+impl PerftTable {
+    /// Creates a new [`PerftTable`] of the given size in MiB.
+    pub fn with_capacity(size_mib: usize) -> Self {
+        let entries = (size_mib * 1024 * 1024 / size_of::<PerftEntry>()).max(1);
+        Self {
+            entries: vec![PerftEntry::default(); entries],
+        }
+    }
+
+    /// Returns the number of leaf nodes of the position with the given key at
+    /// the given depth, if it's been stored.
+    fn load(&self, key: Key, depth: u8) -> Option<u64> {
+        self.entries
+            .get(self.index(key))
+            .filter(|entry| entry.key == key && entry.depth == depth)
+            .map(|entry| entry.nodes)
+    }
+
+    /// Stores the number of leaf nodes of the position with the given key at
+    /// the given depth, replacing whatever was there.
+    fn store(&mut self, key: Key, depth: u8, nodes: u64) {
+        let index = self.index(key);
+        if let Some(entry) = self.entries.get_mut(index) {
+            *entry = PerftEntry { key, depth, nodes };
+        }
+    }
+
+    /// Converts a key into a valid index.
+    fn index(&self, key: Key) -> usize {
+        ((u128::from(key) * self.entries.len() as u128) >> 64) as usize
+    }
+}
 
 /// Outputs and returns the number of leaf nodes `depth` moves in the future.
 ///
 /// If `IS_TIMED`, it will also output the time taken and the average NPS.
 pub fn perft<const SHOULD_PRINT: bool, const IS_TIMED: bool>(board: &Board, depth: u8) -> u64 {
+    perft_with_table::<SHOULD_PRINT, IS_TIMED>(board, depth, None)
+}
+
+/// The same as [`perft()`], but transpositions are looked up in `table`
+/// instead of being counted again.
+pub fn hashed_perft<const SHOULD_PRINT: bool, const IS_TIMED: bool>(
+    board: &Board,
+    depth: u8,
+    table: &mut PerftTable,
+) -> u64 {
+    perft_with_table::<SHOULD_PRINT, IS_TIMED>(board, depth, Some(table))
+}
+
+/// Runs perft on each position of a standard suite and checks that the
+/// results are correct, printing the results and the total NPS.
+pub fn perft_bench() {
+    let time = Instant::now();
+    let mut total = 0;
+    let mut all_correct = true;
+
+    for (fen, depth, expected) in SUITE {
+        let board = fen.parse::<Board>().expect("The suite has valid FENs");
+        let nodes = perft::<false, false>(&board, depth);
+        let verdict = if nodes == expected { "ok" } else { "FAILED" };
+        println!("{fen} depth {depth}: {nodes} (expected {expected}) {verdict}");
+        total += nodes;
+        all_correct &= nodes == expected;
+    }
+
+    let elapsed_us = time.elapsed().as_micros().max(1) as u64;
+    println!(
+        "{total} nodes {} nps {} ms: {}",
+        1_000_000 * total / elapsed_us,
+        elapsed_us / 1_000,
+        if all_correct { "all correct" } else { "FAILED" },
+    );
+}
+
+/// Outputs and returns the number of leaf nodes `depth` moves in the future,
+/// looking up transpositions in `table` if there is one.
+///
+/// If `IS_TIMED`, it will also output the time taken and the average NPS.
+fn perft_with_table<const SHOULD_PRINT: bool, const IS_TIMED: bool>(
+    board: &Board,
+    depth: u8,
+    mut table: Option<&mut PerftTable>,
+) -> u64 {
     #![allow(clippy::similar_names)]
     if IS_TIMED {
         let time = Instant::now();
-        let result = perft::<SHOULD_PRINT, false>(board, depth);
+        let result = perft_with_table::<SHOULD_PRINT, false>(board, depth, table);
         // for more precision
-        let elapsed_us = time.elapsed().as_micros() as u64;
+        let elapsed_us = time.elapsed().as_micros().max(1) as u64;
         let elapsed_ms = elapsed_us / 1_000;
         let nps = 1_000_000 * result / elapsed_us;
         println!("Time taken: {elapsed_ms} ms; NPS: {nps}",);
         return result;
     }
 
-    if SHOULD_PRINT {
-        println!("Result:");
-        if depth == 0 {
-            println!("1");
-            return 1;
-        }
+    if !SHOULD_PRINT {
+        return count(board, depth, table);
     }
 
+    // the root is split into each move so they can be printed
+    println!("Result:");
     if depth == 0 {
+        println!("1");
         return 1;
     }
 
-    let moves = generate_moves::<{ MoveType::ALL }>(board);
-
     let mut total = 0;
-    for mv in moves {
+    for mv in generate_legal_moves(board) {
         let mut copy = *board;
-        if !copy.make_move(mv) {
-            continue;
-        }
+        copy.make_move(mv);
 
-        let moves = perft::<false, false>(&copy, depth - 1);
+        let moves = count(&copy, depth - 1, table.as_deref_mut());
         total += moves;
+        println!("{mv}: {moves}");
+    }
+    println!("Total: {total}");
+    total
+}
 
-        if SHOULD_PRINT {
-            println!("{mv}: {moves}");
-        }
+/// Returns the number of leaf nodes `depth` moves in the future, looking up
+/// transpositions in `table` if there is one.
+fn count(board: &Board, depth: u8, mut table: Option<&mut PerftTable>) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = generate_legal_moves(board);
+
+    // bulk counting: the leaves don't need their own call or even to be made
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let key = board.zobrist();
+    if let Some(nodes) = table.as_deref().and_then(|table| table.load(key, depth)) {
+        return nodes;
+    }
+
+    let mut total = 0;
+    for mv in moves {
+        let mut copy = *board;
+        copy.make_move(mv);
+
+        total += count(&copy, depth - 1, table.as_deref_mut());
     }
-    if SHOULD_PRINT {
-        println!("Total: {total}");
+
+    if let Some(table) = table {
+        table.store(key, depth, total);
     }
     total
 }
+
+#[cfg(test)]
+mod test {
+    use super::{hashed_perft, perft, PerftTable, SUITE};
+    use crate::board::Board;
+
+    /// Checks that hashed perft gives the same results as regular perft,
+    /// even when the table is tiny and has to replace entries constantly.
+    #[test]
+    fn hashed_perft_matches() {
+        for (fen, _, _) in SUITE {
+            let board = fen.parse::<Board>().expect("Valid FEN");
+            let expected = perft::<false, false>(&board, 3);
+
+            for size_mib in [0, 1] {
+                let mut table = PerftTable::with_capacity(size_mib);
+                assert_eq!(
+                    hashed_perft::<false, false>(&board, 3, &mut table),
+                    expected,
+                    "Hashed perft of {fen} differs"
+                );
+            }
+        }
+    }
+}
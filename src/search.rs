@@ -18,26 +18,59 @@
 
 use std::{
     fmt::{self, Display, Formatter, Write},
-    process::exit,
-    sync::{mpsc::Receiver, Mutex},
-    time::{Duration, Instant},
+    sync::{
+        mpsc::{channel, Receiver},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+use oorandom::Rand64;
+
 use crate::{
-    board::Board,
-    engine::{uci::UciOptions, ZobristStack},
-    evaluation::{is_mate, moves_to_mate, Eval, INF_EVAL},
-    movegen::Move,
-    transposition_table::TranspositionTable,
+    board::{Board, Key},
+    defs::{Piece, Side},
+    engine::{
+        uci::{Output, UciOptions},
+        ZobristStack,
+    },
+    evaluation::{
+        is_mate, moves_to_mate,
+        pawns::PawnTable,
+        wdl::{normalize, Wdl},
+        Eval, Phase, DRAW, INF_EVAL,
+    },
+    lookups::{BaseReductions, CUCKOO},
+    movegen::{Move, LOOKUPS},
+    transposition_table::{Bound, TranspositionTable},
     util::{get_unchecked, insert_unchecked},
 };
+use eval_cache::EvalCache;
+use histories::Histories;
 use main_search::search;
-use time::calculate_time_window;
+use params::SearchParameters;
+use smp::{vote, SharedState};
+use strength::Strength;
+use time::{calculate_time_bounds, TimeBounds};
 
+/// Caching static evaluations.
+mod eval_cache;
+/// Histories of quiet moves, for move ordering.
+mod histories;
 /// For carrying out the search.
 mod main_search;
 /// For selecting which order moves are searched in.
 mod movepick;
+/// Tunable search parameters.
+pub mod params;
+/// Sharing the search between threads.
+mod smp;
+/// Detailed search statistics.
+#[cfg(feature = "stats")]
+mod stats;
+/// Limiting the strength of the engine.
+pub mod strength;
 /// Time management.
 mod time;
 
@@ -45,6 +78,26 @@ mod time;
 /// respectively) and the current node.
 pub type Depth = u8;
 
+/// The minimum depth at which aspiration windows are used.
+const ASPIRATION_MIN_DEPTH: Depth = 4;
+
+/// How much wider the initial aspiration window is for each helper thread,
+/// so that the threads don't all search the same windows.
+const ASPIRATION_JITTER: Eval = 4;
+
+/// What the volatility of a line's score is divided by before being added to
+/// its initial aspiration window.
+const ASPIRATION_VOLATILITY_DIVISOR: Eval = 2;
+
+/// What the depth is divided by before being added to the initial aspiration
+/// window: a research costs more the deeper it is, so it's worth searching a
+/// slightly wider window to avoid one.
+const ASPIRATION_DEPTH_DIVISOR: Eval = 4;
+
+/// How many times in a row a search may fall outside the aspiration window
+/// before the window is given up on and the full window is searched.
+const ASPIRATION_MAX_FAILS: u8 = 6;
+
 /// A marker for a type of node to allow searches with generic node types.
 #[allow(clippy::missing_docs_in_private_items)]
 trait Node {
@@ -94,18 +147,20 @@ pub enum Limits {
     Nodes(u64),
     /// Go for an exact amount of time.
     Movetime(Duration),
+    /// Go until a mate in at most the given number of moves is found.
+    Mate(u8),
     /// Go until told to stop.
     Infinite,
 }
 
 /// The current status of the search.
 #[derive(Clone, Copy, Eq, PartialEq)]
-enum SearchStatus {
+pub enum SearchStatus {
     /// Do nothing: continue the search as normal.
     Continue,
     /// Stop the search.
     Stop,
-    /// Stop the search and then exit the process.
+    /// Stop the search and then quit the engine.
     Quit,
 }
 
@@ -132,12 +187,23 @@ pub struct SearchReferences<'a> {
     seldepth: Depth,
     /// How many positions have been searched.
     nodes: u64,
+    /// How many positions had been searched when the status was last
+    /// checked.
+    nodes_at_last_check: u64,
     /// The status of the search: continue, stop or quit?
     status: SearchStatus,
+    /// How many times the current iteration had to be searched again because
+    /// the score fell outside the aspiration window.
+    researches: u8,
     /// The limits of the search.
     limits: Limits,
-    /// How much time we're allocated.
-    allocated: Duration,
+    /// Whether or not the search is pondering.
+    ///
+    /// A pondering search ignores its limits until the GUI sends `ponderhit`,
+    /// after which the limits apply as if the search had only just started.
+    is_pondering: bool,
+    /// How much time the search may take.
+    time_bounds: TimeBounds,
     /// A receiver for the inputted UCI commands.
     uci_rx: &'a Mutex<Receiver<String>>,
     /// A stack of zobrist hashes of previous board states, beginning from the
@@ -148,6 +214,84 @@ pub struct SearchReferences<'a> {
     past_zobrists: &'a mut ZobristStack,
     /// The transposition table.
     tt: &'a TranspositionTable,
+    /// The tunable parameters of the search.
+    params: SearchParameters,
+    /// The minimum height at which a null move can be made.
+    ///
+    /// This is raised during the verification search of null move pruning.
+    nmp_min_height: Depth,
+    /// The height at which the search must return.
+    ///
+    /// Every height below it pushes a key onto
+    /// [`past_zobrists`](Self::past_zobrists), so it's limited by how many
+    /// more keys the stack can hold as well as by the length of the PV.
+    max_height: Depth,
+    /// The base late move reductions.
+    base_reductions: &'a BaseReductions,
+    /// The histories of quiet moves.
+    histories: Histories,
+    /// Root moves that won't be searched because they're the best moves of
+    /// earlier lines in `MultiPV` mode.
+    excluded_moves: Vec<Move>,
+    /// The only root moves that will be searched, or every move if it's
+    /// empty.
+    search_moves: Vec<Move>,
+    /// The state shared with the other threads, if there are any.
+    shared: Option<&'a SharedState>,
+    /// The index of the thread: 0 for the main thread and above 0 for the
+    /// helper threads.
+    thread_id: usize,
+    /// How often each kind of pruning has happened.
+    stats: PruningStats,
+    /// Detailed counts of what happened during the search.
+    #[cfg(feature = "stats")]
+    search_stats: stats::SearchStats,
+    /// Whether or not the GUI is analysing, as set by the `UCI_AnalyseMode`
+    /// option.
+    is_analysing: bool,
+    /// How much the engine dislikes a draw, in centipawns.
+    contempt: Eval,
+    /// How the score is reported to the GUI.
+    score_format: ScoreFormat,
+    /// The limit on the strength of the engine, if there is one.
+    strength: Option<Strength>,
+    /// The best move and score of each line of the last completed
+    /// iteration.
+    root_scores: Vec<(Move, Eval)>,
+    /// The cache of pawn structure scores of this thread.
+    pawn_table: PawnTable,
+    /// The cache of static evaluations of this thread.
+    eval_cache: EvalCache,
+    /// Where the output of the search is written.
+    output: Output,
+}
+
+/// How often each kind of pruning happened during a search.
+#[derive(Default)]
+struct PruningStats {
+    /// Cutoffs from the transposition table.
+    tt_cutoffs: u64,
+    /// Null move searches.
+    null_move_tries: u64,
+    /// Cutoffs from null move pruning.
+    null_move_cutoffs: u64,
+    /// Cutoffs from `ProbCut`.
+    probcut_cutoffs: u64,
+    /// Moves skipped by SEE pruning.
+    see_pruned: u64,
+}
+
+/// How the score of a search is reported to the GUI.
+#[derive(Clone, Copy, Default)]
+struct ScoreFormat {
+    /// The phase of the root position, which the score is interpreted at.
+    phase: Phase,
+    /// Whether or not the chances of a win, draw and loss should be
+    /// reported, as set by the `UCI_ShowWDL` option.
+    is_showing_wdl: bool,
+    /// Whether or not the score should be normalized, as set by the
+    /// `NormalizeScore` option.
+    is_normalizing: bool,
 }
 
 /// The final results of a search.
@@ -166,8 +310,31 @@ pub struct SearchReport {
     pub nps: u64,
     /// The final score.
     pub score: Eval,
+    /// The final score as it's reported in centipawns, which is normalized
+    /// if the `NormalizeScore` option is set.
+    pub cp: Eval,
+    /// Whether the score is exact or only a bound, which it is if it fell
+    /// outside the aspiration window.
+    pub bound: Bound,
+    /// The chances of a win, draw and loss given the score, if the
+    /// `UCI_ShowWDL` option is set.
+    pub wdl: Option<Wdl>,
     /// The principle variation.
     pub pv: Pv,
+    /// Which line this is in `MultiPV` mode, starting from 1.
+    pub multipv: usize,
+    /// The depth at which the best move was first found, after which it never
+    /// changed.
+    pub best_move_depth: Depth,
+    /// How many positions had been searched when the best move was first
+    /// found.
+    pub best_move_nodes: u64,
+    /// How long the search had taken when the best move was first found.
+    pub best_move_time: Duration,
+    /// The status of the search when it finished.
+    ///
+    /// If this is [`SearchStatus::Quit`], the engine should quit.
+    pub status: SearchStatus,
 }
 
 impl Default for Limits {
@@ -195,14 +362,42 @@ impl Iterator for Pv {
     }
 }
 
+impl Display for PruningStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tt cutoffs {} null move cutoffs {}/{} probcut cutoffs {} see pruned {}",
+            self.tt_cutoffs,
+            self.null_move_cutoffs,
+            self.null_move_tries,
+            self.probcut_cutoffs,
+            self.see_pruned
+        )
+    }
+}
+
 impl Display for SearchReport {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "info depth {} seldepth {}", self.depth, self.seldepth)?;
+        write!(
+            f,
+            "info depth {} seldepth {} multipv {}",
+            self.depth, self.seldepth, self.multipv
+        )?;
 
         if is_mate(self.score) {
             write!(f, " score mate {}", moves_to_mate(self.score))?;
         } else {
-            write!(f, " score cp {}", self.score)?;
+            write!(f, " score cp {}", self.cp)?;
+        }
+
+        match self.bound {
+            Bound::Lower => write!(f, " lowerbound")?,
+            Bound::Upper => write!(f, " upperbound")?,
+            Bound::Exact => (),
+        }
+
+        if let Some(wdl) = self.wdl {
+            write!(f, " {wdl}")?;
         }
 
         write!(
@@ -253,9 +448,13 @@ impl Limits {
     ///
     /// If `self` is not [`Timed`](Self::Timed), it will be set to
     /// [`Infinite`](Self::Infinite). If `moves_to_go` is [`None`], it will be
-    /// ignored.
+    /// ignored. If it's `Some(0)`, it will be treated as sudden death, the
+    /// same as if it were never given.
     pub fn set_moves_to_go(&mut self, moves_to_go: Option<Depth>) {
         if let Some(mtg) = moves_to_go {
+            // `movestogo 0` is technically invalid, but some GUIs send it to
+            // mean there is no next time control
+            let mtg = if mtg == 0 { u8::MAX } else { mtg };
             if let &mut Self::Timed {
                 ref mut moves_to_go,
                 ..
@@ -304,6 +503,18 @@ impl Limits {
         }
     }
 
+    /// Sets `self` to [`Mate(moves)`](Self::Mate).
+    ///
+    /// If `moves` is [`None`], `self` will be set to
+    /// [`Infinite`](Self::Infinite).
+    pub fn set_mate(&mut self, moves: Option<u8>) {
+        if let Some(moves) = moves {
+            *self = Self::Mate(moves);
+        } else {
+            self.set_infinite();
+        }
+    }
+
     /// Sets `self` to [`Infinite`](Self::Infinite).
     pub fn set_infinite(&mut self) {
         *self = Self::Infinite;
@@ -377,56 +588,139 @@ impl Pv {
 }
 
 impl<'a> SearchReferences<'a> {
+    /// How many positions are searched between each check of the status.
+    const NODES_BETWEEN_CHECKS: u64 = 2048;
+
     /// Creates a new [`SearchReferences`], which includes but is not limited to the
     /// given parameters.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         start: Instant,
         limits: Limits,
-        allocated: Duration,
+        is_pondering: bool,
+        time_bounds: TimeBounds,
         uci_rx: &'a Mutex<Receiver<String>>,
         past_zobrists: &'a mut ZobristStack,
         tt: &'a TranspositionTable,
+        params: SearchParameters,
+        base_reductions: &'a BaseReductions,
     ) -> Self {
+        let max_height = Depth::try_from(past_zobrists.remaining_capacity()).unwrap_or(Depth::MAX);
         Self {
             start,
             depth: 0,
             seldepth: 0,
+            researches: 0,
             nodes: 0,
+            nodes_at_last_check: 0,
             status: SearchStatus::Continue,
             limits,
-            allocated,
+            is_pondering,
+            time_bounds,
             uci_rx,
             past_zobrists,
             tt,
+            params,
+            nmp_min_height: 0,
+            max_height,
+            base_reductions,
+            histories: Histories::new(),
+            excluded_moves: Vec::new(),
+            search_moves: Vec::new(),
+            shared: None,
+            thread_id: 0,
+            stats: PruningStats::default(),
+            #[cfg(feature = "stats")]
+            search_stats: stats::SearchStats::default(),
+            is_analysing: false,
+            contempt: 0,
+            score_format: ScoreFormat::default(),
+            strength: None,
+            root_scores: Vec::new(),
+            pawn_table: PawnTable::with_weights(params.pawn_weights),
+            eval_cache: EvalCache::new(),
+            output: Output::Stdout,
+        }
+    }
+
+    /// Check the status of the search if enough positions have been searched
+    /// since the last check.
+    fn check_status(&mut self) -> SearchStatus {
+        // a limited strength can allow fewer nodes than there are between
+        // checks, so it's checked every time
+        if self.status == SearchStatus::Continue
+            && self
+                .strength
+                .is_some_and(|strength| self.total_nodes() >= strength.nodes())
+        {
+            self.status = SearchStatus::Stop;
         }
+
+        // don't bother wasting more time if we've already stopped
+        let searched = self.nodes - self.nodes_at_last_check;
+        if searched < Self::NODES_BETWEEN_CHECKS || self.status != SearchStatus::Continue {
+            return self.status;
+        }
+
+        // checking whether `nodes` is a multiple of the interval would miss
+        // every node count that's skipped over between checks
+        self.nodes_at_last_check = self.nodes;
+        if !self.is_main() {
+            if let Some(shared) = self.shared {
+                shared.add_helper_nodes(searched);
+            }
+        }
+        self.poll_status()
     }
 
-    /// Check the status of the search.
+    /// Check the status of the search now.
     ///
     /// This will check the UCI receiver to see if the GUI has told us to stop,
     /// then check to see if we're exceeding the limits of the search.
-    fn check_status(&mut self) -> SearchStatus {
-        // only check every 2048 nodes and don't bother wasting more time if
-        // we've already stopped
-        if self.nodes % 2048 != 0 || self.status != SearchStatus::Continue {
+    fn poll_status(&mut self) -> SearchStatus {
+        if self.status != SearchStatus::Continue {
             return self.status;
         }
 
-        #[allow(clippy::unwrap_used)]
-        if let Ok(token) = self.uci_rx.lock().unwrap().try_recv() {
-            let token = token.trim();
-            if token == "stop" {
+        // only the main thread listens to the GUI and the limits, and the
+        // helpers stop with it
+        if !self.is_main() {
+            if self.shared.is_some_and(SharedState::is_stopped) {
                 self.status = SearchStatus::Stop;
-                return self.status;
             }
-            if token == "quit" {
-                self.status = SearchStatus::Quit;
-                return self.status;
-            }
-            if token == "isready" {
-                println!("readyok");
+            return self.status;
+        }
+
+        // handle everything that's arrived since the last check, so nothing
+        // (such as `isready`) has to wait for the next one
+        #[allow(clippy::unwrap_used)]
+        let uci_rx = self.uci_rx.lock().unwrap();
+        while let Ok(token) = uci_rx.try_recv() {
+            match token.trim() {
+                "stop" => {
+                    self.status = SearchStatus::Stop;
+                    return self.status;
+                }
+                "quit" => {
+                    self.status = SearchStatus::Quit;
+                    return self.status;
+                }
+                "isready" => self.output.write_line("readyok"),
+                "ponderhit" if self.is_pondering => {
+                    // the time window was calculated at the start of the
+                    // search, so it's still correct if the search starts
+                    // from now
+                    self.is_pondering = false;
+                    self.start = Instant::now();
+                }
+                _ => (),
             }
         }
+        drop(uci_rx);
+
+        if self.is_pondering {
+            return self.status;
+        }
 
         // these are the only variants that can cause a search to exit early
         #[allow(clippy::wildcard_enum_match_arm)]
@@ -436,32 +730,39 @@ impl<'a> SearchReferences<'a> {
                     self.status = SearchStatus::Stop;
                 }
             }
-            Limits::Movetime(m) => {
-                if self.start.elapsed() >= m {
-                    self.status = SearchStatus::Stop;
-                }
-            }
-            Limits::Timed { time, .. } => {
-                // if we're about to pass our total amount of time (which
-                // includes the move overhead), stop the search
-                if self.start.elapsed() + Duration::from_millis(1) > time {
+            Limits::Movetime(_) | Limits::Timed { .. } => {
+                // the hard bound has the move overhead subtracted
+                if self.start.elapsed() >= self.time_bounds.hard() {
                     self.status = SearchStatus::Stop;
                 }
             }
             _ => (),
         };
+        if self
+            .strength
+            .is_some_and(|strength| self.total_nodes() >= strength.nodes())
+        {
+            self.status = SearchStatus::Stop;
+        }
 
         self.status
     }
 
-    /// Calculates if the iterative deepening loop should be exited.
+    /// Calculates if the iterative deepening loop should be exited, given the
+    /// report of the best line of the iteration.
     ///
     /// Assumes that this is being called at the end of the loop.
-    fn should_stop(&mut self) -> bool {
-        if self.check_status() != SearchStatus::Continue || self.depth == Depth::MAX {
+    fn should_stop(&mut self, report: &SearchReport) -> bool {
+        let score = report.score;
+        // a `stop` could have arrived since the last check
+        if self.poll_status() != SearchStatus::Continue || self.depth == Depth::MAX {
             return true;
         }
 
+        if self.is_pondering {
+            return false;
+        }
+
         #[allow(clippy::wildcard_enum_match_arm)]
         match self.limits {
             Limits::Depth(d) => {
@@ -469,10 +770,25 @@ impl<'a> SearchReferences<'a> {
                     self.status = SearchStatus::Stop;
                 }
             }
-            Limits::Timed { .. } => {
+            // a shorter mate might exist, but it's good enough
+            Limits::Mate(moves)
+                if score > 0 && is_mate(score) && moves_to_mate(score) <= i16::from(moves) =>
+            {
+                self.status = SearchStatus::Stop;
+            }
+            // an analysis should use all the time it's given
+            Limits::Timed { .. } if !self.is_analysing => {
                 // if we do not have a realistic chance of finishing the next
                 // loop, assume we won't, and stop early.
-                if self.start.elapsed() > self.allocated.mul_f32(0.4) {
+                let stability = report.depth - report.best_move_depth;
+                let soft = self.time_bounds.soft(stability);
+                if self.start.elapsed() > soft {
+                    if self.is_debug() {
+                        self.output.write_line(format_args!(
+                            "info string passed the soft bound of {} ms with a best move stable for {stability} iterations",
+                            soft.as_millis()
+                        ));
+                    }
                     self.status = SearchStatus::Stop;
                 }
             }
@@ -482,9 +798,107 @@ impl<'a> SearchReferences<'a> {
         self.status != SearchStatus::Continue
     }
 
+    /// Checks if the root move `mv` shouldn't be searched, either because
+    /// it's not one of the search moves or it's been excluded.
+    fn is_root_move_skipped(&self, mv: Move) -> bool {
+        (!self.search_moves.is_empty() && !self.search_moves.contains(&mv))
+            || self.excluded_moves.contains(&mv)
+    }
+
+    /// Blocks until the GUI lets the search finish if it finished on its own.
+    ///
+    /// A pondering search waits for `stop`, `ponderhit` or `quit` and an
+    /// infinite search waits for `stop` or `quit`.
+    fn wait_for_gui(&mut self) {
+        let is_infinite = matches!(self.limits, Limits::Infinite);
+        if !(self.is_pondering || is_infinite) || self.status != SearchStatus::Continue {
+            return;
+        }
+
+        #[allow(clippy::unwrap_used)]
+        let uci_rx = self.uci_rx.lock().unwrap();
+        loop {
+            // a closed channel means there's nothing left to wait for
+            let token = uci_rx.recv().unwrap_or_else(|_| String::from("quit"));
+            match token.trim() {
+                "stop" => break,
+                // the limits apply from now on, but an infinite search has
+                // none
+                "ponderhit" if !is_infinite => break,
+                "ponderhit" => self.is_pondering = false,
+                "quit" => {
+                    self.status = SearchStatus::Quit;
+                    break;
+                }
+                "isready" => self.output.write_line("readyok"),
+                _ => (),
+            }
+        }
+        self.is_pondering = false;
+        drop(uci_rx);
+    }
+
+    /// Returns the static evaluation of `board`, using the evaluation cache.
+    fn evaluate(&mut self, board: &Board) -> Eval {
+        self.eval_cache.probe(board, &mut self.pawn_table)
+    }
+
+    /// Returns the score of a draw at the given height, from the point of
+    /// view of the side to move.
+    ///
+    /// With a positive contempt, the side the engine is playing for sees a
+    /// draw as slightly losing and its opponent sees it as slightly winning.
+    const fn draw_score(&self, height: Depth) -> Eval {
+        // the engine is playing for the side to move at the root
+        if height.is_multiple_of(2) {
+            DRAW - self.contempt
+        } else {
+            DRAW + self.contempt
+        }
+    }
+
+    /// Checks if the first iteration was stopped before it could finish.
+    ///
+    /// If so, the best move is just the first legal move the root node came
+    /// across, which hasn't been searched properly.
+    ///
+    /// Assumes that this is being called straight after the root search.
+    fn is_best_move_unvetted(&self) -> bool {
+        self.depth == 1 && self.status != SearchStatus::Continue
+    }
+
+    /// Tells the GUI if the best move of `pv` is unvetted.
+    ///
+    /// See [`Self::is_best_move_unvetted()`].
+    fn warn_if_unvetted(&self, pv: &Pv) {
+        if self.is_best_move_unvetted() {
+            self.output.write_line(format_args!(
+                "info string depth 1 was stopped early: {} is unvetted",
+                pv.get(0)
+            ));
+        }
+    }
+
     /// Returns if the root node should print extra information.
     fn should_print(&mut self) -> bool {
-        self.start.elapsed() > Duration::from_millis(3000)
+        self.is_main() && self.start.elapsed() > Duration::from_millis(3000)
+    }
+
+    /// Checks if extra diagnostics should be printed, which only the main
+    /// thread does.
+    fn is_debug(&self) -> bool {
+        self.is_main() && self.shared.is_some_and(SharedState::is_debug)
+    }
+
+    /// Checks if this is the main thread, which is the only one that talks
+    /// to the GUI.
+    const fn is_main(&self) -> bool {
+        self.thread_id == 0
+    }
+
+    /// Returns the number of positions searched by every thread.
+    fn total_nodes(&self) -> u64 {
+        self.nodes + self.shared.map_or(0, SharedState::helper_nodes)
     }
 
     /// Checks if the position is drawn, either because of repetition or
@@ -511,6 +925,69 @@ impl<'a> SearchReferences<'a> {
             .step_by(2)
             .any(|key| key == current_key)
     }
+
+    /// Checks if the side to move can repeat a position from earlier in the
+    /// search with a single move, which would be a draw.
+    ///
+    /// Positions from before the root are ignored: repeating one of those
+    /// might not be enough for a draw.
+    fn has_upcoming_repetition(&self, board: &Board, height: Depth) -> bool {
+        let current_key = self.past_zobrists.peek();
+        let occupancies = board.occupancies();
+
+        self.past_zobrists
+            .iter()
+            // most recent position is last
+            .rev()
+            .enumerate()
+            // a position 1 ply ago can't be reached again with one move, and
+            // positions an even number of plies ago have the wrong stm
+            .skip(3)
+            .step_by(2)
+            // stop at the root or after an irreversible position
+            .take_while(|&(plies, _)| {
+                plies < usize::from(height) && plies <= usize::from(board.halfmoves())
+            })
+            .filter_map(|(_, key)| CUCKOO.probe(current_key ^ key))
+            .any(|mv| {
+                // the piece making the move is on one of the squares and the
+                // other is empty
+                let start = if board.piece_on(mv.start()) == Piece::NONE {
+                    mv.end()
+                } else {
+                    mv.start()
+                };
+                (LOOKUPS.between(mv.start(), mv.end()) & occupancies).is_empty()
+                    && Side::from(board.piece_on(start)) == board.side_to_move()
+            })
+    }
+}
+
+impl ScoreFormat {
+    /// Creates a new [`ScoreFormat`] for a search of `board` with the given
+    /// options.
+    const fn new(board: &Board, options: &UciOptions) -> Self {
+        Self {
+            phase: board.phase(),
+            is_showing_wdl: options.show_wdl(),
+            is_normalizing: options.normalize_score(),
+        }
+    }
+
+    /// Returns `score` as it should be reported in centipawns.
+    fn cp(self, score: Eval) -> Eval {
+        if self.is_normalizing {
+            normalize(score, self.phase)
+        } else {
+            score
+        }
+    }
+
+    /// Returns the chances of a win, draw and loss given `score`, if they
+    /// should be reported.
+    fn wdl(self, score: Eval) -> Option<Wdl> {
+        self.is_showing_wdl.then(|| Wdl::new(score, self.phase))
+    }
 }
 
 impl SearchReport {
@@ -522,76 +999,1413 @@ impl SearchReport {
         nps: u64,
         score: Eval,
         pv: Pv,
+        multipv: usize,
     ) -> Self {
         Self {
             depth: search_refs.depth,
             seldepth: search_refs.seldepth,
-            nodes: search_refs.nodes,
+            nodes: search_refs.total_nodes(),
             hashfull: search_refs.tt.estimate_hashfull(),
             time,
             nps,
             score,
+            bound: Bound::Exact,
+            cp: search_refs.score_format.cp(score),
+            wdl: search_refs.score_format.wdl(score),
             pv,
+            multipv,
+            best_move_depth: search_refs.depth,
+            best_move_nodes: search_refs.total_nodes(),
+            best_move_time: time,
+            status: search_refs.status,
         }
     }
+
+    /// Returns the best move found by the search.
+    pub fn best_move(&self) -> Move {
+        self.pv.get(0)
+    }
 }
 
-/// Performs iterative deepening on the given board.
+/// Performs iterative deepening on the given board, with as many threads as
+/// the options say.
 // might move `SearchReferences` out later, but this is fine for now
 #[allow(clippy::too_many_arguments)]
 pub fn iterative_deepening(
     board: Board,
     start: Instant,
     limits: Limits,
+    search_moves: Vec<Move>,
+    is_pondering: bool,
     uci_rx: &Mutex<Receiver<String>>,
     past_zobrists: &mut ZobristStack,
-    options: UciOptions,
+    options: &UciOptions,
     tt: &TranspositionTable,
 ) -> SearchReport {
-    let allocated = calculate_time_window(limits, start, options.move_overhead());
-    let mut search_refs =
-        SearchReferences::new(start, limits, allocated, uci_rx, past_zobrists, tt);
+    let time_bounds = calculate_time_bounds(
+        limits,
+        start,
+        options.move_overhead(),
+        options.search_params(),
+    );
+    tt.new_generation();
+    let shared = SharedState::new(options.debug(), options.deterministic());
+    if options.debug() && matches!(limits, Limits::Timed { .. } | Limits::Movetime(_)) {
+        options
+            .output()
+            .write_line(format_args!("info string time bounds: {time_bounds}"));
+    }
+    let mut search_refs = SearchReferences::new(
+        start,
+        limits,
+        is_pondering,
+        time_bounds,
+        uci_rx,
+        past_zobrists,
+        tt,
+        options.search_params(),
+        options.base_reductions(),
+    );
+    // a move given twice is still only one line
+    for mv in search_moves {
+        if !search_refs.search_moves.contains(&mv) {
+            search_refs.search_moves.push(mv);
+        }
+    }
+    search_refs.shared = Some(&shared);
+    search_refs.is_analysing = options.analyse_mode();
+    search_refs.contempt = contempt(options);
+    search_refs.score_format = ScoreFormat::new(&board, options);
+    search_refs.output = options.output().clone();
+    search_refs.strength = options
+        .limit_strength()
+        .then(|| Strength::new(options.elo()));
+    // there can't be more lines than moves to search, but there's always at
+    // least 1 line so checkmate and stalemate are still reported
+    let root_moves = if search_refs.search_moves.is_empty() {
+        board.legal_moves().len()
+    } else {
+        search_refs.search_moves.len()
+    };
+    // a limited strength needs a choice of moves
+    let lines = if search_refs.strength.is_some() {
+        options.multi_pv().max(Strength::LINES)
+    } else {
+        options.multi_pv()
+    };
+    let lines = lines.min(root_moves).max(1);
+
+    let mut report = thread::scope(|scope| {
+        let helpers = (1..options.threads())
+            .map(|thread_id| {
+                let mut past_zobrists = search_refs.past_zobrists.clone();
+                let search_moves = search_refs.search_moves.clone();
+                let shared = &shared;
+                // the helpers don't need to be told about the limits because
+                // they stop when the main thread does
+                scope.spawn(move || {
+                    // a table of its own stops the helper from changing what
+                    // the main thread finds in the shared one
+                    let own_tt;
+                    let tt = if shared.is_deterministic() {
+                        own_tt = TranspositionTable::with_capacity(options.hash());
+                        &own_tt
+                    } else {
+                        tt
+                    };
+                    let mut helper_refs = SearchReferences::new(
+                        start,
+                        Limits::Infinite,
+                        false,
+                        TimeBounds::INFINITE,
+                        uci_rx,
+                        &mut past_zobrists,
+                        tt,
+                        options.search_params(),
+                        options.base_reductions(),
+                    );
+                    helper_refs.search_moves = search_moves;
+                    helper_refs.shared = Some(shared);
+                    helper_refs.thread_id = thread_id;
+                    helper_refs.contempt = contempt(options);
+                    helper_refs.score_format = ScoreFormat::new(&board, options);
+                    let report = deepen(&mut helper_refs, &board, 1);
+                    shared.add_helper_nodes(helper_refs.nodes - helper_refs.nodes_at_last_check);
+                    report
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let report = deepen(&mut search_refs, &board, lines)
+            .expect("The main thread always finishes with a report");
+        shared.stop();
+        // a helper that didn't finish an iteration has nothing to vote with
+        let helper_reports = helpers
+            .into_iter()
+            .filter_map(|helper| helper.join().expect("Helper threads shouldn't panic"))
+            .collect::<Vec<_>>();
+
+        // the lines after the first would have to be voted on too
+        if lines > 1 || helper_reports.is_empty() || shared.is_deterministic() {
+            return report;
+        }
+        let main_best_move = report.best_move();
+        let nodes = search_refs.total_nodes();
+        let mut voted_report = vote(report, helper_reports);
+        voted_report.nodes = nodes;
+        // the GUI should see the line that the best move comes from
+        if voted_report.best_move() != main_best_move {
+            search_refs.output.write_line(&voted_report);
+        }
+        voted_report
+    });
+
+    if let Some(mv) = search_refs.strength.and_then(|strength| {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos());
+        strength.pick(&search_refs.root_scores, &mut Rand64::new(seed))
+    }) {
+        if mv != report.best_move() {
+            report.pv.clear();
+            report.pv.enqueue(mv);
+        }
+    }
+
+    // the root search guarantees that there will always be 1 valid move in
+    // the PV, but make sure an illegal move never reaches the GUI in case of
+    // a bug
+    let best_move = report.best_move();
+    debug_assert!(
+        best_move == Move::null() || board.is_legal(best_move),
+        "The search returned the illegal move {best_move}"
+    );
+    let legal_move = legal_or_fallback(&board, best_move);
+    if legal_move != best_move {
+        if search_refs.is_main() {
+            search_refs.output.write_line(format_args!(
+                "info string {best_move} is illegal: playing {legal_move} instead"
+            ));
+        }
+        report.pv.clear();
+        report.pv.enqueue(legal_move);
+    }
+    // the detailed stats leave out what's counted here
+    if search_refs.is_debug() || cfg!(feature = "stats") {
+        search_refs
+            .output
+            .write_line(format_args!("info string pruning: {}", search_refs.stats));
+    }
+    #[cfg(feature = "stats")]
+    search_refs.output.write_line(&search_refs.search_stats);
+
+    // the GUI isn't allowed to be told the best move until it's stopped
+    // pondering or an infinite search, even if the search has nothing left to
+    // do
+    search_refs.wait_for_gui();
+    if search_refs.is_main() {
+        match ponder_move(&board, &report.pv) {
+            Some(ponder) => search_refs
+                .output
+                .write_line(format_args!("bestmove {legal_move} ponder {ponder}")),
+            None => search_refs
+                .output
+                .write_line(format_args!("bestmove {legal_move}")),
+        }
+    }
+
+    // the status may have changed after the report was made
+    report.status = search_refs.status;
+    report
+}
+
+/// Searches `board` with increasing depth until the search is stopped,
+/// reporting `lines` lines each iteration if this is the main thread.
+///
+/// Returns the report of the best line of the last iteration. For helper
+/// threads, this is the last iteration that wasn't stopped early, or [`None`]
+/// if every iteration was.
+fn deepen(
+    search_refs: &mut SearchReferences<'_>,
+    board: &Board,
+    lines: usize,
+) -> Option<SearchReport> {
     let mut pv = Pv::new();
-    let mut best_move;
     let mut depth = 1;
+    // the score of each line in the previous iteration
+    let mut scores = vec![0; lines];
+    // a running average of how much the score of each line has changed
+    // between iterations
+    let mut volatilities = vec![0; lines];
+    // the best move and the depth, nodes and time when it was first found
+    let mut best_move_since = (Move::null(), 0, 0, Duration::ZERO);
+    let mut last_report = None;
+    let mut last_hashfull = 0;
+
+    'iter_deep: loop {
+        // there's no point in a helper searching a depth that's already been
+        // completed, and half of them go one deeper so that they don't all
+        // search the same depth
+        if let (false, Some(shared)) = (search_refs.is_main(), search_refs.shared) {
+            let skipped_depth = shared
+                .completed_depth()
+                .saturating_add(1 + (search_refs.thread_id % 2) as Depth);
+            depth = depth.max(skipped_depth);
+        }
 
-    let report = 'iter_deep: loop {
         search_refs.depth = depth;
         search_refs.seldepth = 0;
         search_refs.status = SearchStatus::Continue;
+        search_refs.excluded_moves.clear();
+        let mut best_report = None;
+
+        for (line, (score, volatility)) in scores.iter_mut().zip(&mut volatilities).enumerate() {
+            search_refs.researches = 0;
+            pv.clear();
+
+            let previous_score = *score;
+            *score = aspiration_loop(search_refs, &mut pv, board, previous_score, *volatility);
+            // a change to or from a mate score says nothing about how much
+            // the score usually moves
+            if !is_mate(previous_score) && !is_mate(*score) {
+                *volatility = Eval::midpoint(*volatility, score.abs_diff(previous_score) as Eval);
+            }
+
+            // a line that was stopped early can't be trusted, but the first
+            // line is needed for the best move
+            if line > 0 && search_refs.status != SearchStatus::Continue {
+                break;
+            }
+
+            let time = search_refs.start.elapsed();
+            let nps = 1_000_000 * search_refs.total_nodes() / time.as_micros().max(1) as u64;
+            let report = SearchReport::new(search_refs, time, nps, *score, pv.clone(), line + 1);
+
+            if search_refs.is_main() {
+                search_refs.warn_if_unvetted(&pv);
+                search_refs.output.write_line(&report);
+                if search_refs.researches > 0 && search_refs.is_debug() {
+                    search_refs.output.write_line(format_args!(
+                        "info string depth {depth} multipv {} researches {}",
+                        line + 1,
+                        search_refs.researches
+                    ));
+                }
+                if line == 0 && search_refs.is_debug() {
+                    search_refs.output.write_line(format_args!(
+                        "info string depth {depth} hashfull {} ({:+})",
+                        report.hashfull,
+                        report.hashfull as i64 - last_hashfull as i64
+                    ));
+                    last_hashfull = report.hashfull;
+                }
+            }
+
+            search_refs.excluded_moves.push(report.best_move());
+            if line == 0 {
+                best_report = Some(report);
+            }
+            if search_refs.status != SearchStatus::Continue {
+                break;
+            }
+        }
+
+        // the first line is always reported
+        let mut report = best_report?;
+        if report.best_move() != best_move_since.0 {
+            best_move_since = (report.best_move(), depth, report.nodes, report.time);
+        }
+        (
+            _,
+            report.best_move_depth,
+            report.best_move_nodes,
+            report.best_move_time,
+        ) = best_move_since;
+
+        if search_refs.status == SearchStatus::Continue {
+            if let Some(shared) = search_refs.shared {
+                shared.complete_depth(depth);
+            }
+            search_refs.root_scores = search_refs
+                .excluded_moves
+                .iter()
+                .copied()
+                .zip(scores.iter().copied())
+                .collect();
+        } else if !search_refs.is_main() {
+            // the score and PV of an unfinished iteration can't be trusted
+            break 'iter_deep last_report;
+        }
+
+        if search_refs.should_stop(&report) {
+            break 'iter_deep Some(report);
+        }
+
+        last_report = Some(report);
+        depth += 1;
+    }
+}
+
+/// Returns the contempt the search should use: none when analysing, since
+/// the evaluation should then be objective.
+const fn contempt(options: &UciOptions) -> Eval {
+    if options.analyse_mode() {
+        0
+    } else {
+        options.contempt()
+    }
+}
+
+/// Returns `mv` if it's legal in `board`, or otherwise the first legal move,
+/// or a null move if there aren't any.
+fn legal_or_fallback(board: &Board, mv: Move) -> Move {
+    if board.is_legal(mv) {
+        return mv;
+    }
+
+    board.legal_moves().next().unwrap_or_else(Move::null)
+}
+
+/// Returns the move in `pv` that's expected to be played in reply to its
+/// best move, if there is one and it's legal.
+fn ponder_move(board: &Board, pv: &Pv) -> Option<Move> {
+    let [best_move, reply, ..] = *pv.moves() else {
+        return None;
+    };
+
+    let mut copy = *board;
+    (copy.make_move(best_move) && copy.is_legal(reply)).then_some(reply)
+}
+
+/// Searches the root node with an aspiration window around `previous_score`,
+/// widening it and searching again until the score falls inside it.
+///
+/// The initial window is wider the more `volatility` says the score has been
+/// moving between iterations and the deeper the search is. Each fail widens
+/// the failing side by half as much again as the last time, and the full
+/// window is only searched after [`ASPIRATION_MAX_FAILS`] fails in a row.
+///
+/// Returns the score of the final search.
+fn aspiration_loop(
+    search_refs: &mut SearchReferences<'_>,
+    pv: &mut Pv,
+    board: &Board,
+    previous_score: Eval,
+    volatility: Eval,
+) -> Eval {
+    let mut delta = search_refs.params.aspiration_window
+        + volatility / ASPIRATION_VOLATILITY_DIVISOR
+        + Eval::from(search_refs.depth) / ASPIRATION_DEPTH_DIVISOR
+        + (search_refs.thread_id % 4) as Eval * ASPIRATION_JITTER;
+    let mut fails = 0;
+    // the previous score isn't stable enough at low depths and mate scores
+    // are too far from anything else to have a useful window around them
+    let (mut alpha, mut beta) =
+        if search_refs.depth >= ASPIRATION_MIN_DEPTH && !is_mate(previous_score) {
+            (
+                previous_score.saturating_sub(delta).max(-INF_EVAL),
+                previous_score.saturating_add(delta),
+            )
+        } else {
+            (-INF_EVAL, INF_EVAL)
+        };
+
+    loop {
+        let score = search::<RootNode>(search_refs, pv, board, alpha, beta, search_refs.depth, 0);
+
+        if search_refs.status != SearchStatus::Continue {
+            return score;
+        }
+
+        // a full window can't be widened any further, which happens when
+        // the root is checkmated
+        let bound = if score <= alpha && alpha > -INF_EVAL {
+            alpha = alpha.saturating_sub(delta).max(-INF_EVAL);
+            Bound::Upper
+        } else if score >= beta && beta < INF_EVAL {
+            beta = beta.saturating_add(delta);
+            Bound::Lower
+        } else {
+            return score;
+        };
+
+        fails += 1;
+        if fails >= ASPIRATION_MAX_FAILS {
+            (alpha, beta) = (-INF_EVAL, INF_EVAL);
+        }
+
+        // let the GUI know what's happening before the research, which can
+        // take a while
+        if search_refs.is_main() {
+            let time = search_refs.start.elapsed();
+            let nps = 1_000_000 * search_refs.total_nodes() / time.as_micros().max(1) as u64;
+            // the earlier lines are excluded, so this is the next one
+            let multipv = search_refs.excluded_moves.len() + 1;
+            let mut report = SearchReport::new(search_refs, time, nps, score, pv.clone(), multipv);
+            report.bound = bound;
+            search_refs.output.write_line(&report);
+        }
+
+        // at least 1, so that tiny windows still grow
+        delta = delta.saturating_add(delta / 2 + 1);
+        search_refs.researches += 1;
+        pv.clear();
+    }
+}
+
+/// Searches `board` with the given limits and blocks until the search is
+/// finished.
+///
+/// `game_history` is the keys of the positions before `board` that it could
+/// still repeat, oldest first, so that the search can see repetitions of
+/// them. It should start after the last irreversible move, which keeps it
+/// short enough to leave room for the search.
+///
+/// Unlike the `go` command, this never reads from stdin: the search can only
+/// be stopped by its limits, so the result is available as soon as this
+/// returns. Useful for scripted testing.
+pub fn search_sync(
+    board: Board,
+    limits: Limits,
+    options: &UciOptions,
+    tt: &TranspositionTable,
+    game_history: &[Key],
+) -> SearchReport {
+    let (_tx, rx) = channel();
+    let uci_rx = Mutex::new(rx);
+    let mut past_zobrists = game_history.iter().copied().collect::<ZobristStack>();
+    past_zobrists.push(board.zobrist());
+
+    iterative_deepening(
+        board,
+        Instant::now(),
+        limits,
+        Vec::new(),
+        false,
+        &uci_rx,
+        &mut past_zobrists,
+        options,
+        tt,
+    )
+}
 
-        let score = search::<RootNode>(
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{mpsc::channel, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{
+        aspiration_loop, contempt, deepen, iterative_deepening, legal_or_fallback, ponder_move,
+        search, search_sync, vote, Depth, Limits, NonPvNode, Pv, PvNode, RootNode,
+        SearchParameters, SearchReferences, SearchReport, SearchStatus, SharedState, Strength,
+        TimeBounds, ASPIRATION_MAX_FAILS,
+    };
+    use crate::{
+        board::{Board, Key},
+        defs::Square,
+        engine::{
+            uci::{Output, UciOptions},
+            ZobristStack,
+        },
+        evaluation::{moves_to_mate, INF_EVAL},
+        lookups::BaseReductions,
+        movegen::Move,
+        transposition_table::{Bound, TranspositionEntry, TranspositionTable},
+    };
+
+    /// Creates the [`SearchReferences`] of an infinite search of `board`.
+    fn search_refs_for(board: &Board) -> SearchReferences<'static> {
+        search_refs_with(board, SearchParameters::default())
+    }
+
+    /// Creates the [`SearchReferences`] of an infinite search of `board` with
+    /// the given parameters.
+    fn search_refs_with(board: &Board, params: SearchParameters) -> SearchReferences<'static> {
+        let mut past_zobrists = ZobristStack::new();
+        past_zobrists.push(board.zobrist());
+        search_refs_after(past_zobrists, params)
+    }
+
+    /// Creates the [`SearchReferences`] of an infinite search with the given
+    /// parameters of the board whose key is last in `past_zobrists`.
+    ///
+    /// What it borrows is leaked so that it can outlive this function, which
+    /// doesn't matter in a test.
+    fn search_refs_after(
+        past_zobrists: ZobristStack,
+        params: SearchParameters,
+    ) -> SearchReferences<'static> {
+        let (_tx, rx) = channel();
+        let uci_rx = Box::leak(Box::new(Mutex::new(rx)));
+        let past_zobrists = Box::leak(Box::new(past_zobrists));
+        let tt = Box::leak(Box::new(TranspositionTable::with_capacity(1)));
+        let base_reductions = Box::leak(Box::new(BaseReductions::new(
+            params.lmr_base,
+            params.lmr_divisor,
+        )));
+        SearchReferences::new(
+            Instant::now(),
+            Limits::Infinite,
+            false,
+            TimeBounds::INFINITE,
+            uci_rx,
+            past_zobrists,
+            tt,
+            params,
+            base_reductions,
+        )
+    }
+
+    /// The outcome of [`run_search()`].
+    struct SearchRun {
+        /// The report of the search.
+        report: SearchReport,
+        /// Everything the search wrote.
+        lines: Vec<String>,
+        /// When each command was sent.
+        sent: Vec<Instant>,
+        /// The commands the search left for the main loop.
+        unread: Vec<String>,
+    }
+
+    /// Runs [`iterative_deepening()`] on `board` with no game history and an
+    /// empty TT.
+    ///
+    /// Each of `commands` is sent after waiting the given number of
+    /// milliseconds since the previous one was sent, or since the search
+    /// started for the first one.
+    fn run_search(
+        board: Board,
+        limits: Limits,
+        search_moves: Vec<Move>,
+        is_pondering: bool,
+        mut options: UciOptions,
+        commands: &[(u64, &str)],
+    ) -> SearchRun {
+        let (tx, rx) = channel();
+        let uci_rx = Mutex::new(rx);
+        let mut past_zobrists = ZobristStack::new();
+        past_zobrists.push(board.zobrist());
+        let tt = TranspositionTable::with_capacity(1);
+        let (output, lines) = Output::buffer();
+        options.set_output(output);
+
+        let (report, sent) = thread::scope(|scope| {
+            let sender = scope.spawn(|| {
+                commands
+                    .iter()
+                    .map(|&(delay, command)| {
+                        thread::sleep(Duration::from_millis(delay));
+                        tx.send(String::from(command))
+                            .expect("The receiver is alive");
+                        Instant::now()
+                    })
+                    .collect()
+            });
+            let report = iterative_deepening(
+                board,
+                Instant::now(),
+                limits,
+                search_moves,
+                is_pondering,
+                &uci_rx,
+                &mut past_zobrists,
+                &options,
+                &tt,
+            );
+            (report, sender.join().expect("The sender shouldn't panic"))
+        });
+
+        let lines = lines.lock().expect("The search has finished").clone();
+        SearchRun {
+            report,
+            lines,
+            sent,
+            unread: uci_rx
+                .into_inner()
+                .expect("Mutex isn't poisoned")
+                .try_iter()
+                .collect(),
+        }
+    }
+
+    /// Searches `board` to `depth` with the given parameters and returns the
+    /// number of nodes searched.
+    fn nodes_searched(board: &Board, depth: Depth, params: SearchParameters) -> u64 {
+        let mut search_refs = search_refs_with(board, params);
+        search_refs.limits = Limits::Depth(depth);
+
+        search::<RootNode>(
             &mut search_refs,
-            &mut pv,
-            &board,
+            &mut Pv::new(),
+            board,
             -INF_EVAL,
             INF_EVAL,
             depth,
             0,
         );
+        search_refs.nodes
+    }
+
+    /// Checks that a synchronous search finds a back-rank mate.
+    #[test]
+    fn search_sync_finds_mate() {
+        let board = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let tt = TranspositionTable::with_capacity(1);
+        let mut limits = Limits::default();
+        limits.set_depth(Some(3));
+
+        let report = search_sync(board, limits, &UciOptions::default(), &tt, &[]);
+
+        assert_eq!(report.best_move().to_string(), "a1a8", "Missed Ra8#");
+    }
+
+    /// Checks that the search sees repetitions of positions from earlier in
+    /// the game: a queen down, Black's only way not to lose is to go back to
+    /// a position that has already occurred.
+    #[test]
+    fn search_sync_game_history() {
+        let board = "7k/8/8/8/8/8/8/3Q2K1 b - - 4 3"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let mut tt = TranspositionTable::with_capacity(1);
+        let mut limits = Limits::default();
+        limits.set_depth(Some(4));
+
+        // Kg8 Qd2 Kh8 Qd1 has just been played from the same position
+        let mut game_history = Vec::new();
+        let mut game_board = board;
+        for mv in ["h8g8", "d1d2", "g8h8", "d2d1"] {
+            game_history.push(game_board.zobrist());
+            let mv = game_board
+                .legal_moves()
+                .iter()
+                .find(|legal_move| legal_move.to_string() == mv)
+                .expect("Legal move");
+            assert!(game_board.make_move(mv), "Legal move");
+        }
+        assert_eq!(
+            game_board.zobrist(),
+            board.zobrist(),
+            "Back where it started"
+        );
+
+        let report = search_sync(board, limits, &UciOptions::default(), &tt, &[]);
+        assert!(report.score < -500, "Without the history, Black is lost");
+
+        tt.clear();
+        let report = search_sync(board, limits, &UciOptions::default(), &tt, &game_history);
+        assert_eq!(
+            report.best_move().to_string(),
+            "h8g8",
+            "Missed the repetition"
+        );
+        assert!(report.score > -100, "The repetition is a draw");
+    }
+
+    /// Checks that `go mate` stops as soon as a short enough mate is found
+    /// instead of searching forever.
+    #[test]
+    fn mate_limit() {
+        let board = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let tt = TranspositionTable::with_capacity(1);
+        let mut limits = Limits::default();
+        limits.set_mate(Some(1));
+
+        let report = search_sync(board, limits, &UciOptions::default(), &tt, &[]);
+
+        assert_eq!(moves_to_mate(report.score), 1, "Missed the mate in 1");
+        assert!(report.depth < 10, "The search should stop early");
+    }
+
+    /// Checks that the search finds a knight under-promotion that forks the
+    /// king and queen.
+    #[test]
+    fn checking_underpromotion() {
+        let board = "8/2q1P3/5k2/8/8/8/8/K6R w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let tt = TranspositionTable::with_capacity(1);
+        let mut limits = Limits::default();
+        limits.set_depth(Some(4));
+
+        let report = search_sync(board, limits, &UciOptions::default(), &tt, &[]);
+
+        assert_eq!(report.best_move().to_string(), "e7e8n", "Missed e8=N+");
+    }
+
+    /// Checks that the info line of a normal search includes `multipv 1`.
+    #[test]
+    fn report_has_multipv() {
+        let tt = TranspositionTable::with_capacity(1);
+        let mut limits = Limits::default();
+        limits.set_depth(Some(2));
+
+        let report = search_sync(Board::default(), limits, &UciOptions::default(), &tt, &[]);
+
+        assert!(
+            report.to_string().contains(" multipv 1 "),
+            "Missing multipv: {report}"
+        );
+    }
+
+    /// Checks that an illegal best move is replaced with a legal one.
+    #[test]
+    fn illegal_best_move_fallback() {
+        let board = Board::default();
+        let illegal = Move::new(Square::E2, Square::E5);
+        let legal = Move::new(Square::E2, Square::E4);
+
+        assert!(
+            legal_or_fallback(&board, legal) == legal,
+            "Legal moves should be kept"
+        );
+        let fallback = legal_or_fallback(&board, illegal);
+        assert!(board.is_legal(fallback), "The fallback should be legal");
+    }
+
+    /// Checks that excluded root moves are never searched, which is what
+    /// `MultiPV` relies on for finding the next-best lines.
+    #[test]
+    fn excluded_root_moves() {
+        // the king on a1 can only go to a2, b1 or b2
+        let board = "7k/8/8/8/8/8/8/K7 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let mut search_refs = search_refs_for(&board);
+        search_refs.limits = Limits::Depth(3);
+        search_refs.depth = 3;
+        search_refs.excluded_moves = vec![
+            Move::new(Square::A1, Square::A2),
+            Move::new(Square::A1, Square::B1),
+        ];
+
+        let mut pv = Pv::new();
+        aspiration_loop(&mut search_refs, &mut pv, &board, 0, 0);
+        assert!(
+            pv.get(0) == Move::new(Square::A1, Square::B2),
+            "Only Kb2 wasn't excluded"
+        );
+    }
+
+    /// Checks that a search with helper threads finishes and finds a mate.
+    #[test]
+    fn helper_threads() {
+        let board = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let mut options = UciOptions::default();
+        options.set_threads(4);
+
+        let run = run_search(board, Limits::Depth(5), Vec::new(), false, options, &[]);
+
+        assert_eq!(run.report.best_move().to_string(), "a1a8", "Missed Ra8#");
+    }
+
+    /// Checks that helpers that are stopped before finishing an iteration
+    /// don't report anything, so the move of the main thread is played.
+    #[test]
+    fn unfinished_helpers() {
+        let board = Board::default();
+        let shared = SharedState::new(false, false);
+        let mut main_refs = search_refs_for(&board);
+        main_refs.limits = Limits::Depth(1);
+        main_refs.shared = Some(&shared);
+        let main_report =
+            deepen(&mut main_refs, &board, 1).expect("The main thread always reports");
+        shared.stop();
 
-        // the root search guarantees that there will always be 1 valid move in
-        // the PV
-        best_move = pv.get(0);
-        let time = search_refs.start.elapsed();
-        let nps = 1_000_000 * search_refs.nodes / time.as_micros().max(1) as u64;
-        let report = SearchReport::new(&search_refs, time, nps, score, pv.clone());
+        let helper_reports = (1..4)
+            .filter_map(|thread_id| {
+                let mut helper_refs = search_refs_for(&board);
+                helper_refs.shared = Some(&shared);
+                helper_refs.thread_id = thread_id;
+                // make the first check of the status happen straight away
+                helper_refs.nodes = SearchReferences::NODES_BETWEEN_CHECKS;
+                deepen(&mut helper_refs, &board, 1)
+            })
+            .collect::<Vec<_>>();
+        assert!(helper_reports.is_empty(), "No helper finished an iteration");
 
-        println!("{report}");
+        let main_best_move = main_report.best_move();
+        assert!(
+            vote(main_report, helper_reports).best_move() == main_best_move,
+            "The move of the main thread should be played"
+        );
+    }
+
+    /// Checks that a `stop` is acted on almost as soon as it's sent.
+    #[test]
+    fn stop_is_prompt() {
+        let run = run_search(
+            Board::default(),
+            Limits::Infinite,
+            Vec::new(),
+            false,
+            UciOptions::default(),
+            &[(200, "stop")],
+        );
+        let stopped = Instant::now();
+
+        assert!(
+            run.report.status == SearchStatus::Stop,
+            "Search should stop"
+        );
+        assert!(
+            stopped.duration_since(run.sent[0]) < Duration::from_millis(100),
+            "The search took too long to stop"
+        );
+    }
+
+    /// Checks that a `quit` during a search stops it without exiting the
+    /// process and still returns a best move.
+    #[test]
+    fn quit_during_search() {
+        let run = run_search(
+            Board::default(),
+            Limits::Infinite,
+            Vec::new(),
+            false,
+            UciOptions::default(),
+            &[(0, "quit")],
+        );
+
+        assert!(
+            run.report.status == SearchStatus::Quit,
+            "Search should quit"
+        );
+        assert!(run.report.best_move() != Move::null(), "Missing best move");
+    }
 
-        if search_refs.should_stop() {
-            break 'iter_deep report;
+    /// Checks that a pondering search ignores its limits until `ponderhit`.
+    #[test]
+    fn ponder() {
+        // give the search time to get past the depth limit
+        let ponder = |command| {
+            run_search(
+                Board::default(),
+                Limits::Depth(1),
+                Vec::new(),
+                true,
+                UciOptions::default(),
+                &[(50, command)],
+            )
+            .report
+        };
+
+        let report = ponder("stop");
+        assert!(report.depth > 1, "Pondering should ignore the depth limit");
+
+        // would never return if the limits weren't restored
+        let report = ponder("ponderhit");
+        assert!(report.status == SearchStatus::Stop, "Search should stop");
+    }
+
+    /// Checks that only the search moves are searched, even with more lines
+    /// than search moves.
+    #[test]
+    fn search_moves() {
+        let mut options = UciOptions::default();
+        options.set_multi_pv(3);
+        let a3 = Move::new(Square::A2, Square::A3);
+
+        let run = run_search(
+            Board::default(),
+            Limits::Depth(3),
+            vec![a3],
+            false,
+            options,
+            &[],
+        );
+
+        assert!(run.report.best_move() == a3, "a3 is the only search move");
+    }
+
+    /// Checks that the lowest Elo doesn't search many more nodes than its
+    /// limit.
+    #[test]
+    fn strength_node_limit() {
+        let tt = TranspositionTable::with_capacity(1);
+        let mut options = UciOptions::default();
+        options.set_limit_strength(true);
+        options.set_elo(800);
+        let mut limits = Limits::default();
+        limits.set_depth(Some(20));
+
+        let report = search_sync(Board::default(), limits, &options, &tt, &[]);
+
+        let max_nodes = Strength::new(800).nodes();
+        assert!(
+            report.nodes <= max_nodes + 10,
+            "Searched {} nodes, more than about {max_nodes}",
+            report.nodes
+        );
+    }
+
+    /// Checks that `MultiPV` only ranks the search moves, and that a search
+    /// move given twice is only reported once.
+    #[test]
+    fn search_moves_multi_pv() {
+        let mut options = UciOptions::default();
+        options.set_multi_pv(3);
+        let e4 = Move::new(Square::E2, Square::E4);
+        let d4 = Move::new(Square::D2, Square::D4);
+
+        let run = run_search(
+            Board::default(),
+            Limits::Depth(3),
+            vec![e4, d4, e4],
+            false,
+            options,
+            &[],
+        );
+
+        let mut multipvs = Vec::new();
+        for line in run
+            .lines
+            .iter()
+            .filter(|line| line.starts_with("info depth 3 "))
+        {
+            let mut tokens = line.split_whitespace();
+            let multipv = tokens.by_ref().skip_while(|&t| t != "multipv").nth(1);
+            let best_move = tokens.skip_while(|&t| t != "pv").nth(1);
+            assert!(
+                best_move == Some("e2e4") || best_move == Some("d2d4"),
+                "Not a search move: {line}"
+            );
+            multipvs.extend(multipv);
         }
+        multipvs.dedup();
+        assert_eq!(multipvs, ["1", "2"], "There should be exactly two lines");
+    }
+
+    /// Checks that a search that runs out of things to do while pondering
+    /// waits for the GUI before returning.
+    #[test]
+    fn ponder_waits_for_gui() {
+        // checkmated, so every iteration is instant
+        let board = "R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let start = Instant::now();
+
+        run_search(
+            board,
+            Limits::Depth(1),
+            Vec::new(),
+            true,
+            UciOptions::default(),
+            &[(50, "ponderhit")],
+        );
+
+        assert!(
+            start.elapsed() >= Duration::from_millis(50),
+            "The search should have waited for ponderhit"
+        );
+    }
+
+    /// Checks that an infinite search that runs out of things to do waits for
+    /// `stop` before returning.
+    #[test]
+    fn infinite_waits_for_stop() {
+        // checkmated, so every iteration is instant
+        let board = "R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let start = Instant::now();
+
+        run_search(
+            board,
+            Limits::Infinite,
+            Vec::new(),
+            false,
+            UciOptions::default(),
+            &[(50, "stop")],
+        );
+
+        assert!(
+            start.elapsed() >= Duration::from_millis(50),
+            "The search should have waited for stop"
+        );
+    }
+
+    /// Checks that contempt makes a draw bad for the engine and good for its
+    /// opponent, unless the GUI is analysing.
+    #[test]
+    fn contempt_draw_score() {
+        let mut options = UciOptions::default();
+        options.set_contempt(20);
+        let mut search_refs = search_refs_for(&Board::default());
+        search_refs.contempt = contempt(&options);
+
+        assert_eq!(search_refs.draw_score(0), -20, "Bad for the engine");
+        assert_eq!(search_refs.draw_score(3), 20, "Good for the opponent");
+
+        options.set_analyse_mode(true);
+        assert_eq!(contempt(&options), 0, "No contempt when analysing");
+    }
+
+    /// Checks that the ponder move is the legal reply in the PV.
+    #[test]
+    fn ponder_move_from_pv() {
+        let board = Board::default();
+        let mut pv = Pv::new();
+        pv.enqueue(Move::new(Square::E2, Square::E4));
+        assert!(ponder_move(&board, &pv).is_none(), "There is no reply");
+
+        pv.enqueue(Move::new(Square::E7, Square::E5));
+        assert!(
+            ponder_move(&board, &pv) == Some(Move::new(Square::E7, Square::E5)),
+            "The reply should be the ponder move"
+        );
 
         pv.clear();
-        depth += 1;
-    };
+        pv.enqueue(Move::new(Square::E2, Square::E4));
+        pv.enqueue(Move::new(Square::E2, Square::E4));
+        assert!(
+            ponder_move(&board, &pv).is_none(),
+            "An illegal reply shouldn't be pondered"
+        );
+    }
 
-    println!("bestmove {best_move}");
+    /// Checks that an `isready` during a search is consumed by the search and
+    /// anything after a `stop` is left for the main loop, so each `isready` is
+    /// answered exactly once.
+    #[test]
+    fn isready_during_search() {
+        let run = run_search(
+            Board::default(),
+            Limits::Infinite,
+            Vec::new(),
+            false,
+            UciOptions::default(),
+            &[(0, "isready"), (0, "stop"), (0, "isready")],
+        );
 
-    if search_refs.check_status() == SearchStatus::Quit {
-        exit(0);
+        assert!(
+            run.report.status == SearchStatus::Stop,
+            "Search should stop"
+        );
+        assert_eq!(
+            run.lines.iter().filter(|&line| line == "readyok").count(),
+            1,
+            "The first isready is for the search"
+        );
+        assert_eq!(
+            run.unread,
+            ["isready"],
+            "The second isready is for the main loop"
+        );
     }
 
-    report
+    /// Checks that stopping the first iteration early is detected and still
+    /// leaves a move in the PV.
+    #[test]
+    fn unvetted_best_move() {
+        let board = Board::default();
+        let mut search_refs = search_refs_for(&board);
+        let mut pv = Pv::new();
+        search_refs.depth = 1;
+        // pretend a `stop` arrived before the search started
+        search_refs.status = SearchStatus::Stop;
+
+        search::<RootNode>(&mut search_refs, &mut pv, &board, -INF_EVAL, INF_EVAL, 1, 0);
+
+        assert!(search_refs.is_best_move_unvetted(), "Fallback not detected");
+        assert_eq!(pv.len(), 1, "The fallback move should be in the PV");
+
+        let (output, lines) = Output::buffer();
+        search_refs.output = output;
+        search_refs.warn_if_unvetted(&pv);
+        assert_eq!(
+            *lines.lock().expect("Nothing else holds the lock"),
+            [format!(
+                "info string depth 1 was stopped early: {} is unvetted",
+                pv.get(0)
+            )],
+            "The GUI should be warned"
+        );
+    }
+
+    /// Checks that searching past the maximum height returns a score instead
+    /// of overflowing.
+    #[test]
+    fn max_height() {
+        let board = Board::default();
+        let mut search_refs = search_refs_for(&board);
+        let mut pv = Pv::new();
+        let height = Depth::MAX - 2;
+
+        let score = search::<PvNode>(
+            &mut search_refs,
+            &mut pv,
+            &board,
+            -INF_EVAL,
+            INF_EVAL,
+            4,
+            height,
+        );
+
+        assert!((-INF_EVAL..=INF_EVAL).contains(&score), "Invalid score");
+    }
+
+    /// Checks that a TT entry stored far from the fifty-move rule doesn't
+    /// cause a cutoff close to it and that nothing is stored close to it.
+    #[test]
+    fn tt_fifty_move_rule() {
+        let fresh = "4k3/8/8/8/8/8/4P3/R3K3 w Q - 0 60"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let stale = "4k3/8/8/8/8/8/4P3/R3K3 w Q - 95 60"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        assert_eq!(fresh.zobrist(), stale.zobrist(), "Same position");
+
+        let mut search_refs = search_refs_for(&stale);
+        let entry =
+            TranspositionEntry::new(fresh.zobrist(), 1000, Move::null(), 10, Bound::Exact, 0);
+        search_refs.tt.store(fresh.zobrist(), entry);
+
+        let score = search::<NonPvNode>(&mut search_refs, &mut Pv::new(), &stale, 0, 1, 1, 1);
+
+        assert_eq!(search_refs.stats.tt_cutoffs, 0, "TT cutoff at 95 halfmoves");
+        assert_ne!(score, 1000, "The stored score was returned");
+
+        let mut search_refs = search_refs_for(&stale);
+        search::<PvNode>(
+            &mut search_refs,
+            &mut Pv::new(),
+            &stale,
+            -INF_EVAL,
+            INF_EVAL,
+            3,
+            1,
+        );
+
+        assert!(
+            search_refs.tt.load(stale.zobrist(), 1).is_none(),
+            "Stored at 95 halfmoves"
+        );
+    }
+
+    /// Checks that a position from earlier in the search that the side to
+    /// move can go back to with one move is found, unless the move is
+    /// blocked, the move is the opponent's or the position is from before the
+    /// root.
+    #[test]
+    fn upcoming_repetition() {
+        // plays `moves` from `fen`, returning the keys of every position and
+        // the final board
+        let play = |fen: &str, moves: &[&str]| {
+            let mut board = fen.parse::<Board>().expect("Valid FEN");
+            let mut past_zobrists = ZobristStack::new();
+            past_zobrists.push(board.zobrist());
+            for &mv in moves {
+                let mv = board
+                    .legal_moves()
+                    .iter()
+                    .find(|legal_move| legal_move.to_string() == mv)
+                    .expect("Legal move");
+                assert!(board.make_move(mv), "Legal move");
+                past_zobrists.push(board.zobrist());
+            }
+            (past_zobrists, board)
+        };
+
+        // Ra4-a1 goes back to the position after Ra1, 3 plies ago
+        let (past_zobrists, board) = play(
+            "4k3/8/8/8/8/8/8/1R2K3 w - - 10 1",
+            &["b1a1", "e8e7", "a1a4", "e7e8"],
+        );
+        let search_refs = search_refs_after(past_zobrists, SearchParameters::default());
+        assert!(
+            search_refs.has_upcoming_repetition(&board, 4),
+            "Ra4-a1 repeats a position after the root"
+        );
+        assert!(
+            !search_refs.has_upcoming_repetition(&board, 2),
+            "The position is from before the root"
+        );
+
+        // the same, but the rook goes round the pawn on a2 and the king goes
+        // round so that no other position can be repeated
+        let (past_zobrists, board) = play(
+            "4k3/8/8/8/8/8/P7/1R2K3 w - - 10 1",
+            &[
+                "b1a1", "e8f7", "a1d1", "f7g7", "d1d4", "g7f8", "d4a4", "f8e8",
+            ],
+        );
+        let search_refs = search_refs_after(past_zobrists, SearchParameters::default());
+        assert!(
+            !search_refs.has_upcoming_repetition(&board, 8),
+            "The pawn on a2 blocks Ra4-a1"
+        );
+
+        // Kd8-e8 would go back to the position after Ra1, but it's White to
+        // move
+        let (past_zobrists, board) = play(
+            "4k3/8/8/8/8/8/8/1R2K3 w - - 10 1",
+            &["b1a1", "e8e7", "a1a2", "e7d7", "a2a1", "d7d8"],
+        );
+        let search_refs = search_refs_after(past_zobrists, SearchParameters::default());
+        assert!(
+            !search_refs.has_upcoming_repetition(&board, 6),
+            "Only Black can go back to the position"
+        );
+    }
+
+    /// Checks that the search doesn't push more keys than the stack of keys
+    /// can hold when the game history already fills most of it.
+    #[test]
+    fn full_key_stack() {
+        let board = Board::default();
+        let mut past_zobrists = ZobristStack::new();
+        while past_zobrists.remaining_capacity() > 4 {
+            past_zobrists.push(past_zobrists.len() as Key);
+        }
+        past_zobrists.push(board.zobrist());
+        let mut search_refs = search_refs_after(past_zobrists, SearchParameters::default());
+        assert_eq!(search_refs.max_height, 3, "Three more keys fit");
+        let mut pv = Pv::new();
+
+        let score =
+            search::<RootNode>(&mut search_refs, &mut pv, &board, -INF_EVAL, INF_EVAL, 6, 0);
+
+        assert!((-INF_EVAL..=INF_EVAL).contains(&score), "Invalid score");
+        assert_eq!(
+            search_refs.past_zobrists.remaining_capacity(),
+            3,
+            "Every pushed key should have been popped"
+        );
+    }
+
+    /// Checks that an aspiration window that's too small causes the root to
+    /// be searched again.
+    #[test]
+    fn aspiration_researches() {
+        let board = Board::default();
+        let params = SearchParameters {
+            aspiration_window: 1,
+            ..SearchParameters::default()
+        };
+        let mut search_refs = search_refs_with(&board, params);
+        search_refs.depth = 5;
+
+        // the startpos is nowhere near +5 pawns
+        let score = aspiration_loop(&mut search_refs, &mut Pv::new(), &board, 500, 0);
+
+        assert!(score < 500, "The score should have fallen below the window");
+        assert!(search_refs.researches > 0, "Missing researches");
+    }
+
+    /// Checks that the initial aspiration window is widened by the
+    /// volatility of the score.
+    #[test]
+    fn aspiration_volatility() {
+        let board = Board::default();
+        let params = SearchParameters {
+            aspiration_window: 1,
+            ..SearchParameters::default()
+        };
+
+        // the startpos is nowhere near +1 pawn, but is within 2 pawns of it
+        let researches = |volatility| {
+            let mut search_refs = search_refs_with(&board, params);
+            search_refs.depth = 5;
+            aspiration_loop(&mut search_refs, &mut Pv::new(), &board, 100, volatility);
+            search_refs.researches
+        };
+
+        assert!(researches(0) > 0, "A stable score should fail");
+        assert_eq!(researches(400), 0, "A volatile score shouldn't fail");
+    }
+
+    /// Checks that the full window is searched after
+    /// [`ASPIRATION_MAX_FAILS`] fails in a row.
+    #[test]
+    fn aspiration_max_fails() {
+        let board = Board::default();
+        let params = SearchParameters {
+            aspiration_window: 1,
+            ..SearchParameters::default()
+        };
+        let mut search_refs = search_refs_with(&board, params);
+        search_refs.depth = 5;
+
+        // the window would have to be widened far more than 6 times to reach
+        // the real score from here
+        let score = aspiration_loop(&mut search_refs, &mut Pv::new(), &board, 5000, 0);
+
+        assert!(
+            score < 5000,
+            "The score should have fallen below the window"
+        );
+        assert_eq!(
+            search_refs.researches, ASPIRATION_MAX_FAILS,
+            "The window should have been dropped after the last fail"
+        );
+    }
+
+    /// Checks that a minimum null move depth that is never reached disables
+    /// null move pruning.
+    #[test]
+    fn nmp_min_depth_disables_nmp() {
+        let board = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let params = SearchParameters::default();
+        let disabled = SearchParameters {
+            nmp_min_depth: Depth::MAX,
+            ..params
+        };
+        let unreachable = SearchParameters {
+            nmp_min_depth: 100,
+            ..params
+        };
+
+        let nmp_nodes = nodes_searched(&board, 6, params);
+        let disabled_nodes = nodes_searched(&board, 6, disabled);
+
+        assert_eq!(
+            disabled_nodes,
+            nodes_searched(&board, 6, unreachable),
+            "NMP should be disabled in both searches"
+        );
+        assert!(
+            nmp_nodes < disabled_nodes,
+            "NMP should reduce the node count: {nmp_nodes} vs {disabled_nodes}"
+        );
+    }
+
+    /// Checks that `ProbCut` cuts off some nodes in a position full of
+    /// captures.
+    #[test]
+    fn probcut_cutoffs() {
+        let board = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let mut search_refs = search_refs_for(&board);
+
+        search::<RootNode>(
+            &mut search_refs,
+            &mut Pv::new(),
+            &board,
+            -INF_EVAL,
+            INF_EVAL,
+            10,
+            0,
+        );
+
+        assert!(
+            search_refs.stats.probcut_cutoffs > 0,
+            "ProbCut should have cut off at least one node"
+        );
+    }
 }
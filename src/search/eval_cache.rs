@@ -0,0 +1,92 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    board::{Board, Key},
+    evaluation::{evaluate, pawns::PawnTable, Eval},
+};
+
+/// The number of entries in an [`EvalCache`]. Must be a power of 2.
+const EVAL_CACHE_SIZE: usize = 1 << 14;
+
+/// An entry of an [`EvalCache`].
+#[derive(Clone, Copy)]
+struct EvalEntry {
+    /// The key of the board the entry was stored from.
+    key: Key,
+    /// The static evaluation of that board.
+    eval: Eval,
+}
+
+/// A cache of static evaluations, indexed by the key of the board.
+///
+/// The same positions are often evaluated more than once in a search, such as
+/// by null move pruning and then again by the quiescence search.
+pub struct EvalCache {
+    /// The entries of the cache.
+    entries: Vec<EvalEntry>,
+}
+
+impl EvalCache {
+    /// Creates a new, empty [`EvalCache`].
+    pub fn new() -> Self {
+        // a key of 0 marks an empty entry: a real board having that key is
+        // vanishingly unlikely
+        Self {
+            entries: vec![EvalEntry { key: 0, eval: 0 }; EVAL_CACHE_SIZE],
+        }
+    }
+
+    /// Returns the static evaluation of `board`, calculating and storing it
+    /// if it isn't in the cache.
+    pub fn probe(&mut self, board: &Board, pawn_table: &mut PawnTable) -> Eval {
+        let key = board.zobrist();
+        let index = key as usize & (EVAL_CACHE_SIZE - 1);
+        let entry = &mut self.entries[index];
+        if entry.key != key {
+            *entry = EvalEntry {
+                key,
+                eval: evaluate(board, pawn_table),
+            };
+        }
+        entry.eval
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EvalCache;
+    use crate::{
+        board::Board,
+        evaluation::{evaluate, pawns::PawnTable},
+    };
+
+    /// Checks that the cached evaluation is the same as the evaluation.
+    #[test]
+    fn probe() {
+        let board = "r1bqkbnr/pppp1ppp/2n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let mut cache = EvalCache::new();
+        let mut pawn_table = PawnTable::new();
+        let eval = evaluate(&board, &mut pawn_table);
+
+        assert_eq!(cache.probe(&board, &mut pawn_table), eval, "First probe");
+        assert_eq!(cache.probe(&board, &mut pawn_table), eval, "Cached probe");
+    }
+}
@@ -0,0 +1,252 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::Depth;
+use crate::{
+    board::Board,
+    defs::{Piece, PieceType, Side, Square},
+    evaluation::Eval,
+    movegen::Move,
+    util::get_unchecked,
+};
+
+/// Scores of moves, based on how often they've caused a beta cutoff.
+///
+/// There are two tables for quiet moves: the butterfly table, indexed by side
+/// and the start and end squares of the move, and the main table, indexed by
+/// the moving piece and the end square. Quiet moves are ordered by the average
+/// of the two. Captures have a table of their own, indexed by the moving
+/// piece, the end square and the type of the captured piece.
+pub struct Histories {
+    /// Indexed by side, start square and end square.
+    butterfly: [[[Eval; Square::TOTAL]; Square::TOTAL]; Side::TOTAL],
+    /// Indexed by piece and end square.
+    piece_to: [[Eval; Square::TOTAL]; Piece::TOTAL],
+    /// Indexed by piece, end square and captured piece type, with an extra
+    /// entry for [`PieceType::NONE`] (en passant and promotions).
+    capture: [[[Eval; PieceType::TOTAL + 1]; Square::TOTAL]; Piece::TOTAL],
+}
+
+/// The maximum absolute value of an entry in any table.
+pub const MAX_HISTORY: Eval = 4_000;
+
+impl Histories {
+    /// Creates new [`Histories`] with every entry set to 0.
+    #[allow(clippy::large_stack_arrays)]
+    pub const fn new() -> Self {
+        Self {
+            butterfly: [[[0; Square::TOTAL]; Square::TOTAL]; Side::TOTAL],
+            piece_to: [[0; Square::TOTAL]; Piece::TOTAL],
+            capture: [[[0; PieceType::TOTAL + 1]; Square::TOTAL]; Piece::TOTAL],
+        }
+    }
+
+    /// Returns the capture history of the capture `mv` in `board`.
+    pub fn capture_score(&self, board: &Board, mv: Move) -> Eval {
+        let piece = get_unchecked(&self.capture, board.piece_on(mv.start()).to_index());
+        let end = get_unchecked(piece, mv.end().to_index());
+        *get_unchecked(end, PieceType::from(board.piece_on(mv.end())).to_index())
+    }
+
+    /// Returns the score of the quiet move `mv` in `board`: the average of its
+    /// butterfly and main history.
+    pub fn score(&self, board: &Board, mv: Move) -> Eval {
+        self.butterfly_score(board, mv)
+            .midpoint(self.piece_to_score(board, mv))
+    }
+
+    /// Rewards the quiet move `best_move` for causing a beta cutoff at the
+    /// given depth and punishes the other quiet moves in `quiets` for not
+    /// doing so.
+    ///
+    /// `quiets` may contain `best_move`.
+    pub fn update<T>(&mut self, board: &Board, best_move: Move, quiets: T, depth: Depth)
+    where
+        T: IntoIterator<Item = Move>,
+    {
+        let bonus = bonus(depth);
+        self.update_move(board, best_move, bonus);
+        for mv in quiets.into_iter().filter(|&mv| mv != best_move) {
+            self.update_move(board, mv, -bonus);
+        }
+    }
+
+    /// Rewards the capture `best_move` for causing a beta cutoff at the given
+    /// depth and punishes the other captures in `captures` for not doing so.
+    ///
+    /// `captures` may contain `best_move`.
+    pub fn update_captures<T>(&mut self, board: &Board, best_move: Move, captures: T, depth: Depth)
+    where
+        T: IntoIterator<Item = Move>,
+    {
+        let bonus = bonus(depth);
+        self.update_capture(board, best_move, bonus);
+        for mv in captures.into_iter().filter(|&mv| mv != best_move) {
+            self.update_capture(board, mv, -bonus);
+        }
+    }
+
+    /// Returns the butterfly history of `mv`.
+    fn butterfly_score(&self, board: &Board, mv: Move) -> Eval {
+        let side = get_unchecked(&self.butterfly, board.side_to_move().to_index());
+        let start = get_unchecked(side, mv.start().to_index());
+        *get_unchecked(start, mv.end().to_index())
+    }
+
+    /// Returns the main history of `mv`.
+    fn piece_to_score(&self, board: &Board, mv: Move) -> Eval {
+        let piece = get_unchecked(&self.piece_to, board.piece_on(mv.start()).to_index());
+        *get_unchecked(piece, mv.end().to_index())
+    }
+
+    /// Adds `bonus` to both histories of `mv`.
+    fn update_move(&mut self, board: &Board, mv: Move, bonus: Eval) {
+        let side = board.side_to_move().to_index();
+        let piece = board.piece_on(mv.start()).to_index();
+        let (start, end) = (mv.start().to_index(), mv.end().to_index());
+
+        if let Some(entry) = self
+            .butterfly
+            .get_mut(side)
+            .and_then(|side| side.get_mut(start))
+            .and_then(|start| start.get_mut(end))
+        {
+            apply_bonus(entry, bonus);
+        }
+        if let Some(entry) = self
+            .piece_to
+            .get_mut(piece)
+            .and_then(|piece| piece.get_mut(end))
+        {
+            apply_bonus(entry, bonus);
+        }
+    }
+
+    /// Adds `bonus` to the capture history of `mv`.
+    fn update_capture(&mut self, board: &Board, mv: Move, bonus: Eval) {
+        let piece = board.piece_on(mv.start()).to_index();
+        let captured = PieceType::from(board.piece_on(mv.end())).to_index();
+
+        if let Some(entry) = self
+            .capture
+            .get_mut(piece)
+            .and_then(|piece| piece.get_mut(mv.end().to_index()))
+            .and_then(|end| end.get_mut(captured))
+        {
+            apply_bonus(entry, bonus);
+        }
+    }
+}
+
+/// Calculates the bonus of a move that caused a cutoff at the given depth.
+fn bonus(depth: Depth) -> Eval {
+    // squaring a high depth would overflow an `Eval`
+    let depth = i32::from(depth);
+    (depth * depth * 16).min(1_200) as Eval
+}
+
+/// Adds `bonus` to `entry`, scaled down the closer `entry` is to
+/// [`MAX_HISTORY`] in the same direction so that it never exceeds it.
+fn apply_bonus(entry: &mut Eval, bonus: Eval) {
+    let scaled = i32::from(*entry) * i32::from(bonus).abs() / i32::from(MAX_HISTORY);
+    // `bonus` never exceeds `MAX_HISTORY`, so `entry` stays within
+    // `±MAX_HISTORY`
+    #[allow(clippy::cast_possible_truncation)]
+    let scaled = scaled as Eval;
+    *entry += bonus - scaled;
+}
+
+#[cfg(test)]
+mod test {
+    use super::Histories;
+    use crate::board::Board;
+
+    /// Checks that a quiet cutoff updates both tables and that the score is
+    /// the average of the two.
+    #[test]
+    fn update_both_tables() {
+        let board = Board::default();
+        let e2 = "e2".parse().expect("Valid square");
+        let e4 = "e4".parse().expect("Valid square");
+        let d2 = "d2".parse().expect("Valid square");
+        let d4 = "d4".parse().expect("Valid square");
+        let e2e4 = board
+            .legal_moves_from(e2)
+            .move_with(e2, e4)
+            .expect("e4 is legal");
+        let d2d4 = board
+            .legal_moves_from(d2)
+            .move_with(d2, d4)
+            .expect("d4 is legal");
+        let mut histories = Histories::new();
+
+        histories.update(&board, e2e4, [d2d4, e2e4], 4);
+
+        let butterfly = histories.butterfly_score(&board, e2e4);
+        let piece_to = histories.piece_to_score(&board, e2e4);
+        assert!(butterfly > 0, "Butterfly history should be rewarded");
+        assert!(piece_to > 0, "Main history should be rewarded");
+        assert_eq!(
+            histories.score(&board, e2e4),
+            butterfly.midpoint(piece_to),
+            "Score should be the average"
+        );
+        assert!(
+            histories.score(&board, d2d4) < 0,
+            "Other quiets should be punished"
+        );
+    }
+
+    /// Checks that a capture cutoff rewards the capture and punishes the
+    /// other captures.
+    #[test]
+    fn update_captures() {
+        // the knight on c3 can take on b5 or d5
+        let board = "4k3/8/8/1p1p4/8/2N5/8/4K3 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let c3 = "c3".parse().expect("Valid square");
+        let b5 = "b5".parse().expect("Valid square");
+        let d5 = "d5".parse().expect("Valid square");
+        let other_capture = board
+            .legal_moves_from(c3)
+            .move_with(c3, b5)
+            .expect("Nxb5 is legal");
+        let best_capture = board
+            .legal_moves_from(c3)
+            .move_with(c3, d5)
+            .expect("Nxd5 is legal");
+        let mut histories = Histories::new();
+
+        histories.update_captures(&board, best_capture, [other_capture, best_capture], 4);
+
+        assert!(
+            histories.capture_score(&board, best_capture) > 0,
+            "The cutoff capture should be rewarded"
+        );
+        assert!(
+            histories.capture_score(&board, other_capture) < 0,
+            "Other captures should be punished"
+        );
+        assert_eq!(
+            histories.score(&board, best_capture),
+            0,
+            "The quiet histories should be untouched"
+        );
+    }
+}
@@ -17,17 +17,29 @@
  */
 
 use super::{
-    movepick::MovePicker, Depth, Node, NonPvNode, Pv, PvNode, SearchReferences, SearchStatus,
+    movepick::{AllMovesPicker, MovePicker},
+    params::SearchParameters,
+    Depth, Node, NonPvNode, Pv, PvNode, SearchReferences, SearchStatus,
 };
 use crate::{
     board::Board,
     defs::MoveType,
-    evaluation::{evaluate, mate_in, mated_in, Eval, DRAW, INF_EVAL},
-    lookups::base_reductions,
-    movegen::Move,
+    evaluation::{is_mate, mate_in, mated_in, Eval, INF_EVAL, MATE_BOUND},
+    lookups::BaseReductions,
+    movegen::{generate_quiet_checks, Move, Moves, MAX_LEGAL_MOVES},
     transposition_table::{Bound, TranspositionEntry, TranspositionHit},
+    util::Stack,
 };
 
+/// The number of halfmoves from which TT cutoffs are no longer taken and
+/// scores are no longer stored in the TT.
+///
+/// A stored score could be from the same position with more halfmoves to go
+/// before the fifty-move rule, so it might be a win that's actually a draw,
+/// and a score from close to the fifty-move rule might be a draw that's
+/// actually a win with more halfmoves to go.
+const TT_MAX_HALFMOVES: u8 = 90;
+
 /// Performs a search on `board`.
 ///
 /// Returns the evaluation of after searching to the given depth. If `NodeType`
@@ -39,16 +51,23 @@ pub fn search<NodeType: Node>(
     board: &Board,
     mut alpha: Eval,
     mut beta: Eval,
-    depth: Depth,
+    mut depth: Depth,
     height: Depth,
 ) -> Eval {
+    // the PV or the stack of keys can't hold any more
+    if height >= search_refs.max_height {
+        return search_refs.evaluate(board);
+    }
+
     if depth == 0 {
-        return quiescence_search(search_refs, board, alpha, beta, height);
+        return quiescence_search(search_refs, board, alpha, beta, height, true);
     }
 
     let is_in_check = board.is_in_check();
     search_refs.seldepth = search_refs.seldepth.max(height);
     search_refs.nodes += 1;
+    #[cfg(feature = "stats")]
+    search_refs.search_stats.add_node(NodeType::IS_PV);
 
     if !NodeType::IS_ROOT {
         // mate distance pruning
@@ -62,39 +81,227 @@ pub fn search<NodeType: Node>(
             return alpha;
         }
 
-        // draw by repetition or 50mr
-        if search_refs.is_draw(board.halfmoves()) {
-            return DRAW;
+        // draw by repetition, 50mr or insufficient material
+        let draw_score = search_refs.draw_score(height);
+        if search_refs.is_draw(board.halfmoves()) || board.is_insufficient_material() {
+            return draw_score;
+        }
+
+        // upcoming repetition detection
+        // if we can repeat a position with our next move, we can get at
+        // least a draw
+        if alpha < draw_score && search_refs.has_upcoming_repetition(board, height) {
+            alpha = draw_score;
+            if alpha >= beta {
+                return alpha;
+            }
         }
     }
 
     // load from tt
     let tt_hit = search_refs.tt.load(board.zobrist(), height);
+    #[cfg(feature = "stats")]
+    search_refs.search_stats.add_tt_probe(tt_hit.is_some());
+    // repetitions have already been returned as draws above, so a position
+    // from earlier in the game never gets this far
     if let Some(h) = tt_hit {
         if !NodeType::IS_PV
+            && board.halfmoves() < TT_MAX_HALFMOVES
             && h.depth() >= depth
             && (h.bound() == Bound::Exact
                 || h.bound() == Bound::Lower && h.score() >= beta
                 || h.bound() == Bound::Upper && h.score() <= alpha)
         {
+            search_refs.stats.tt_cutoffs += 1;
             return h.score();
         }
     }
 
+    // internal iterative reduction (IIR)
+    // without a TT move, the moves are ordered much worse and this node
+    // probably wasn't worth searching before, so spend less time on it. The
+    // reduced search will store a move for the next iteration.
+    if !NodeType::IS_ROOT
+        && depth >= search_refs.params.iir_min_depth
+        && tt_hit.map_or(Move::null(), TranspositionHit::mv) == Move::null()
+    {
+        depth -= 1;
+    }
+
+    let mut new_pv = Pv::new();
+
+    // null move pruning (NMP)
+    // if we can skip our turn and our opponent still can't stop us from
+    // exceeding beta with a reduced search, our position is probably so good
+    // that a full search would also exceed beta. This doesn't hold in
+    // zugzwang, which is why we need some pieces other than pawns and at high
+    // depths we verify the cutoff with a normal search.
+    if !NodeType::IS_PV
+        && !is_in_check
+        && depth >= search_refs.params.nmp_min_depth
+        && height >= search_refs.nmp_min_height
+        && board.non_pawn_material(board.side_to_move()) > 0
+        && search_refs.evaluate(board) >= beta
+    {
+        let reduction = search_refs.params.nmp_base_reduction
+            + depth / search_refs.params.nmp_reduction_divisor;
+        let null_depth = depth.saturating_sub(reduction);
+        let mut copy = *board;
+        copy.make_null_move();
+        search_refs.past_zobrists.push(copy.zobrist());
+
+        let score = -search::<NonPvNode>(
+            search_refs,
+            &mut new_pv,
+            &copy,
+            -beta,
+            -beta + 1,
+            null_depth,
+            height + 1,
+        );
+
+        search_refs.past_zobrists.pop();
+
+        if search_refs.check_status() != SearchStatus::Continue {
+            return 0;
+        }
+
+        search_refs.stats.null_move_tries += 1;
+
+        if score >= beta {
+            // don't trust unproven mates
+            let score = if is_mate(score) { beta } else { score };
+
+            if depth < search_refs.params.nmp_verification_depth {
+                search_refs.stats.null_move_cutoffs += 1;
+                return score;
+            }
+
+            // don't allow null moves for the first part of the verification
+            // search
+            let old_min_height = search_refs.nmp_min_height;
+            search_refs.nmp_min_height = height + null_depth - null_depth / 4;
+            let verified_score = search::<NonPvNode>(
+                search_refs,
+                &mut new_pv,
+                board,
+                beta - 1,
+                beta,
+                null_depth,
+                height,
+            );
+            search_refs.nmp_min_height = old_min_height;
+
+            if verified_score >= beta {
+                search_refs.stats.null_move_cutoffs += 1;
+                return score;
+            }
+        }
+        new_pv.clear();
+    }
+
+    // ProbCut
+    // if a capture scores well above beta in a reduced search, a full search
+    // would very probably still exceed beta. Captures that can't win enough
+    // material to get there according to SEE are skipped, and a quiescence
+    // search filters out the rest cheaply before the reduced search.
+    let probcut_beta = beta.saturating_add(search_refs.params.probcut_margin);
+    if !NodeType::IS_PV
+        && !is_in_check
+        && depth >= search_refs.params.probcut_min_depth
+        && !is_mate(beta)
+        // the table says a reduced search wouldn't reach the raised beta
+        && !tt_hit.is_some_and(|h| {
+            h.depth().saturating_add(search_refs.params.probcut_reduction) >= depth
+                && h.score() < probcut_beta
+        })
+    {
+        let threshold = probcut_beta - search_refs.evaluate(board);
+        // the depth of the children, so this node is searched to 1 more
+        let probcut_depth = depth.saturating_sub(search_refs.params.probcut_reduction);
+        let movepicker = MovePicker::new_see_captures(board, threshold, &search_refs.histories);
+        for mv in movepicker {
+            let mut copy = *board;
+            if !copy.make_move(mv) {
+                continue;
+            }
+            search_refs.past_zobrists.push(copy.zobrist());
+
+            let mut score = -quiescence_search(
+                search_refs,
+                &copy,
+                -probcut_beta,
+                -probcut_beta + 1,
+                height + 1,
+                false,
+            );
+            if score >= probcut_beta {
+                score = -search::<NonPvNode>(
+                    search_refs,
+                    &mut new_pv,
+                    &copy,
+                    -probcut_beta,
+                    -probcut_beta + 1,
+                    probcut_depth,
+                    height + 1,
+                );
+            }
+
+            search_refs.past_zobrists.pop();
+
+            if search_refs.check_status() != SearchStatus::Continue {
+                return 0;
+            }
+
+            if score >= probcut_beta {
+                search_refs.stats.probcut_cutoffs += 1;
+                let tt_entry = TranspositionEntry::new(
+                    board.zobrist(),
+                    score,
+                    mv,
+                    probcut_depth + 1,
+                    Bound::Lower,
+                    height,
+                );
+                if board.halfmoves() < TT_MAX_HALFMOVES {
+                    search_refs.tt.store(board.zobrist(), tt_entry);
+                }
+                return score;
+            }
+        }
+        new_pv.clear();
+    }
+
     let mut best_score = -INF_EVAL;
     let mut best_move = Move::null();
-    let mut new_pv = Pv::new();
-    let movepicker = MovePicker::new::<{ MoveType::ALL }>(
-        board,
-        tt_hit.map_or(Move::null(), TranspositionHit::mv),
-    );
+    let mut movepicker =
+        AllMovesPicker::new(board, tt_hit.map_or(Move::null(), TranspositionHit::mv));
 
     let mut total_moves: u8 = 0;
-    for mv in movepicker {
+    let mut quiets = Stack::<Move, MAX_LEGAL_MOVES>::new();
+    let mut captures = Stack::<Move, MAX_LEGAL_MOVES>::new();
+    while let Some(mv) = movepicker.next(board, &search_refs.histories) {
+        if NodeType::IS_ROOT && search_refs.is_root_move_skipped(mv) {
+            continue;
+        }
+
+        // SEE pruning
+        // at low depths, a capture that loses a lot of material is very
+        // unlikely to be better than a move we've already searched
+        if !NodeType::IS_PV
+            && !is_in_check
+            && best_score > -MATE_BOUND
+            && is_see_pruned(board, mv, depth, &search_refs.params)
+        {
+            search_refs.stats.see_pruned += 1;
+            continue;
+        }
+
         let mut copy = *board;
         if !copy.make_move(mv) {
             continue;
         }
+        search_refs.tt.prefetch(copy.zobrist());
         search_refs.past_zobrists.push(copy.zobrist());
         total_moves += 1;
 
@@ -104,7 +311,9 @@ pub fn search<NodeType: Node>(
         }
 
         if NodeType::IS_ROOT && search_refs.should_print() {
-            println!("info currmovenumber {total_moves} currmove {mv}");
+            search_refs.output.write_line(format_args!(
+                "info currmovenumber {total_moves} currmove {mv}"
+            ));
         }
 
         let extension = extension(is_in_check);
@@ -123,7 +332,13 @@ pub fn search<NodeType: Node>(
         // then exceeds alpha, then great: we've found a better move.)
         let mut score = 0;
         if !NodeType::IS_PV || total_moves > 1 {
-            let reduction = reduction(depth, total_moves);
+            // promotions that give check are too forcing to reduce, even
+            // if they're under-promotions
+            let reduction = if mv.is_promotion() && copy.is_in_check() {
+                0
+            } else {
+                reduction(search_refs.base_reductions, depth, total_moves)
+            };
 
             score = -search::<NonPvNode>(
                 search_refs,
@@ -135,6 +350,10 @@ pub fn search<NodeType: Node>(
                 height + 1,
             );
 
+            #[cfg(feature = "stats")]
+            if reduction > 0 {
+                search_refs.search_stats.add_lmr(score > alpha);
+            }
             if score > alpha && reduction > 0 {
                 score = -search::<NonPvNode>(
                     search_refs,
@@ -167,10 +386,18 @@ pub fn search<NodeType: Node>(
             return if NodeType::IS_ROOT { alpha } else { 0 };
         }
 
+        if board.is_quiet(mv) {
+            quiets.push(mv);
+        } else {
+            captures.push(mv);
+        }
+
         best_score = best_score.max(score);
 
         if NodeType::IS_ROOT && search_refs.should_print() {
-            println!("info currmovenumber {total_moves} currmove {mv} currscore {score} bestscore {best_score}");
+            search_refs.output.write_line(format_args!(
+                "info currmovenumber {total_moves} currmove {mv} currscore {score} bestscore {best_score}"
+            ));
         }
 
         // the move is even better than what we originally had
@@ -205,10 +432,25 @@ pub fn search<NodeType: Node>(
         return if board.is_in_check() {
             mated_in(height)
         } else {
-            DRAW
+            search_refs.draw_score(height)
         };
     }
 
+    if best_score >= beta {
+        // the loop stops at the move that caused the cutoff
+        #[cfg(feature = "stats")]
+        search_refs.search_stats.add_cutoff(total_moves);
+        if board.is_quiet(best_move) {
+            search_refs
+                .histories
+                .update(board, best_move, quiets.iter(), depth);
+        } else {
+            search_refs
+                .histories
+                .update_captures(board, best_move, captures.iter(), depth);
+        }
+    }
+
     // store into tt
     let bound = if best_score >= beta {
         Bound::Lower
@@ -218,9 +460,11 @@ pub fn search<NodeType: Node>(
     } else {
         Bound::Exact
     };
-    let tt_entry =
-        TranspositionEntry::new(board.zobrist(), best_score, best_move, depth, bound, height);
-    search_refs.tt.store(board.zobrist(), tt_entry);
+    if board.halfmoves() < TT_MAX_HALFMOVES {
+        let tt_entry =
+            TranspositionEntry::new(board.zobrist(), best_score, best_move, depth, bound, height);
+        search_refs.tt.store(board.zobrist(), tt_entry);
+    }
 
     best_score
 }
@@ -228,22 +472,30 @@ pub fn search<NodeType: Node>(
 /// Performs a search that only considers captures and uses a static evaluation
 /// at the leaf nodes.
 ///
-/// This should be called at the leaf nodes of the main search.
+/// If `are_checks_included` is set, quiet moves that give check are also
+/// searched. This should be called at the leaf nodes of the main search.
 fn quiescence_search(
     search_refs: &mut SearchReferences<'_>,
     board: &Board,
     mut alpha: Eval,
     beta: Eval,
     height: Depth,
+    are_checks_included: bool,
 ) -> Eval {
     search_refs.seldepth = search_refs.seldepth.max(height);
     search_refs.nodes += 1;
+    #[cfg(feature = "stats")]
+    search_refs.search_stats.add_quiescence_node();
+
+    if height >= search_refs.max_height {
+        return search_refs.evaluate(board);
+    }
 
     let is_in_check = board.is_in_check();
     let mut best_score = if is_in_check {
         mated_in(height)
     } else {
-        evaluate(board)
+        search_refs.evaluate(board)
     };
 
     alpha = alpha.max(best_score);
@@ -252,26 +504,49 @@ fn quiescence_search(
     }
 
     let movepicker = if is_in_check {
-        MovePicker::new::<{ MoveType::EVASIONS }>(board, Move::null())
+        MovePicker::new::<{ MoveType::EVASIONS }>(board, Move::null(), &search_refs.histories)
+    } else {
+        // captures that lose material are very unlikely to raise alpha
+        MovePicker::new_see_captures(board, 0, &search_refs.histories)
+    };
+    // searching quiet checks any deeper than the first ply makes the search
+    // explode, and evasions already include every quiet move worth searching
+    let quiet_checks = if are_checks_included && !is_in_check {
+        generate_quiet_checks(board)
     } else {
-        MovePicker::new::<{ MoveType::CAPTURES }>(board, Move::null())
+        Moves::new()
     };
 
-    for mv in movepicker {
+    let mut captures = Stack::<Move, MAX_LEGAL_MOVES>::new();
+    // checks that just lose the piece are very unlikely to lead anywhere
+    for mv in movepicker.chain(quiet_checks.filter_see_ge(board, 0)) {
         let mut copy = *board;
         if !copy.make_move(mv) {
             continue;
         }
 
-        let score = -quiescence_search(search_refs, &copy, -beta, -alpha, height + 1);
+        let score = -quiescence_search(search_refs, &copy, -beta, -alpha, height + 1, false);
 
         if search_refs.check_status() != SearchStatus::Continue {
             return 0;
         }
 
+        // evasions can be quiet, but the quiet histories are left to the main
+        // search
+        let is_capture = !board.is_quiet(mv);
+        if is_capture {
+            captures.push(mv);
+        }
+
         best_score = best_score.max(score);
         alpha = alpha.max(score);
         if alpha >= beta {
+            if is_capture {
+                // there's no depth here, so it counts as the shallowest
+                search_refs
+                    .histories
+                    .update_captures(board, mv, captures.iter(), 1);
+            }
             return alpha;
         }
     }
@@ -289,11 +564,47 @@ const fn extension(is_in_check: bool) -> Depth {
     extension
 }
 
+/// Checks if `mv` is a capture that loses too much material to be worth
+/// searching at the given depth.
+fn is_see_pruned(board: &Board, mv: Move, depth: Depth, params: &SearchParameters) -> bool {
+    depth <= params.see_pruning_depth
+        && board.is_capture(mv)
+        && !board.see_ge(mv, -params.see_capture_margin * Eval::from(depth))
+}
+
 /// Calculates how much to reduce the search by during late move reductions.
-fn reduction(depth: Depth, total_moves: u8) -> Depth {
+fn reduction(base_reductions: &BaseReductions, depth: Depth, total_moves: u8) -> Depth {
     if depth >= 3 && total_moves >= 3 {
-        base_reductions(depth, total_moves)
+        base_reductions.get(depth, total_moves)
     } else {
         0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::is_see_pruned;
+    use crate::{board::Board, search::params::SearchParameters};
+
+    /// Checks that a capture losing a queen for a pawn is pruned at low
+    /// depths but not at high depths.
+    #[test]
+    fn see_pruning() {
+        let board = "4k3/3p4/4p3/8/8/8/8/4QK2 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let params = SearchParameters::default();
+        let e1 = "e1".parse().expect("Valid square");
+        let e6 = "e6".parse().expect("Valid square");
+        let mv = board
+            .legal_moves_from(e1)
+            .move_with(e1, e6)
+            .expect("Qxe6 is legal");
+
+        assert!(is_see_pruned(&board, mv, 1, &params), "Qxe6 at depth 1");
+        assert!(
+            !is_see_pruned(&board, mv, params.see_pruning_depth + 1, &params),
+            "Qxe6 past the maximum depth"
+        );
+    }
+}
@@ -16,8 +16,9 @@
  * Crab. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::cmp::Ordering;
+use std::{cmp::Ordering, mem};
 
+use super::histories::Histories;
 use crate::{
     board::Board,
     defs::{MoveType, PieceType},
@@ -32,7 +33,44 @@ pub struct MovePicker {
     moves: ScoredMoves,
 }
 
+/// A selector of the next best move out of every move in a position, which
+/// only generates each kind of move once it's reached.
+///
+/// The TT move comes first, then the captures that don't lose material, then
+/// the quiet moves and finally the captures that do. A cutoff from the TT
+/// move means no moves are generated at all.
+pub struct AllMovesPicker {
+    /// The stage the picker is at.
+    stage: Stage,
+    /// The move found in the transposition table, or [`Move::null()`].
+    tt_move: Move,
+    /// The scored moves of the current stage.
+    moves: ScoredMoves,
+    /// The captures that lose material, saved for after the quiet moves.
+    bad_captures: ScoredMoves,
+}
+
+/// The stages of an [`AllMovesPicker`], in order.
+#[derive(Clone, Copy)]
+enum Stage {
+    /// Yield the TT move.
+    TtMove,
+    /// Generate and score the captures.
+    GenerateCaptures,
+    /// Yield the captures that don't lose material.
+    GoodCaptures,
+    /// Generate and score the quiet moves.
+    GenerateQuiets,
+    /// Yield the quiet moves.
+    Quiets,
+    /// Yield the captures that lose material.
+    BadCaptures,
+}
+
 /// A [`Move`] that has been given a certain score.
+///
+/// [`ScoredMove`]s are ordered (and compared for equality) by their score
+/// alone, so sorting a list of them puts the lowest-scoring move first.
 #[allow(clippy::missing_docs_in_private_items)]
 #[derive(Clone, Copy)]
 pub struct ScoredMove {
@@ -46,12 +84,21 @@ pub struct ScoredMoves {
     moves: Stack<ScoredMove, MAX_LEGAL_MOVES>,
 }
 
-/// The score of a quiet move.
+/// The base score of a quiet move, to which its history is added.
+///
+/// Even with the most extreme history, a quiet move stays between losing and
+/// winning captures.
 const QUIET_SCORE: Eval = 5_000;
 /// The score of a move found in the transposition table.
 const TT_SCORE: Eval = INF_EVAL;
 /// The score of a capture with a winning static exchange evaluation.
 const WINNING_CAPTURE_SCORE: Eval = 10_000;
+/// The capture history of a capture is divided by this before being added to
+/// its score.
+///
+/// This keeps the history of a capture from outweighing the value of the
+/// captured piece by more than a pawn or so.
+const CAPTURE_HISTORY_DIVISOR: Eval = 32;
 
 impl Iterator for MovePicker {
     type Item = Move;
@@ -102,34 +149,135 @@ impl Iterator for ScoredMoves {
 }
 
 impl MovePicker {
-    /// Creates a new [`MovePicker`] based on the information in `board` and
-    /// `tt_move`.
+    /// Creates a new [`MovePicker`] based on the information in `board`,
+    /// `tt_move` and `histories`.
     ///
     /// If `tt_move == Move::null()`, it will be ignored.
-    pub fn new<const MOVE_TYPE: u8>(board: &Board, tt_move: Move) -> Self {
-        let mut moves = generate_moves::<MOVE_TYPE>(board).score::<MOVE_TYPE>(board, tt_move);
+    pub fn new<const MOVE_TYPE: u8>(board: &Board, tt_move: Move, histories: &Histories) -> Self {
+        let mut moves =
+            generate_moves::<MOVE_TYPE>(board).score::<MOVE_TYPE>(board, tt_move, histories);
+        moves.sort();
+        Self { moves }
+    }
+
+    /// Creates a new [`MovePicker`] of the captures in `board` with a static
+    /// exchange evaluation of at least `threshold`.
+    pub fn new_see_captures(board: &Board, threshold: Eval, histories: &Histories) -> Self {
+        let mut moves = generate_moves::<{ MoveType::CAPTURES }>(board)
+            .filter_see_ge(board, threshold)
+            .collect::<Moves>()
+            .score::<{ MoveType::CAPTURES }>(board, Move::null(), histories);
         moves.sort();
         Self { moves }
     }
 }
 
+impl AllMovesPicker {
+    /// Creates a new [`AllMovesPicker`] that yields `tt_move` first, as long
+    /// as it could be played in `board`.
+    pub fn new(board: &Board, tt_move: Move) -> Self {
+        // the TT move might be from a different position with the same key
+        let stage = if board.is_pseudolegal(tt_move) {
+            Stage::TtMove
+        } else {
+            Stage::GenerateCaptures
+        };
+        Self {
+            stage,
+            tt_move,
+            moves: ScoredMoves::new(),
+            bad_captures: ScoredMoves::new(),
+        }
+    }
+
+    /// Returns the next move, generating the moves of the next stage if
+    /// needed.
+    ///
+    /// `board` and `histories` must be the same every time.
+    pub fn next(&mut self, board: &Board, histories: &Histories) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    return Some(self.tt_move);
+                }
+                Stage::GenerateCaptures => {
+                    self.moves =
+                        generate_moves::<{ MoveType::CAPTURES }>(board)
+                            .score::<{ MoveType::CAPTURES }>(board, Move::null(), histories);
+                    self.moves.sort();
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => match self.moves.pop() {
+                    Some(scored_move) if scored_move.score < WINNING_CAPTURE_SCORE => {
+                        // the moves are sorted, so the rest are all bad
+                        // captures too
+                        self.moves.moves.push(scored_move);
+                        self.bad_captures = mem::replace(&mut self.moves, ScoredMoves::new());
+                        self.stage = Stage::GenerateQuiets;
+                    }
+                    Some(scored_move) if scored_move.mv == self.tt_move => (),
+                    Some(scored_move) => return Some(scored_move.mv),
+                    None => self.stage = Stage::GenerateQuiets,
+                },
+                Stage::GenerateQuiets => {
+                    self.moves = generate_moves::<{ MoveType::QUIETS }>(board)
+                        .score::<{ MoveType::QUIETS }>(board, Move::null(), histories);
+                    self.moves.sort();
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => match self.moves.next() {
+                    Some(mv) if mv == self.tt_move => (),
+                    Some(mv) => return Some(mv),
+                    None => self.stage = Stage::BadCaptures,
+                },
+                Stage::BadCaptures => match self.bad_captures.next() {
+                    Some(mv) if mv == self.tt_move => (),
+                    mv => return mv,
+                },
+            }
+        }
+    }
+}
+
 impl Moves {
-    /// Scores the moves in `moves`, given the information in `search_info` and
-    /// the current height.
-    pub fn score<const MOVE_TYPE: u8>(self, board: &Board, tt_move: Move) -> ScoredMoves {
-        self.map(|mv| ScoredMove::new::<MOVE_TYPE>(board, mv, tt_move))
+    /// Filters the moves down to those with a static exchange evaluation of at
+    /// least `threshold`.
+    ///
+    /// This is meant for captures. It performs a static exchange evaluation on
+    /// every move, so it takes O(moves × SEE).
+    pub fn filter_see_ge(self, board: &Board, threshold: Eval) -> impl Iterator<Item = Move> + '_ {
+        self.filter(move |&mv| board.see_ge(mv, threshold))
+    }
+
+    /// Scores the moves in `moves`, given the information in `board`,
+    /// `tt_move` and `histories`.
+    pub fn score<const MOVE_TYPE: u8>(
+        self,
+        board: &Board,
+        tt_move: Move,
+        histories: &Histories,
+    ) -> ScoredMoves {
+        self.map(|mv| ScoredMove::new::<MOVE_TYPE>(board, mv, tt_move, histories))
             .collect()
     }
 }
 
 impl ScoredMove {
+    /// Creates a [`ScoredMove`] with the given score.
+    pub const fn with_score(mv: Move, score: Eval) -> Self {
+        Self { mv, score }
+    }
+
     /// Scores a [`Move`].
-    pub fn new<const MOVE_TYPE: u8>(board: &Board, mv: Move, tt_move: Move) -> Self {
+    pub fn new<const MOVE_TYPE: u8>(
+        board: &Board,
+        mv: Move,
+        tt_move: Move,
+        histories: &Histories,
+    ) -> Self {
         if MOVE_TYPE != MoveType::CAPTURES && mv == tt_move {
-            return Self {
-                mv,
-                score: TT_SCORE,
-            };
+            return Self::with_score(mv, TT_SCORE);
         }
 
         let captured_piece = if mv.is_en_passant() {
@@ -141,10 +289,7 @@ impl ScoredMove {
         };
 
         if captured_piece == PieceType::NONE {
-            return Self {
-                mv,
-                score: QUIET_SCORE,
-            };
+            return Self::with_score(mv, QUIET_SCORE + histories.score(board, mv));
         }
 
         debug_assert!(
@@ -152,15 +297,23 @@ impl ScoredMove {
             "How are you capturing a king?"
         );
 
-        let mut score = captured_piece.mvv_bonus();
-        if board.is_winning_exchange(mv) {
+        let mut score = captured_piece.mvv_bonus()
+            + histories.capture_score(board, mv) / CAPTURE_HISTORY_DIVISOR;
+        if board.see_ge(mv, 0) {
             score += WINNING_CAPTURE_SCORE;
         }
-        Self { mv, score }
+        Self::with_score(mv, score)
     }
 }
 
 impl ScoredMoves {
+    /// Creates an empty [`ScoredMoves`].
+    const fn new() -> Self {
+        Self {
+            moves: Stack::new(),
+        }
+    }
+
     /// Sorts the scored moves.
     pub fn sort(&mut self) {
         self.moves.sort_by(Ord::cmp);
@@ -173,3 +326,123 @@ impl ScoredMoves {
         self.moves.pop()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::iter;
+
+    use super::{AllMovesPicker, ScoredMove, ScoredMoves};
+    use crate::{
+        board::Board,
+        defs::{MoveType, Square},
+        movegen::{generate_moves, Move},
+        perft::SUITE,
+        search::histories::Histories,
+    };
+
+    /// Checks that the TT move is yielded first and only once.
+    #[test]
+    fn tt_move_yielded_once() {
+        let board = Board::default();
+        let tt_move = Move::new(Square::G1, Square::F3);
+
+        let histories = Histories::new();
+        let mut movepicker = AllMovesPicker::new(&board, tt_move);
+        let moves = iter::from_fn(|| movepicker.next(&board, &histories)).collect::<Vec<_>>();
+
+        assert!(moves.first() == Some(&tt_move), "TT move should be first");
+        assert_eq!(
+            moves.iter().filter(|&&mv| mv == tt_move).count(),
+            1,
+            "TT move should only be yielded once"
+        );
+        assert_eq!(moves.len(), 20, "All other moves should still be yielded");
+    }
+
+    /// Checks that the stages yield every move exactly once, with the good
+    /// captures before the quiets and the bad captures after them.
+    #[test]
+    fn stages_yield_every_move() {
+        let histories = Histories::new();
+        for (fen, _, _) in SUITE {
+            let board = fen.parse::<Board>().expect("Valid FEN");
+            let mut movepicker = AllMovesPicker::new(&board, Move::null());
+            let mut moves = iter::from_fn(|| movepicker.next(&board, &histories))
+                .map(|mv| mv.to_string())
+                .collect::<Vec<_>>();
+            let mut expected = generate_moves::<{ MoveType::ALL }>(&board)
+                .map(|mv| mv.to_string())
+                .collect::<Vec<_>>();
+            moves.sort();
+            expected.sort();
+
+            assert_eq!(moves, expected, "Wrong moves for {fen}");
+        }
+
+        // Qxe6 loses the queen, so it comes after Kf2
+        let board = "4k3/3p4/4p3/8/8/8/8/4QK2 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let mut movepicker = AllMovesPicker::new(&board, Move::null());
+        let moves = iter::from_fn(|| movepicker.next(&board, &histories)).collect::<Vec<_>>();
+        assert_eq!(
+            moves.last(),
+            Some(&Move::new(Square::E1, Square::E6)),
+            "The bad capture should be last"
+        );
+    }
+
+    /// Checks that a losing capture is filtered out at a threshold of 0 but
+    /// not at a low enough negative threshold.
+    #[test]
+    fn filter_see_ge() {
+        // the pawn on e6 is defended by the pawn on d7
+        let board = "4k3/3p4/4p3/8/8/8/8/4QK2 w - - 0 1"
+            .parse::<Board>()
+            .expect("Valid FEN");
+        let queen_takes_pawn = Move::new(Square::E1, Square::E6);
+        let captures = || generate_moves::<{ MoveType::CAPTURES }>(&board);
+
+        assert!(
+            !captures()
+                .filter_see_ge(&board, 0)
+                .any(|mv| mv == queen_takes_pawn),
+            "Qxe6 loses the queen for a pawn"
+        );
+        assert!(
+            captures()
+                .filter_see_ge(&board, -800)
+                .any(|mv| mv == queen_takes_pawn),
+            "Qxe6 loses exactly 800"
+        );
+        assert!(
+            !captures()
+                .filter_see_ge(&board, -799)
+                .any(|mv| mv == queen_takes_pawn),
+            "Qxe6 loses more than 799"
+        );
+    }
+
+    /// Checks that sorted moves are yielded from highest to lowest score.
+    #[test]
+    fn sort_order() {
+        let low = Move::new(Square::A2, Square::A3);
+        let middle = Move::new(Square::B2, Square::B3);
+        let high = Move::new(Square::C2, Square::C3);
+        let mut moves: ScoredMoves = [
+            ScoredMove::with_score(middle, 0),
+            ScoredMove::with_score(high, 100),
+            ScoredMove::with_score(low, -100),
+        ]
+        .into_iter()
+        .collect();
+
+        moves.sort();
+
+        assert_eq!(
+            moves.collect::<Vec<_>>(),
+            [high, middle, low],
+            "Moves should be yielded in descending order of score"
+        );
+    }
+}
@@ -0,0 +1,412 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::Depth;
+use crate::evaluation::{pawns::PawnWeights, Eval};
+
+/// Parameters of the search that can be tuned.
+#[derive(Clone, Copy)]
+pub struct SearchParameters {
+    /// The constant term of the base late move reductions.
+    pub lmr_base: f32,
+    /// The divisor of the logarithmic term of the base late move reductions.
+    pub lmr_divisor: f32,
+    /// The minimum depth at which null move pruning is done.
+    pub nmp_min_depth: Depth,
+    /// The minimum depth at which a null move cutoff is verified with a
+    /// normal search.
+    pub nmp_verification_depth: Depth,
+    /// The constant term of the null move reduction.
+    pub nmp_base_reduction: Depth,
+    /// The null move reduction increases by 1 every this many plies of depth.
+    pub nmp_reduction_divisor: Depth,
+    /// The minimum depth at which a node without a TT move is reduced.
+    pub iir_min_depth: Depth,
+    /// The minimum depth at which `ProbCut` is done.
+    pub probcut_min_depth: Depth,
+    /// How far above beta a reduced search of a capture has to score for
+    /// `ProbCut` to cut off.
+    pub probcut_margin: Eval,
+    /// How much shallower the children are searched by `ProbCut` than by
+    /// the normal search.
+    pub probcut_reduction: Depth,
+    /// The initial distance of each side of the aspiration window from the
+    /// score of the previous iteration.
+    pub aspiration_window: Eval,
+    /// The maximum depth at which captures that lose material are pruned.
+    pub see_pruning_depth: Depth,
+    /// How much material a capture can lose per ply of depth before it's
+    /// pruned.
+    pub see_capture_margin: Eval,
+    /// The percentage of the increment that is added to the time window.
+    pub inc_percentage: u32,
+    /// The maximum percentage of the remaining time that the time window can
+    /// take up.
+    ///
+    /// This stops a large increment relative to the remaining time from
+    /// making us flag.
+    pub max_time_percentage: u32,
+    /// The weights of the pawn structure terms of the evaluation.
+    ///
+    /// They're tuned along with the search, since they're the terms of the
+    /// evaluation that most affect how it plays.
+    pub pawn_weights: PawnWeights,
+}
+
+impl Default for SearchParameters {
+    fn default() -> Self {
+        Self {
+            lmr_base: 0.0,
+            lmr_divisor: 2.0,
+            nmp_min_depth: 3,
+            nmp_verification_depth: 16,
+            nmp_base_reduction: 3,
+            nmp_reduction_divisor: 3,
+            iir_min_depth: 4,
+            probcut_min_depth: 5,
+            probcut_margin: 200,
+            probcut_reduction: 4,
+            aspiration_window: 25,
+            see_pruning_depth: 6,
+            see_capture_margin: 100,
+            inc_percentage: 75,
+            max_time_percentage: 50,
+            pawn_weights: PawnWeights::default(),
+        }
+    }
+}
+
+/// A search parameter exposed as a UCI spin option for tuning.
+#[cfg(feature = "tune")]
+struct TunableParameter {
+    /// The name of the option.
+    name: &'static str,
+    /// The minimum value of the option.
+    min: i32,
+    /// The maximum value of the option.
+    max: i32,
+    /// Gets the value of the option from the parameters.
+    get: fn(&SearchParameters) -> i32,
+    /// Sets the parameter from the value of the option.
+    set: fn(&mut SearchParameters, i32),
+}
+
+/// The search parameters exposed as UCI options.
+///
+/// The floating-point parameters are given in hundredths, since spin options
+/// can only be integers. The pawn structure weights are split into their
+/// middlegame and endgame values, and the passed pawn bonuses are named after
+/// the rank of the pawn from the point of view of its side.
+#[cfg(feature = "tune")]
+const TUNABLE_PARAMETERS: [TunableParameter; 33] = [
+    TunableParameter {
+        name: "LmrBase",
+        min: 0,
+        max: 200,
+        get: |params| (params.lmr_base * 100.0).round() as i32,
+        set: |params, value| params.lmr_base = value as f32 / 100.0,
+    },
+    TunableParameter {
+        name: "LmrDivisor",
+        min: 100,
+        max: 400,
+        get: |params| (params.lmr_divisor * 100.0).round() as i32,
+        set: |params, value| params.lmr_divisor = value as f32 / 100.0,
+    },
+    TunableParameter {
+        name: "NmpMinDepth",
+        min: 1,
+        max: 8,
+        get: |params| i32::from(params.nmp_min_depth),
+        set: |params, value| params.nmp_min_depth = value as Depth,
+    },
+    TunableParameter {
+        name: "NmpVerificationDepth",
+        min: 4,
+        max: 32,
+        get: |params| i32::from(params.nmp_verification_depth),
+        set: |params, value| params.nmp_verification_depth = value as Depth,
+    },
+    TunableParameter {
+        name: "NmpBaseReduction",
+        min: 1,
+        max: 6,
+        get: |params| i32::from(params.nmp_base_reduction),
+        set: |params, value| params.nmp_base_reduction = value as Depth,
+    },
+    TunableParameter {
+        name: "NmpReductionDivisor",
+        min: 1,
+        max: 8,
+        get: |params| i32::from(params.nmp_reduction_divisor),
+        set: |params, value| params.nmp_reduction_divisor = value as Depth,
+    },
+    TunableParameter {
+        name: "IirMinDepth",
+        min: 2,
+        max: 10,
+        get: |params| i32::from(params.iir_min_depth),
+        set: |params, value| params.iir_min_depth = value as Depth,
+    },
+    TunableParameter {
+        name: "ProbcutMinDepth",
+        min: 2,
+        max: 12,
+        get: |params| i32::from(params.probcut_min_depth),
+        set: |params, value| params.probcut_min_depth = value as Depth,
+    },
+    TunableParameter {
+        name: "ProbcutMargin",
+        min: 50,
+        max: 400,
+        get: |params| i32::from(params.probcut_margin),
+        set: |params, value| params.probcut_margin = value as Eval,
+    },
+    TunableParameter {
+        name: "ProbcutReduction",
+        min: 2,
+        max: 6,
+        get: |params| i32::from(params.probcut_reduction),
+        set: |params, value| params.probcut_reduction = value as Depth,
+    },
+    TunableParameter {
+        name: "AspirationWindow",
+        min: 5,
+        max: 100,
+        get: |params| i32::from(params.aspiration_window),
+        set: |params, value| params.aspiration_window = value as Eval,
+    },
+    TunableParameter {
+        name: "SeePruningDepth",
+        min: 1,
+        max: 12,
+        get: |params| i32::from(params.see_pruning_depth),
+        set: |params, value| params.see_pruning_depth = value as Depth,
+    },
+    TunableParameter {
+        name: "SeeCaptureMargin",
+        min: 20,
+        max: 200,
+        get: |params| i32::from(params.see_capture_margin),
+        set: |params, value| params.see_capture_margin = value as Eval,
+    },
+    TunableParameter {
+        name: "IncPercentage",
+        min: 25,
+        max: 100,
+        get: |params| params.inc_percentage as i32,
+        set: |params, value| params.inc_percentage = value as u32,
+    },
+    TunableParameter {
+        name: "MaxTimePercentage",
+        min: 20,
+        max: 80,
+        get: |params| params.max_time_percentage as i32,
+        set: |params, value| params.max_time_percentage = value as u32,
+    },
+    TunableParameter {
+        name: "PassedPawnMg2",
+        min: 0,
+        max: 200,
+        get: |params| i32::from(params.pawn_weights.passed[1].0),
+        set: |params, value| params.pawn_weights.passed[1].0 = value as Eval,
+    },
+    TunableParameter {
+        name: "PassedPawnEg2",
+        min: 0,
+        max: 200,
+        get: |params| i32::from(params.pawn_weights.passed[1].1),
+        set: |params, value| params.pawn_weights.passed[1].1 = value as Eval,
+    },
+    TunableParameter {
+        name: "PassedPawnMg3",
+        min: 0,
+        max: 200,
+        get: |params| i32::from(params.pawn_weights.passed[2].0),
+        set: |params, value| params.pawn_weights.passed[2].0 = value as Eval,
+    },
+    TunableParameter {
+        name: "PassedPawnEg3",
+        min: 0,
+        max: 200,
+        get: |params| i32::from(params.pawn_weights.passed[2].1),
+        set: |params, value| params.pawn_weights.passed[2].1 = value as Eval,
+    },
+    TunableParameter {
+        name: "PassedPawnMg4",
+        min: 0,
+        max: 200,
+        get: |params| i32::from(params.pawn_weights.passed[3].0),
+        set: |params, value| params.pawn_weights.passed[3].0 = value as Eval,
+    },
+    TunableParameter {
+        name: "PassedPawnEg4",
+        min: 0,
+        max: 200,
+        get: |params| i32::from(params.pawn_weights.passed[3].1),
+        set: |params, value| params.pawn_weights.passed[3].1 = value as Eval,
+    },
+    TunableParameter {
+        name: "PassedPawnMg5",
+        min: 0,
+        max: 200,
+        get: |params| i32::from(params.pawn_weights.passed[4].0),
+        set: |params, value| params.pawn_weights.passed[4].0 = value as Eval,
+    },
+    TunableParameter {
+        name: "PassedPawnEg5",
+        min: 0,
+        max: 200,
+        get: |params| i32::from(params.pawn_weights.passed[4].1),
+        set: |params, value| params.pawn_weights.passed[4].1 = value as Eval,
+    },
+    TunableParameter {
+        name: "PassedPawnMg6",
+        min: 0,
+        max: 200,
+        get: |params| i32::from(params.pawn_weights.passed[5].0),
+        set: |params, value| params.pawn_weights.passed[5].0 = value as Eval,
+    },
+    TunableParameter {
+        name: "PassedPawnEg6",
+        min: 0,
+        max: 200,
+        get: |params| i32::from(params.pawn_weights.passed[5].1),
+        set: |params, value| params.pawn_weights.passed[5].1 = value as Eval,
+    },
+    TunableParameter {
+        name: "PassedPawnMg7",
+        min: 0,
+        max: 200,
+        get: |params| i32::from(params.pawn_weights.passed[6].0),
+        set: |params, value| params.pawn_weights.passed[6].0 = value as Eval,
+    },
+    TunableParameter {
+        name: "PassedPawnEg7",
+        min: 0,
+        max: 200,
+        get: |params| i32::from(params.pawn_weights.passed[6].1),
+        set: |params, value| params.pawn_weights.passed[6].1 = value as Eval,
+    },
+    TunableParameter {
+        name: "IsolatedPawnMg",
+        min: -50,
+        max: 0,
+        get: |params| i32::from(params.pawn_weights.isolated.0),
+        set: |params, value| params.pawn_weights.isolated.0 = value as Eval,
+    },
+    TunableParameter {
+        name: "IsolatedPawnEg",
+        min: -50,
+        max: 0,
+        get: |params| i32::from(params.pawn_weights.isolated.1),
+        set: |params, value| params.pawn_weights.isolated.1 = value as Eval,
+    },
+    TunableParameter {
+        name: "DoubledPawnMg",
+        min: -50,
+        max: 0,
+        get: |params| i32::from(params.pawn_weights.doubled.0),
+        set: |params, value| params.pawn_weights.doubled.0 = value as Eval,
+    },
+    TunableParameter {
+        name: "DoubledPawnEg",
+        min: -50,
+        max: 0,
+        get: |params| i32::from(params.pawn_weights.doubled.1),
+        set: |params, value| params.pawn_weights.doubled.1 = value as Eval,
+    },
+    TunableParameter {
+        name: "BackwardPawnMg",
+        min: -50,
+        max: 0,
+        get: |params| i32::from(params.pawn_weights.backward.0),
+        set: |params, value| params.pawn_weights.backward.0 = value as Eval,
+    },
+    TunableParameter {
+        name: "BackwardPawnEg",
+        min: -50,
+        max: 0,
+        get: |params| i32::from(params.pawn_weights.backward.1),
+        set: |params, value| params.pawn_weights.backward.1 = value as Eval,
+    },
+];
+
+#[cfg(feature = "tune")]
+impl SearchParameters {
+    /// Prints a UCI spin option for each tunable parameter.
+    pub fn print_options(&self) {
+        for param in &TUNABLE_PARAMETERS {
+            println!(
+                "option name {} type spin default {} min {} max {}",
+                param.name,
+                (param.get)(self),
+                param.min,
+                param.max,
+            );
+        }
+    }
+
+    /// Sets the parameter of the option called `name` to `value`, clamped in
+    /// the range of the option.
+    ///
+    /// Returns `false` if there's no option called `name`.
+    pub fn set_option(&mut self, name: &str, value: i32) -> bool {
+        let Some(param) = TUNABLE_PARAMETERS.iter().find(|param| param.name == name) else {
+            return false;
+        };
+        (param.set)(self, value.clamp(param.min, param.max));
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "tune")]
+    use super::SearchParameters;
+
+    /// Checks that the options are set by name and clamped to their range.
+    #[test]
+    #[cfg(feature = "tune")]
+    fn set_option() {
+        let mut params = SearchParameters::default();
+
+        assert!(params.set_option("LmrDivisor", 250), "LmrDivisor exists");
+        assert!(
+            (params.lmr_divisor - 2.5).abs() < f32::EPSILON,
+            "LmrDivisor is in hundredths"
+        );
+        assert!(
+            params.set_option("NmpBaseReduction", 100),
+            "NmpBaseReduction exists"
+        );
+        assert_eq!(params.nmp_base_reduction, 6, "The value should be clamped");
+        assert!(
+            params.set_option("PassedPawnEg7", 150),
+            "PassedPawnEg7 exists"
+        );
+        assert_eq!(
+            params.pawn_weights.passed[6].1, 150,
+            "PassedPawnEg7 is the endgame bonus on the seventh rank"
+        );
+        assert!(
+            !params.set_option("Hash", 16),
+            "Hash isn't a search parameter"
+        );
+    }
+}
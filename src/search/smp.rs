@@ -0,0 +1,149 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+
+use super::{Depth, SearchReport};
+use crate::movegen::Move;
+
+/// The state shared between every thread of a search.
+pub struct SharedState {
+    /// Whether or not the main thread has stopped, which means the helper
+    /// threads should stop too.
+    stop: AtomicBool,
+    /// The highest depth that any thread has completed.
+    completed_depth: AtomicU8,
+    /// The number of positions searched by the helper threads so far.
+    helper_nodes: AtomicU64,
+    /// Whether or not extra diagnostics should be printed as `info string`s.
+    is_debug: bool,
+    /// Whether or not the helper threads are kept from affecting the main
+    /// thread, so that the search gives the same result and node count on
+    /// every run.
+    is_deterministic: bool,
+}
+
+impl SharedState {
+    /// How much each vote is offset by, so that the thread with the worst
+    /// score still has a say.
+    const VOTE_OFFSET: i64 = 14;
+
+    /// Creates a new [`SharedState`] for a search that hasn't started yet.
+    pub const fn new(is_debug: bool, is_deterministic: bool) -> Self {
+        Self {
+            stop: AtomicBool::new(false),
+            completed_depth: AtomicU8::new(0),
+            helper_nodes: AtomicU64::new(0),
+            is_debug,
+            is_deterministic,
+        }
+    }
+
+    /// Checks if extra diagnostics should be printed.
+    pub const fn is_debug(&self) -> bool {
+        self.is_debug
+    }
+
+    /// Checks if the helper threads are kept from affecting the main thread.
+    pub const fn is_deterministic(&self) -> bool {
+        self.is_deterministic
+    }
+
+    /// Tells the helper threads to stop.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Checks if the helper threads should stop.
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Records that a thread has completed `depth`.
+    pub fn complete_depth(&self, depth: Depth) {
+        self.completed_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    /// Returns the highest depth that any thread has completed.
+    pub fn completed_depth(&self) -> Depth {
+        self.completed_depth.load(Ordering::Relaxed)
+    }
+
+    /// Adds `nodes` to the number of positions searched by the helper
+    /// threads, unless the search is deterministic, in which case only the
+    /// nodes of the main thread are counted.
+    pub fn add_helper_nodes(&self, nodes: u64) {
+        if !self.is_deterministic {
+            self.helper_nodes.fetch_add(nodes, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the number of positions searched by the helper threads.
+    pub fn helper_nodes(&self) -> u64 {
+        self.helper_nodes.load(Ordering::Relaxed)
+    }
+}
+
+/// Picks the report whose best move has the most votes.
+///
+/// Each thread votes for its best move, weighted by how deep it searched and
+/// how much better its score is than the worst score. Ties go to the main
+/// thread.
+pub fn vote(main_report: SearchReport, helper_reports: Vec<SearchReport>) -> SearchReport {
+    let mut reports = helper_reports;
+    reports.insert(0, main_report);
+    let min_score = reports
+        .iter()
+        .map(|report| report.score)
+        .min()
+        .expect("There's always the main report");
+    let weight = |report: &SearchReport| {
+        (i64::from(report.score) - i64::from(min_score) + SharedState::VOTE_OFFSET)
+            * i64::from(report.depth)
+    };
+
+    let mut votes = Vec::<(Move, i64)>::new();
+    for report in &reports {
+        if let Some(&mut (_, ref mut total)) = votes
+            .iter_mut()
+            .find(|&&mut (mv, _)| mv == report.best_move())
+        {
+            *total += weight(report);
+        } else {
+            votes.push((report.best_move(), weight(report)));
+        }
+    }
+    let total_votes = |report: &SearchReport| {
+        votes
+            .iter()
+            .find(|&&(mv, _)| mv == report.best_move())
+            .map_or(0, |&(_, total)| total)
+    };
+
+    let best_index = reports
+        .iter()
+        .enumerate()
+        // `max_by_key()` returns the last maximum, so this prefers the first
+        .rev()
+        .max_by_key(|&(_, report)| total_votes(report))
+        .map_or(0, |(index, _)| index);
+    reports
+        .into_iter()
+        .nth(best_index)
+        .expect("The index came from the same reports")
+}
@@ -0,0 +1,150 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fmt::{self, Display, Formatter};
+
+/// Detailed counts of what happened during a search, for evaluating changes
+/// to the search beyond the node count of `bench`.
+///
+/// Only compiled with the `stats` feature, since counting everything costs a
+/// little speed. The pruning counts that are always kept aren't repeated here.
+#[derive(Default)]
+pub struct SearchStats {
+    /// How many PV nodes of the main search were searched.
+    pub pv_nodes: u64,
+    /// How many non-PV nodes of the main search were searched.
+    pub non_pv_nodes: u64,
+    /// How many nodes of the quiescence search were searched.
+    pub quiescence_nodes: u64,
+    /// How many times the transposition table was probed.
+    pub tt_probes: u64,
+    /// How many of those probes found an entry.
+    pub tt_hits: u64,
+    /// How many moves were searched with a late move reduction.
+    pub lmr_searches: u64,
+    /// How many of those had to be searched again without the reduction.
+    pub lmr_researches: u64,
+    /// How many beta cutoffs happened on the first, second, etc. move, with
+    /// every move after the last entry counted in the last entry.
+    pub cutoffs_by_move: [u64; Self::CUTOFF_MOVES],
+}
+
+impl SearchStats {
+    /// How many move indices have their own cutoff count.
+    pub const CUTOFF_MOVES: usize = 8;
+
+    /// Counts a node of the main search.
+    pub const fn add_node(&mut self, is_pv: bool) {
+        if is_pv {
+            self.pv_nodes += 1;
+        } else {
+            self.non_pv_nodes += 1;
+        }
+    }
+
+    /// Counts a node of the quiescence search.
+    pub const fn add_quiescence_node(&mut self) {
+        self.quiescence_nodes += 1;
+    }
+
+    /// Counts a probe of the transposition table.
+    pub fn add_tt_probe(&mut self, is_hit: bool) {
+        self.tt_probes += 1;
+        self.tt_hits += u64::from(is_hit);
+    }
+
+    /// Counts a search with a late move reduction.
+    pub fn add_lmr(&mut self, is_research: bool) {
+        self.lmr_searches += 1;
+        self.lmr_researches += u64::from(is_research);
+    }
+
+    /// Counts a beta cutoff on the move with the given 1-based index.
+    pub fn add_cutoff(&mut self, move_number: u8) {
+        let index = usize::from(move_number.max(1) - 1).min(Self::CUTOFF_MOVES - 1);
+        if let Some(count) = self.cutoffs_by_move.get_mut(index) {
+            *count += 1;
+        }
+    }
+}
+
+impl Display for SearchStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "info string stats nodes: pv {} non-pv {} quiescence {}",
+            self.pv_nodes, self.non_pv_nodes, self.quiescence_nodes
+        )?;
+        writeln!(
+            f,
+            "info string stats tt hit rate {} ({}/{})",
+            percentage(self.tt_hits, self.tt_probes),
+            self.tt_hits,
+            self.tt_probes
+        )?;
+        writeln!(
+            f,
+            "info string stats lmr research rate {} ({}/{})",
+            percentage(self.lmr_researches, self.lmr_searches),
+            self.lmr_researches,
+            self.lmr_searches
+        )?;
+        let cutoffs = self.cutoffs_by_move.iter().sum();
+        write!(f, "info string stats cutoffs by move ({cutoffs}):")?;
+        for (index, &count) in self.cutoffs_by_move.iter().enumerate() {
+            let plus = if index == Self::CUTOFF_MOVES - 1 {
+                "+"
+            } else {
+                ""
+            };
+            write!(f, " {}{plus} {}", index + 1, percentage(count, cutoffs))?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats `part / total` as a percentage with 1 decimal place, or "n/a" if
+/// `total` is 0.
+fn percentage(part: u64, total: u64) -> String {
+    if total == 0 {
+        String::from("n/a")
+    } else {
+        format!("{:.1}%", part as f64 * 100.0 / total as f64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SearchStats;
+
+    /// Checks that late cutoffs are counted in the last entry.
+    #[test]
+    fn add_cutoff() {
+        let mut stats = SearchStats::default();
+
+        stats.add_cutoff(1);
+        stats.add_cutoff(2);
+        stats.add_cutoff(30);
+
+        assert_eq!(
+            stats.cutoffs_by_move,
+            [1, 1, 0, 0, 0, 0, 0, 1],
+            "Wrong cutoff counts"
+        );
+    }
+}
@@ -0,0 +1,121 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use oorandom::Rand64;
+
+use crate::{evaluation::Eval, movegen::Move};
+
+/// A limit on how well the engine plays, as set by the `UCI_LimitStrength`
+/// and `UCI_Elo` options.
+///
+/// The search is capped to a number of nodes and the move played is picked at
+/// random from the root moves that scored close enough to the best one. Both
+/// get more generous as the Elo rises.
+#[derive(Clone, Copy)]
+pub struct Strength {
+    /// The maximum number of positions to search.
+    nodes: u64,
+    /// How far below the best score a root move can be and still be played.
+    margin: Eval,
+}
+
+impl Strength {
+    /// How many lines are searched so there's a choice of moves to play.
+    pub const LINES: usize = 4;
+    /// The lowest Elo that can be played at.
+    pub const MIN_ELO: u16 = 800;
+    /// The highest Elo that can be played at, where every move is the best
+    /// move of its search.
+    pub const MAX_ELO: u16 = 2800;
+    /// The number of nodes searched at the lowest Elo.
+    const MIN_NODES: f64 = 1_000.0;
+    /// How many more Elo it takes to double the number of nodes.
+    const ELO_PER_DOUBLING: f64 = 150.0;
+
+    /// Creates a new [`Strength`] that plays at about `elo`, clamped between
+    /// [`Self::MIN_ELO`] and [`Self::MAX_ELO`].
+    pub fn new(elo: u16) -> Self {
+        let elo = elo.clamp(Self::MIN_ELO, Self::MAX_ELO);
+        let doublings = f64::from(elo - Self::MIN_ELO) / Self::ELO_PER_DOUBLING;
+        Self {
+            nodes: (Self::MIN_NODES * doublings.exp2()) as u64,
+            margin: ((Self::MAX_ELO - elo) / 8) as Eval,
+        }
+    }
+
+    /// Returns the maximum number of positions to search.
+    pub const fn nodes(self) -> u64 {
+        self.nodes
+    }
+
+    /// Picks a random move out of `root_scores`, a list of root moves and
+    /// their scores, that's within the margin of the best score, using `rng`
+    /// to choose.
+    ///
+    /// Returns [`None`] if `root_scores` is empty.
+    pub fn pick(self, root_scores: &[(Move, Eval)], rng: &mut Rand64) -> Option<Move> {
+        let best_score = root_scores.iter().map(|&(_, score)| score).max()?;
+        let candidates = root_scores
+            .iter()
+            .filter(|&&(_, score)| score >= best_score.saturating_sub(self.margin))
+            .map(|&(mv, _)| mv)
+            .collect::<Vec<_>>();
+        let index = rng.rand_range(0..candidates.len() as u64) as usize;
+        candidates.get(index).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use oorandom::Rand64;
+
+    use super::Strength;
+    use crate::{defs::Square, movegen::Move};
+
+    /// Checks that every move within the margin gets picked, that nothing
+    /// outside it does and that a higher Elo searches more nodes.
+    #[test]
+    fn pick_within_margin() {
+        let weak = Strength::new(1_000);
+        let strong = Strength::new(2_000);
+        let best = Move::new(Square::E2, Square::E4);
+        let close = Move::new(Square::D2, Square::D4);
+        let blunder = Move::new(Square::F2, Square::F3);
+        let root_scores = [(best, 50), (close, 50 - strong.margin), (blunder, -500)];
+
+        assert!(weak.nodes() < strong.nodes(), "Weaker should search less");
+        let mut rng = Rand64::new(0x00C0_FFEE);
+        let picks = (0..20)
+            .map(|_| {
+                strong
+                    .pick(&root_scores, &mut rng)
+                    .expect("There are moves")
+            })
+            .collect::<Vec<_>>();
+        assert!(picks.contains(&best), "The best move should be picked");
+        assert!(picks.contains(&close), "The close move should be picked");
+        assert!(
+            !picks.contains(&blunder),
+            "The blunder is outside the margin"
+        );
+        assert!(
+            strong.pick(&[], &mut rng).is_none(),
+            "There's nothing to pick"
+        );
+    }
+}
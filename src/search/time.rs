@@ -16,9 +16,13 @@
  * Crab. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::time::{Duration, Instant};
+use std::{
+    fmt::{self, Display, Formatter},
+    time::{Duration, Instant},
+};
 
-use super::Limits;
+use super::{params::SearchParameters, Depth, Limits};
+use crate::util::get_unchecked;
 
 impl Limits {
     /// The maximum number of effective moves to go until the next time
@@ -27,24 +31,238 @@ impl Limits {
     /// In other words, the time manager treats the moves until the next time
     /// control as `moves_to_go.min(MAX_MOVES_TO_GO)`.
     const MAX_MOVES_TO_GO: u8 = 40;
+    /// The minimum amount of time a [`Movetime`](Limits::Movetime) search is
+    /// given after subtracting the move overhead.
+    const MIN_MOVETIME: Duration = Duration::from_millis(1);
 }
 
-/// Calculates the maximum window of time that should be used for the next
-/// iterative deepening loop.
-pub fn calculate_time_window(limits: Limits, start: Instant, move_overhead: Duration) -> Duration {
-    if let Limits::Timed {
-        time,
-        inc,
-        moves_to_go,
-    } = limits
-    {
-        // prioritise a low number of moves to go, but if it's sudden death
-        // (let's say), we set a maximum on the apparent moves to go, in order
-        // to avoid allocating too little time
-        let moves_to_go = moves_to_go.min(Limits::MAX_MOVES_TO_GO);
-
-        (time / u32::from(moves_to_go) + inc).saturating_sub(start.elapsed() + move_overhead)
-    } else {
-        Duration::MAX
+/// How long a search may take.
+#[derive(Clone, Copy)]
+pub struct TimeBounds {
+    /// Another iteration shouldn't be started after this much time, before
+    /// it's scaled by the stability of the best move.
+    soft: Duration,
+    /// The search must stop after this much time.
+    hard: Duration,
+}
+
+impl Display for TimeBounds {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "soft {} ms hard {} ms",
+            self.soft.as_millis(),
+            self.hard.as_millis()
+        )
+    }
+}
+
+impl TimeBounds {
+    /// Bounds that never run out.
+    pub const INFINITE: Self = Self {
+        soft: Duration::MAX,
+        hard: Duration::MAX,
+    };
+    /// The percentage of the hard bound that the soft bound is.
+    const SOFT_PERCENTAGE: u32 = 40;
+    /// The percentage that the soft bound is scaled by, indexed by how many
+    /// iterations the best move has stayed the same for.
+    ///
+    /// A best move that keeps changing needs more time to settle and one that
+    /// never changes probably won't, so it isn't worth the time.
+    const STABILITY_PERCENTAGES: [u32; 5] = [140, 120, 100, 90, 80];
+
+    /// Returns the time after which another iteration shouldn't be started,
+    /// given how many iterations the best move has stayed the same for.
+    ///
+    /// This is never more than the hard bound.
+    pub fn soft(&self, stability: Depth) -> Duration {
+        let index = usize::from(stability).min(Self::STABILITY_PERCENTAGES.len() - 1);
+        // `Duration::MAX` can't be multiplied
+        self.soft
+            .checked_mul(*get_unchecked(&Self::STABILITY_PERCENTAGES, index))
+            .map_or(self.soft, |soft| soft / 100)
+            .min(self.hard)
+    }
+
+    /// Returns the time after which the search must stop.
+    pub const fn hard(&self) -> Duration {
+        self.hard
+    }
+}
+
+/// Calculates the soft and hard time bounds of a search that was started at
+/// `start`.
+///
+/// Both have the move overhead and the time since `start` subtracted. For
+/// [`Movetime`](Limits::Movetime), both are the movetime. How much of the
+/// increment and remaining time a timed search can use is taken from
+/// `params`.
+#[allow(clippy::wildcard_enum_match_arm)]
+pub fn calculate_time_bounds(
+    limits: Limits,
+    start: Instant,
+    move_overhead: Duration,
+    params: SearchParameters,
+) -> TimeBounds {
+    match limits {
+        Limits::Timed {
+            time,
+            inc,
+            moves_to_go,
+        } => {
+            // prioritise a low number of moves to go, but if it's sudden death
+            // (let's say), we set a maximum on the apparent moves to go, in
+            // order to avoid allocating too little time
+            let moves_to_go = moves_to_go.min(Limits::MAX_MOVES_TO_GO);
+
+            let window = time / u32::from(moves_to_go) + inc * params.inc_percentage / 100;
+            let max_window = time * params.max_time_percentage / 100;
+
+            // the hard bound is checked during the search, so it's the one
+            // that stops us from flagging
+            let hard = window
+                .min(max_window)
+                .saturating_sub(start.elapsed() + move_overhead);
+            TimeBounds {
+                soft: hard * TimeBounds::SOFT_PERCENTAGE / 100,
+                hard,
+            }
+        }
+        Limits::Movetime(movetime) => {
+            let movetime = movetime
+                .saturating_sub(move_overhead)
+                .max(Limits::MIN_MOVETIME);
+            TimeBounds {
+                soft: movetime,
+                hard: movetime,
+            }
+        }
+        _ => TimeBounds::INFINITE,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{calculate_time_bounds, Depth, Limits, SearchParameters, TimeBounds};
+
+    /// Checks that the move overhead is subtracted from the movetime.
+    #[test]
+    fn movetime_subtracts_overhead() {
+        let start = Instant::now();
+        let overhead = Duration::from_millis(16);
+
+        let window = calculate_time_bounds(
+            Limits::Movetime(Duration::from_millis(100)),
+            start,
+            overhead,
+            SearchParameters::default(),
+        )
+        .hard();
+        assert_eq!(window, Duration::from_millis(84), "Overhead not subtracted");
+
+        let window = calculate_time_bounds(
+            Limits::Movetime(Duration::from_millis(10)),
+            start,
+            overhead,
+            SearchParameters::default(),
+        )
+        .hard();
+        assert_eq!(window, Limits::MIN_MOVETIME, "Movetime not clamped");
+    }
+
+    /// Checks that part of the increment is used when there's plenty of time
+    /// left.
+    #[test]
+    fn increment_used_with_ample_time() {
+        let limits = Limits::Timed {
+            time: Duration::from_secs(30),
+            inc: Duration::from_secs(1),
+            moves_to_go: u8::MAX,
+        };
+        // 30 s / 40 moves + 75% of 1 s
+        let expected = Duration::from_millis(1_500);
+
+        let window = calculate_time_bounds(
+            limits,
+            Instant::now(),
+            Duration::ZERO,
+            SearchParameters::default(),
+        )
+        .hard();
+
+        assert!(window <= expected, "Window too large: {window:?}");
+        assert!(
+            window + Duration::from_millis(50) > expected,
+            "Window too small: {window:?}"
+        );
+    }
+
+    /// Checks that `movestogo 0` is treated as sudden death.
+    #[test]
+    fn zero_moves_to_go_is_sudden_death() {
+        let time = Duration::from_secs(10);
+        let sudden_death = Limits::new_timed(time);
+        let mut zero_moves_to_go = Limits::new_timed(time);
+        zero_moves_to_go.set_moves_to_go(Some(0));
+
+        let (
+            Limits::Timed {
+                moves_to_go: expected,
+                ..
+            },
+            Limits::Timed { moves_to_go, .. },
+        ) = (sudden_death, zero_moves_to_go)
+        else {
+            panic!("Both limits should be timed");
+        };
+        assert_eq!(moves_to_go, expected, "movestogo 0 should be sudden death");
+    }
+
+    /// Checks that a large increment doesn't make us use all our remaining
+    /// time.
+    #[test]
+    fn increment_capped_with_low_time() {
+        let time = Duration::from_millis(100);
+        let limits = Limits::Timed {
+            time,
+            inc: Duration::from_secs(2),
+            moves_to_go: u8::MAX,
+        };
+
+        let window = calculate_time_bounds(
+            limits,
+            Instant::now(),
+            Duration::ZERO,
+            SearchParameters::default(),
+        )
+        .hard();
+
+        assert!(window <= time / 2, "Window too large: {window:?}");
+    }
+
+    /// Checks that the soft bound grows when the best move is unstable and
+    /// shrinks when it's stable, but never passes the hard bound.
+    #[test]
+    fn soft_bound_scales_with_stability() {
+        let limits = Limits::new_timed(Duration::from_secs(40));
+        let bounds = calculate_time_bounds(
+            limits,
+            Instant::now(),
+            Duration::ZERO,
+            SearchParameters::default(),
+        );
+
+        assert!(
+            bounds.soft(0) > bounds.soft(2) && bounds.soft(2) > bounds.soft(Depth::MAX),
+            "A stable best move should get less time"
+        );
+        assert!(bounds.soft(0) <= bounds.hard(), "Soft bound too large");
+        assert!(
+            TimeBounds::INFINITE.soft(0) == Duration::MAX,
+            "Infinite bounds should stay infinite"
+        );
     }
 }
@@ -0,0 +1,121 @@
+/*
+ * Crab, a UCI-compatible chess engine
+ * Copyright (C) 2024 Jasper Shovelton
+ *
+ * Crab is free software: you can redistribute it and/or modify it under the
+ * terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or (at your option) any later
+ * version.
+ *
+ * Crab is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+ * details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * Crab. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use oorandom::Rand64;
+
+use crate::{
+    bench::BENCH_POSITIONS,
+    board::Board,
+    defs::Side,
+    evaluation::{evaluate, pawns::PawnTable},
+    perft::perft_bench,
+};
+
+/// How many random moves are played from each position of
+/// [`BENCH_POSITIONS`].
+const PLAYOUT_LENGTH: usize = 40;
+/// The seed of the random moves, so that every run checks the same positions.
+const SEED: u128 = 0x5E1F_7E57;
+
+/// Checks the consistency of the engine, printing every problem found.
+///
+/// Every position reached by random playouts from [`BENCH_POSITIONS`] must
+/// evaluate the same as its mirror (the evaluation is from the point of view
+/// of the side to move, so it shouldn't change) and its incrementally-updated
+/// accumulators must match the ones calculated from scratch. Then move
+/// generation and making moves are checked with [`perft_bench()`].
+pub fn selftest() {
+    let (positions, failures) = check_playouts();
+    println!("{positions} positions checked: {failures} failed");
+    perft_bench();
+}
+
+/// Plays random moves from every position of [`BENCH_POSITIONS`] and checks
+/// each position reached, returning how many were checked and how many
+/// failed.
+fn check_playouts() -> (usize, usize) {
+    let mut rng = Rand64::new(SEED);
+    let mut pawn_table = PawnTable::new();
+    let mut positions = 0;
+    let mut failures = 0;
+
+    for fen in BENCH_POSITIONS {
+        let mut board = fen.parse::<Board>().expect("Malformed bench position");
+        for _ in 0..PLAYOUT_LENGTH {
+            positions += 1;
+            if let Err(problem) = check(&board, &mut pawn_table) {
+                println!("{board}: {problem}");
+                failures += 1;
+            }
+
+            let moves = board.legal_moves();
+            if moves.len() == 0 {
+                break;
+            }
+            let index = rng.rand_range(0..moves.len() as u64) as usize;
+            let mv = moves.iter().nth(index).expect("The index is in range");
+            board.make_move(mv);
+        }
+    }
+
+    (positions, failures)
+}
+
+/// Checks that `board` evaluates the same as its mirror and that its
+/// accumulators are the same as when they're calculated from scratch.
+fn check(board: &Board, pawn_table: &mut PawnTable) -> Result<(), &'static str> {
+    let rebuilt = board.rebuilt();
+    if board.zobrist() != rebuilt.zobrist() {
+        return Err("incorrect zobrist key");
+    }
+    if board.pawn_zobrist() != rebuilt.pawn_zobrist() {
+        return Err("incorrect pawn zobrist key");
+    }
+    if board.phase() != rebuilt.phase() {
+        return Err("incorrect phase");
+    }
+    for side in [Side::WHITE, Side::BLACK] {
+        if board.non_pawn_material(side) != rebuilt.non_pawn_material(side) {
+            return Err("incorrect non-pawn material");
+        }
+    }
+    let (score, rebuilt_score) = (board.score(), rebuilt.score());
+    if score.0 != rebuilt_score.0 || score.1 != rebuilt_score.1 {
+        return Err("incorrect score");
+    }
+
+    if evaluate(board, pawn_table) != evaluate(&board.mirrored(), pawn_table) {
+        return Err("the mirrored board evaluates differently");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::check_playouts;
+
+    /// Checks that every position of the playouts is consistent.
+    #[test]
+    fn playouts() {
+        let (positions, failures) = check_playouts();
+
+        assert!(positions > 0, "No positions checked");
+        assert_eq!(failures, 0, "{failures} positions failed");
+    }
+}
@@ -18,7 +18,7 @@
 
 use std::{
     mem::{size_of, transmute},
-    sync::atomic::{AtomicU64, Ordering},
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
 };
 
 use crate::{
@@ -26,6 +26,7 @@ use crate::{
     evaluation::{Eval, MATE_BOUND},
     movegen::Move,
     search::Depth,
+    util::get_unchecked,
 };
 
 /// The bound of a score depending on how it was obtained.
@@ -54,8 +55,11 @@ pub struct TranspositionEntry {
     mv: Move,
     /// The depth at which the score was obtained.
     depth: Depth,
-    /// The bound of the score.
-    bound: Bound,
+    /// The generation of the entry in the upper 6 bits and the bound of the
+    /// score in the lower 2 bits.
+    ///
+    /// This isn't a [`Bound`] so that every `u64` is a valid entry.
+    flags: u8,
 }
 
 /// The information from a successful transposition table lookup.
@@ -71,11 +75,36 @@ pub struct TranspositionHit {
     bound: Bound,
 }
 
+/// The entries that a key can be stored in.
+///
+/// It's aligned to half a cache line, so a lookup only ever touches one line.
+#[repr(align(32))]
+struct Bucket([AtomicU64; TranspositionTable::BUCKET_SIZE]);
+
+/// The buckets in one cache line.
+///
+/// The table is made of these so that it's aligned to a cache line.
+#[repr(align(64))]
+struct CacheLine([Bucket; TranspositionTable::BUCKETS_PER_LINE]);
+
 /// A transposition table: a hash of previous board positions and information
 /// about each position.
-#[allow(clippy::missing_docs_in_private_items)]
 pub struct TranspositionTable {
-    tt: Vec<AtomicU64>,
+    /// The buckets of entries of the table.
+    tt: Vec<CacheLine>,
+    /// Whether or not to count collisions and overwrites.
+    ///
+    /// This is off by default to avoid slowing down the search.
+    track_stats: bool,
+    /// The number of stores that replaced an entry with a different key.
+    collisions: AtomicU64,
+    /// The number of stores that replaced any non-empty entry.
+    overwrites: AtomicU64,
+    /// The generation of the current search.
+    ///
+    /// Entries from older generations are stale, so they're more likely to
+    /// be replaced.
+    generation: AtomicU8,
 }
 
 impl From<u64> for TranspositionEntry {
@@ -101,7 +130,7 @@ impl TranspositionEntry {
             score: normalise(score, height),
             mv,
             depth,
-            bound,
+            flags: bound as u8,
         }
     }
 
@@ -109,6 +138,40 @@ impl TranspositionEntry {
     const fn matches(self, key: Key) -> bool {
         self.key == key as u16
     }
+
+    /// Returns the bound of the score.
+    const fn bound(self) -> Bound {
+        match self.flags & TranspositionTable::BOUND_MASK {
+            0 => Bound::Lower,
+            1 => Bound::Exact,
+            _ => Bound::Upper,
+        }
+    }
+
+    /// Returns the generation of the search that stored the entry.
+    const fn generation(self) -> u8 {
+        self.flags >> TranspositionTable::GENERATION_SHIFT
+    }
+
+    /// Sets the generation of the entry, keeping only its lowest 6 bits.
+    const fn set_generation(&mut self, generation: u8) {
+        self.flags = (self.flags & TranspositionTable::BOUND_MASK)
+            | (generation << TranspositionTable::GENERATION_SHIFT);
+    }
+}
+
+impl Bucket {
+    /// Creates a new, empty [`Bucket`].
+    const fn new() -> Self {
+        Self([const { AtomicU64::new(0) }; TranspositionTable::BUCKET_SIZE])
+    }
+}
+
+impl CacheLine {
+    /// Creates a new, empty [`CacheLine`].
+    const fn new() -> Self {
+        Self([const { Bucket::new() }; TranspositionTable::BUCKETS_PER_LINE])
+    }
 }
 
 impl TranspositionHit {
@@ -144,9 +207,27 @@ impl TranspositionHit {
 }
 
 impl TranspositionTable {
+    /// The bits of [`TranspositionEntry::flags`] that hold the bound.
+    const BOUND_MASK: u8 = 0b11;
+    /// How far the generation is shifted in [`TranspositionEntry::flags`].
+    const GENERATION_SHIFT: u8 = 2;
+    /// How many entries are in each bucket.
+    const BUCKET_SIZE: usize = 4;
+    /// How many buckets are in each cache line.
+    const BUCKETS_PER_LINE: usize = 2;
+    /// How much depth an entry is worth less for each generation it's been
+    /// stale for, when picking an entry to replace.
+    const AGE_PENALTY: i32 = 8;
+
     /// Creates a new, empty, zero-sized [`TranspositionTable`].
     pub const fn new() -> Self {
-        Self { tt: Vec::new() }
+        Self {
+            tt: Vec::new(),
+            track_stats: false,
+            collisions: AtomicU64::new(0),
+            overwrites: AtomicU64::new(0),
+            generation: AtomicU8::new(0),
+        }
     }
 
     /// Creates a new, zeroed [`Transposition table`] with the given size in
@@ -157,67 +238,185 @@ impl TranspositionTable {
         tt
     }
 
-    /// Resizes the the table to the given size in MiB and zeroes it.
+    /// Resizes the table to the given size in MiB, zeroing it if the size
+    /// changes.
+    ///
+    /// If the table is already that size, its entries are kept.
     pub fn resize(&mut self, size_mib: usize) {
-        let entries = size_mib * 1024 * 1024 / size_of::<TranspositionEntry>();
-        *self.tt_mut() = Vec::with_capacity(entries);
-        for _ in 0..entries {
-            self.tt_mut().push(AtomicU64::new(0));
+        let lines = size_mib * 1024 * 1024 / size_of::<CacheLine>();
+        if lines == self.tt().len() {
+            return;
         }
+        *self.tt_mut() = Vec::with_capacity(lines);
+        for _ in 0..lines {
+            self.tt_mut().push(CacheLine::new());
+        }
+    }
+
+    /// Starts a new generation, which makes every existing entry stale.
+    ///
+    /// This should be called at the start of each search. Unlike
+    /// [`clear()`](Self::clear), it takes no time, and the stale entries can
+    /// still be used until they're replaced.
+    pub fn new_generation(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
     }
 
-    /// Zeroes the table.
+    /// Zeroes the table and its statistics.
+    ///
+    /// This is slow for large tables, so it should only be done when the GUI
+    /// asks for it.
     pub fn clear(&mut self) {
-        for entry in self.tt_mut() {
-            *entry.get_mut() = 0;
+        for bucket in self.tt_mut().iter_mut().flat_map(|line| &mut line.0) {
+            for entry in &mut bucket.0 {
+                *entry.get_mut() = 0;
+            }
         }
+        *self.collisions.get_mut() = 0;
+        *self.overwrites.get_mut() = 0;
     }
 
     /// Returns the entry with the given key, or [`None`] if it doesn't exist.
     pub fn load(&self, key: Key, height: Depth) -> Option<TranspositionHit> {
-        // SAFETY: `index()` is guaranteed to be a valid index
-        let atomic_entry = unsafe { self.tt().get_unchecked(self.index(key)) };
-        let entry = TranspositionEntry::from(atomic_entry.load(Ordering::Relaxed));
-        entry.matches(key).then_some(TranspositionHit::new(
-            entry.score,
-            entry.mv,
-            entry.depth,
-            entry.bound,
-            height,
-        ))
+        self.bucket(key)
+            .0
+            .iter()
+            .map(|atomic_entry| atomic_entry.load(Ordering::Relaxed))
+            .filter(|&raw_entry| raw_entry != 0)
+            .map(TranspositionEntry::from)
+            .find(|entry| entry.matches(key))
+            .map(|entry| {
+                TranspositionHit::new(entry.score, entry.mv, entry.depth, entry.bound(), height)
+            })
     }
 
     /// Stores an entry with the given key.
-    pub fn store(&self, key: Key, entry: TranspositionEntry) {
-        // SAFETY: `index()` is guaranteed to be a valid index
-        let atomic_entry = unsafe { self.tt().get_unchecked(self.index(key)) };
-        // this follows the 'always-replace' strategy
+    ///
+    /// An entry of the same position or an empty entry in the bucket of the
+    /// key is replaced first. Otherwise, the least valuable entry is
+    /// replaced: the shallowest, with stale entries counting as shallower.
+    pub fn store(&self, key: Key, mut entry: TranspositionEntry) {
+        let generation = self.generation();
+        entry.set_generation(generation);
+
+        let mut replaced = None;
+        let mut lowest_value = i32::MAX;
+        for atomic_entry in &self.bucket(key).0 {
+            let raw_old_entry = atomic_entry.load(Ordering::Relaxed);
+            let old_entry = TranspositionEntry::from(raw_old_entry);
+            if raw_old_entry == 0 || old_entry.matches(key) {
+                replaced = Some((atomic_entry, raw_old_entry));
+                break;
+            }
+            let value = Self::replacement_value(old_entry, generation);
+            if value < lowest_value {
+                lowest_value = value;
+                replaced = Some((atomic_entry, raw_old_entry));
+            }
+        }
+        let Some((atomic_entry, raw_old_entry)) = replaced else {
+            return;
+        };
+
+        if self.track_stats && raw_old_entry != 0 {
+            self.overwrites.fetch_add(1, Ordering::Relaxed);
+            if !TranspositionEntry::from(raw_old_entry).matches(key) {
+                self.collisions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
         atomic_entry.store(u64::from(entry), Ordering::Relaxed);
     }
 
-    /// Estimates how full the hash is, per mille.
+    /// Sets whether or not collisions and overwrites should be counted.
+    pub const fn set_track_stats(&mut self, track_stats: bool) {
+        self.track_stats = track_stats;
+    }
+
+    /// Returns whether or not collisions and overwrites are being counted.
+    pub const fn is_tracking_stats(&self) -> bool {
+        self.track_stats
+    }
+
+    /// Returns the number of stores that replaced an entry of a different
+    /// position since the table was last cleared.
+    pub fn collisions(&self) -> u64 {
+        self.collisions.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of stores that replaced any existing entry since
+    /// the table was last cleared.
+    pub fn overwrites(&self) -> u64 {
+        self.overwrites.load(Ordering::Relaxed)
+    }
+
+    /// Estimates how full the hash is with entries from the current search,
+    /// per mille.
     pub fn estimate_hashfull(&self) -> usize {
+        let generation = self.generation();
         self.tt()
             .iter()
-            .take(1000)
-            .filter(|entry| entry.load(Ordering::Relaxed) != 0)
+            .take(1000 / (Self::BUCKET_SIZE * Self::BUCKETS_PER_LINE))
+            .flat_map(|line| &line.0)
+            .flat_map(|bucket| &bucket.0)
+            .map(|entry| entry.load(Ordering::Relaxed))
+            .filter(|&entry| {
+                entry != 0 && TranspositionEntry::from(entry).generation() == generation
+            })
             .count()
     }
 
-    /// Converts a key into a valid index.
+    /// Returns the current generation, truncated to the 6 bits that fit in an
+    /// entry.
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed) & (u8::MAX >> Self::GENERATION_SHIFT)
+    }
+
+    /// Calculates how valuable `entry` is to keep: its depth, minus a penalty
+    /// for each generation it's been stale for.
+    fn replacement_value(entry: TranspositionEntry, generation: u8) -> i32 {
+        let age = generation.wrapping_sub(entry.generation()) & (u8::MAX >> Self::GENERATION_SHIFT);
+        i32::from(entry.depth) - Self::AGE_PENALTY * i32::from(age)
+    }
+
+    /// Hints to the CPU that the bucket of `key` will be probed soon, so that
+    /// it can start loading it into the cache.
+    ///
+    /// It does nothing on targets without a prefetch instruction.
+    #[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+    pub fn prefetch(&self, key: Key) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            let bucket: *const Bucket = self.bucket(key);
+            // SAFETY: prefetching has no side effects, and the pointer is
+            // valid anyway
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(bucket.cast::<i8>()) };
+        }
+    }
+
+    /// Returns the bucket that `key` belongs in.
+    fn bucket(&self, key: Key) -> &Bucket {
+        let index = self.index(key);
+        // SAFETY: `index()` is guaranteed to be a valid index
+        let line = unsafe { self.tt().get_unchecked(index / Self::BUCKETS_PER_LINE) };
+        get_unchecked(&line.0, index % Self::BUCKETS_PER_LINE)
+    }
+
+    /// Converts a key into a valid index of a bucket.
     fn index(&self, key: Key) -> usize {
-        // this maps the key from range 0..2.pow(64) to 0..self.tt().len(), with
-        // the same uniform distribution
-        ((u128::from(key) * self.tt().len() as u128) >> 64) as usize
+        // this maps the key from range 0..2.pow(64) to 0..buckets, with the
+        // same uniform distribution
+        let buckets = self.tt().len() * Self::BUCKETS_PER_LINE;
+        ((u128::from(key) * buckets as u128) >> 64) as usize
     }
 
-    /// Returns a reference to the internal vector of entries.
-    const fn tt(&self) -> &Vec<AtomicU64> {
+    /// Returns a reference to the internal vector of cache lines.
+    const fn tt(&self) -> &Vec<CacheLine> {
         &self.tt
     }
 
-    /// Returns a mutable reference to the internal vector of entries.
-    fn tt_mut(&mut self) -> &mut Vec<AtomicU64> {
+    /// Returns a mutable reference to the internal vector of cache lines.
+    fn tt_mut(&mut self) -> &mut Vec<CacheLine> {
         &mut self.tt
     }
 }
@@ -245,3 +444,62 @@ fn denormalise(score: Eval, height: Depth) -> Eval {
         score
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Bound, TranspositionEntry, TranspositionTable};
+    use crate::movegen::Move;
+
+    /// Checks that replacing an entry of a different key in a full bucket
+    /// counts as a collision.
+    #[test]
+    fn collisions_are_counted() {
+        let mut tt = TranspositionTable::with_capacity(1);
+        tt.set_track_stats(true);
+        // any small keys map to bucket 0
+        let store = |key| {
+            tt.store(
+                key,
+                TranspositionEntry::new(key, 0, Move::null(), 1, Bound::Exact, 0),
+            );
+        };
+
+        for key in 1..=TranspositionTable::BUCKET_SIZE as u64 {
+            store(key);
+        }
+        store(1);
+        assert_eq!(tt.collisions(), 0, "Same key counted as a collision");
+        assert_eq!(tt.overwrites(), 1, "Overwrite not counted");
+
+        store(TranspositionTable::BUCKET_SIZE as u64 + 1);
+        assert_eq!(tt.collisions(), 1, "Collision not counted");
+        assert_eq!(tt.overwrites(), 2, "Overwrite not counted");
+    }
+
+    /// Checks that a full bucket replaces its shallowest entry, with stale
+    /// entries counting as shallower.
+    #[test]
+    fn least_valuable_entry_is_replaced() {
+        let tt = TranspositionTable::with_capacity(1);
+        let store = |key, depth| {
+            tt.store(
+                key,
+                TranspositionEntry::new(key, 0, Move::null(), depth, Bound::Exact, 0),
+            );
+        };
+
+        store(1, 5);
+        tt.new_generation();
+        assert_eq!(tt.estimate_hashfull(), 0, "Stale entries were counted");
+        store(2, 1);
+        store(3, 20);
+        store(4, 20);
+        // the stale entry of depth 5 is worth less than the new one of depth 1
+        store(5, 20);
+        assert!(tt.load(1, 0).is_none(), "Stale entry was kept");
+
+        store(6, 10);
+        assert!(tt.load(2, 0).is_none(), "Shallowest entry was kept");
+        assert!(tt.load(3, 0).is_some(), "Deep entry was replaced");
+    }
+}
@@ -174,6 +174,11 @@ impl<T: Copy, const SIZE: usize> Stack<T, SIZE> {
         self.first_empty
     }
 
+    /// Returns how many more items can be pushed onto the stack.
+    pub const fn remaining_capacity(&self) -> usize {
+        SIZE - self.first_empty
+    }
+
     /// Sorts the items in the stack with the comparator function, `cmp`.
     pub fn sort_by<F>(&mut self, mut cmp: F)
     where